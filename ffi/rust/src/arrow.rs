@@ -0,0 +1,340 @@
+//! Export of column batches through the Arrow C Data Interface
+//!
+//! See <https://arrow.apache.org/docs/format/CDataInterface.html>. A batch is
+//! exported as a non-nullable struct array with one child per column, which
+//! is how Arrow libraries import record batches.
+
+use std::ffi::{c_void, CString};
+use std::os::raw::c_char;
+use std::sync::Arc;
+use crate::columnar::{Column, ColumnBatch, ColumnData, ColumnType};
+
+const ARROW_FLAG_NULLABLE: i64 = 2;
+
+#[repr(C)]
+pub struct ArrowSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut ArrowSchema,
+    pub dictionary: *mut ArrowSchema,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    pub private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct ArrowArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut ArrowArray,
+    pub dictionary: *mut ArrowArray,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    pub private_data: *mut c_void,
+}
+
+/// Arrow format string for a column type
+fn format_for(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Float64 => "g",
+        ColumnType::Int64 => "l",
+        ColumnType::Utf8 => "u",
+        ColumnType::Bool => "b",
+        ColumnType::Float32List | ColumnType::Float64List => "+l",
+    }
+}
+
+/// Arrow format string for the elements of a list column
+fn element_format_for(column_type: ColumnType) -> Option<&'static str> {
+    match column_type {
+        ColumnType::Float32List => Some("f"),
+        ColumnType::Float64List => Some("g"),
+        _ => None,
+    }
+}
+
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut ArrowSchema>,
+}
+
+fn new_schema(format: &str, name: &str, flags: i64, children: Vec<*mut ArrowSchema>) -> ArrowSchema {
+    let mut private = Box::new(SchemaPrivate {
+        format: CString::new(format).unwrap_or_default(),
+        name: CString::new(name).unwrap_or_default(),
+        children,
+    });
+
+    ArrowSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: std::ptr::null(),
+        flags,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: std::ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    let Some(schema) = schema.as_mut() else {
+        return;
+    };
+
+    let private = Box::from_raw(schema.private_data as *mut SchemaPrivate);
+    for &child in &private.children {
+        if let Some(release) = (*child).release {
+            release(child);
+        }
+        drop(Box::from_raw(child));
+    }
+
+    schema.release = None;
+}
+
+struct ArrayPrivate {
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+    // Keeps the column buffers alive until every array that borrows them is released
+    _batch: Arc<ColumnBatch>,
+}
+
+fn new_array(
+    length: usize,
+    null_count: usize,
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+    batch: Arc<ColumnBatch>,
+) -> ArrowArray {
+    let mut private = Box::new(ArrayPrivate {
+        buffers,
+        children,
+        _batch: batch,
+    });
+
+    ArrowArray {
+        length: length as i64,
+        null_count: null_count as i64,
+        offset: 0,
+        n_buffers: private.buffers.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.buffers.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: std::ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    let Some(array) = array.as_mut() else {
+        return;
+    };
+
+    let private = Box::from_raw(array.private_data as *mut ArrayPrivate);
+    for &child in &private.children {
+        if let Some(release) = (*child).release {
+            release(child);
+        }
+        drop(Box::from_raw(child));
+    }
+
+    array.release = None;
+}
+
+/// Buffers of a column in the order Arrow expects for its format
+fn column_buffers(column: &Column) -> Vec<*const c_void> {
+    let validity = if column.null_count == 0 {
+        std::ptr::null()
+    } else {
+        column.validity.as_ptr() as *const c_void
+    };
+
+    match &column.data {
+        ColumnData::Utf8 { offsets, data } => vec![
+            validity,
+            offsets.as_ptr() as *const c_void,
+            data.as_ptr() as *const c_void,
+        ],
+        // List elements live in a child array
+        ColumnData::Float32List { offsets, .. } | ColumnData::Float64List { offsets, .. } => {
+            vec![validity, offsets.as_ptr() as *const c_void]
+        }
+        _ => vec![validity, column.values_ptr() as *const c_void],
+    }
+}
+
+/// Schema of a column, with an `item` child for the elements of a list
+fn column_schema(column: &Column) -> ArrowSchema {
+    let column_type = column.column_type();
+    let children = element_format_for(column_type)
+        .map(|format| new_schema(format, "item", ARROW_FLAG_NULLABLE, Vec::new()))
+        .map(|item| Box::into_raw(Box::new(item)))
+        .into_iter()
+        .collect();
+
+    let name = column.name.to_str().unwrap_or_default();
+    new_schema(format_for(column_type), name, ARROW_FLAG_NULLABLE, children)
+}
+
+/// Array of a column, with a child array holding the elements of a list
+fn column_array(column: &Column, batch: &Arc<ColumnBatch>) -> ArrowArray {
+    let children = match (&column.data, column.offsets()) {
+        (ColumnData::Float32List { .. } | ColumnData::Float64List { .. }, Some(offsets)) => {
+            let elements = offsets.last().copied().unwrap_or(0) as usize;
+            let values = vec![std::ptr::null(), column.values_ptr() as *const c_void];
+            let item = new_array(elements, 0, values, Vec::new(), Arc::clone(batch));
+            vec![Box::into_raw(Box::new(item))]
+        }
+        _ => Vec::new(),
+    };
+
+    new_array(
+        column.len(),
+        column.null_count,
+        column_buffers(column),
+        children,
+        Arc::clone(batch),
+    )
+}
+
+/// Export a batch as a struct-typed Arrow schema and array
+///
+/// The caller takes ownership of both structs and must call their
+/// `release` callbacks.
+pub fn export_batch(batch: ColumnBatch) -> (ArrowSchema, ArrowArray) {
+    let batch = Arc::new(batch);
+
+    let child_schemas = batch
+        .columns
+        .iter()
+        .map(|column| Box::into_raw(Box::new(column_schema(column))))
+        .collect();
+
+    let child_arrays = batch
+        .columns
+        .iter()
+        .map(|column| Box::into_raw(Box::new(column_array(column, &batch))))
+        .collect();
+
+    let schema = new_schema("+s", "", 0, child_schemas);
+    let array = new_array(
+        batch.len,
+        0,
+        vec![std::ptr::null()],
+        child_arrays,
+        Arc::clone(&batch),
+    );
+
+    (schema, array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn sample_batch() -> ColumnBatch {
+        let mut ids = Column::new("source_id", ColumnType::Int64).unwrap();
+        let mut flux = Column::new("phot_g_mean_flux", ColumnType::Float64).unwrap();
+        let mut names = Column::new("designation", ColumnType::Utf8).unwrap();
+        for (id, value, name) in [("1", "2.5", "Gaia DR3 1"), ("2", "null", "Gaia DR3 2")] {
+            ids.push_str(id);
+            flux.push_str(value);
+            names.push_str(name);
+        }
+
+        ColumnBatch {
+            len: 2,
+            columns: vec![ids, flux, names],
+        }
+    }
+
+    #[test]
+    fn test_export_batch() {
+        let (mut schema, mut array) = export_batch(sample_batch());
+
+        unsafe {
+            assert_eq!(CStr::from_ptr(schema.format).to_str().unwrap(), "+s");
+            assert_eq!(schema.n_children, 3);
+            let formats: Vec<&str> = (0..3)
+                .map(|i| CStr::from_ptr((**schema.children.add(i)).format).to_str().unwrap())
+                .collect();
+            assert_eq!(formats, vec!["l", "g", "u"]);
+
+            assert_eq!(array.length, 2);
+            let flux = &**array.children.add(1);
+            assert_eq!(flux.null_count, 1);
+            assert_eq!(flux.n_buffers, 2);
+            let values = *flux.buffers.add(1) as *const f64;
+            assert_eq!(*values, 2.5);
+
+            let names = &**array.children.add(2);
+            assert_eq!(names.n_buffers, 3);
+            assert!((*names.buffers).is_null());
+
+            (schema.release.unwrap())(&mut schema);
+            (array.release.unwrap())(&mut array);
+        }
+
+        assert!(schema.release.is_none());
+        assert!(array.release.is_none());
+    }
+
+    #[test]
+    fn test_export_list_column() {
+        let mut flux = Column::new("flux", ColumnType::Float32List).unwrap();
+        flux.push_str("[1.5,2.5]");
+        flux.push_str("null");
+        flux.push_str("[3.5]");
+        let batch = ColumnBatch {
+            len: 3,
+            columns: vec![flux],
+        };
+        let (mut schema, mut array) = export_batch(batch);
+
+        unsafe {
+            let list_schema = &**schema.children;
+            assert_eq!(CStr::from_ptr(list_schema.format).to_str().unwrap(), "+l");
+            assert_eq!(CStr::from_ptr((**list_schema.children).format).to_str().unwrap(), "f");
+
+            let list = &**array.children;
+            assert_eq!((list.length, list.null_count, list.n_children), (3, 1, 1));
+            let offsets = *list.buffers.add(1) as *const i32;
+            assert_eq!(std::slice::from_raw_parts(offsets, 4), &[0, 2, 2, 3]);
+
+            let item = &**list.children;
+            assert_eq!(item.length, 3);
+            assert_eq!(*(*item.buffers.add(1) as *const f32).add(2), 3.5);
+
+            (schema.release.unwrap())(&mut schema);
+            (array.release.unwrap())(&mut array);
+        }
+    }
+
+    #[test]
+    fn test_child_array_outlives_parent() {
+        let (mut schema, mut array) = export_batch(sample_batch());
+
+        unsafe {
+            // Consumers may move a child out and release the parent first
+            let child_ptr = *array.children;
+            let mut child = std::ptr::read(child_ptr);
+            (*child_ptr).release = None;
+
+            (array.release.unwrap())(&mut array);
+            (schema.release.unwrap())(&mut schema);
+
+            assert_eq!(*(*child.buffers.add(1) as *const i64), 1);
+            (child.release.unwrap())(&mut child);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use crate::columnar::ColumnBatch;
+use crate::error::{ErrorCode, ParseError};
+use crate::options::ReaderOptions;
+use crate::reader::GaiaCsvReader;
+
+/// Outcome of parsing each of several files, in the order they were given
+///
+/// Each parsed file is a single batch holding all of its rows.
+#[derive(Debug, Default)]
+pub struct FileBatch {
+    pub files: Vec<Result<ColumnBatch, ParseError>>,
+}
+
+impl FileBatch {
+    /// A batch in which every file failed with the same error, e.g. invalid
+    /// reader options
+    pub fn failed(files: usize, err: ParseError) -> Self {
+        Self {
+            files: (0..files).map(|_| Err(err.clone())).collect(),
+        }
+    }
+}
+
+/// Parse whole files on a pool of `threads` threads (one per core if 0)
+///
+/// Files are handed out one at a time, so a slow file does not hold up the
+/// rest. A failed file does not stop the others, and neither does a panic
+/// while parsing one, which is reported as that file's error.
+pub fn parse_files(paths: &[String], options: &ReaderOptions, threads: usize) -> FileBatch {
+    parse_each(paths, threads, |path| {
+        GaiaCsvReader::open(path, options).and_then(|mut reader| reader.next_columns(0))
+    })
+}
+
+fn parse_each(
+    paths: &[String],
+    threads: usize,
+    parse: impl Fn(&str) -> Result<ColumnBatch, ParseError> + Sync,
+) -> FileBatch {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(paths.len())
+    .max(1);
+
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Result<ColumnBatch, ParseError>>>> =
+        paths.iter().map(|_| Mutex::new(None)).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    return;
+                };
+
+                // A panic must not unwind out of the scope and across the FFI
+                // boundary, where it would abort the caller
+                let result = catch_unwind(AssertUnwindSafe(|| parse(path)))
+                    .unwrap_or_else(|panic| Err(panicked(path, panic)));
+                *results[index].lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+            });
+        }
+    });
+
+    FileBatch {
+        files: results
+            .into_iter()
+            .map(|result| {
+                result
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .expect("every file is parsed")
+            })
+            .collect(),
+    }
+}
+
+fn panicked(path: &str, panic: Box<dyn Any + Send>) -> ParseError {
+    let reason = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    ParseError::new(ErrorCode::Io, format!("{}: parsing panicked: {}", path, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_gz_fixture, SAMPLE_CSV};
+
+    #[test]
+    fn test_parse_files() {
+        let sample = write_gz_fixture("batch_sample.csv.gz", SAMPLE_CSV);
+        let short = write_gz_fixture("batch_short.csv.gz", "source_id,ra\n1,2.5\n");
+        let paths = vec![
+            sample.to_str().unwrap().to_string(),
+            "/nonexistent/batch.csv.gz".to_string(),
+            short.to_str().unwrap().to_string(),
+        ];
+        let options = ReaderOptions::with_columns(&["source_id".to_string(), "ra".to_string()]);
+
+        for threads in [0, 1, 2, 8] {
+            let batch = parse_files(&paths, &options, threads);
+            assert_eq!(batch.files.len(), 3);
+            assert_eq!(batch.files[0].as_ref().unwrap().len, 3);
+            assert_eq!(batch.files[1].as_ref().unwrap_err().code, ErrorCode::Io);
+            assert_eq!(batch.files[2].as_ref().unwrap().len, 1);
+        }
+
+        assert!(parse_files(&[], &options, 0).files.is_empty());
+    }
+
+    #[test]
+    fn test_panic_is_file_error() {
+        let paths = vec!["a.csv.gz".to_string(), "b.csv.gz".to_string()];
+        let batch = parse_each(&paths, 2, |path| {
+            if path == "a.csv.gz" {
+                panic!("bad row");
+            }
+            Ok(ColumnBatch::default())
+        });
+
+        let err = batch.files[0].as_ref().unwrap_err();
+        assert_eq!(err.code, ErrorCode::Io);
+        assert_eq!(err.message, "a.csv.gz: parsing panicked: bad row");
+        assert!(batch.files[1].is_ok());
+    }
+}
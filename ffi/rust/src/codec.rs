@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use flate2::bufread::MultiGzDecoder;
+use crate::error::{ErrorCode, ParseError};
+use crate::pgzip::open_parallel;
+
+/// Compression of an input file, detected from its magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Uncompressed CSV
+    Plain,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Codec {
+    /// Identify the codec from the first bytes of a file
+    ///
+    /// Anything without a known magic number is treated as plain text.
+    pub fn detect(magic: &[u8]) -> Self {
+        match magic {
+            [0x1f, 0x8b, ..] => Codec::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Codec::Zstd,
+            [b'B', b'Z', b'h', ..] => Codec::Bzip2,
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Codec::Xz,
+            _ => Codec::Plain,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Plain => "plain",
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+            Codec::Bzip2 => "bzip2",
+            Codec::Xz => "xz",
+        }
+    }
+}
+
+/// Open a file for reading, decompressing it with whichever codec its magic
+/// bytes indicate
+///
+/// Codecs other than gzip are behind cargo features of the same name; a
+/// file using one that was left out of the build fails with
+/// `UnsupportedCompression`. Gzip files may have several members, and each
+/// member's trailer is checked as it is read. With `gzip_threads` above 1,
+/// a large gzip file is memory-mapped and inflated in parallel.
+pub(crate) fn open_decompressed(file_path: &str, gzip_threads: usize) -> Result<Box<dyn BufRead>, ParseError> {
+    let io_error = |e| ParseError::from_io(e, file_path);
+
+    let file = File::open(file_path).map_err(io_error)?;
+    let mut file = BufReader::new(file);
+    let magic = file.fill_buf().map_err(io_error)?;
+    if magic.is_empty() {
+        // No Gaia file is empty; this is a download that never got going
+        return Err(ParseError::new(ErrorCode::Truncated, format!("{}: file is empty", file_path)));
+    }
+
+    let codec = Codec::detect(magic);
+
+    let reader: Box<dyn BufRead> = match codec {
+        Codec::Plain => Box::new(file),
+        Codec::Gzip if gzip_threads > 1 => open_parallel(file, gzip_threads).map_err(io_error)?,
+        Codec::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(file).map_err(io_error)?,
+        )),
+        #[cfg(feature = "bzip2")]
+        Codec::Bzip2 => Box::new(BufReader::new(bzip2::bufread::MultiBzDecoder::new(file))),
+        #[cfg(feature = "xz")]
+        Codec::Xz => Box::new(BufReader::new(xz2::bufread::XzDecoder::new_multi_decoder(file))),
+        #[allow(unreachable_patterns)]
+        codec => {
+            return Err(ParseError::new(
+                ErrorCode::UnsupportedCompression,
+                format!(
+                    "{}: {} compression is not enabled in this build (cargo feature \"{}\")",
+                    file_path,
+                    codec.name(),
+                    codec.name()
+                ),
+            ))
+        }
+    };
+
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use crate::test_support::{gzip_bytes, write_fixture, write_gz_fixture, SAMPLE_CSV};
+
+    fn read_all(file_path: &std::path::Path) -> String {
+        let mut contents = String::new();
+        open_decompressed(file_path.to_str().unwrap(), 0)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Codec::detect(&[0x1f, 0x8b, 0x08]), Codec::Gzip);
+        assert_eq!(Codec::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x24]), Codec::Zstd);
+        assert_eq!(Codec::detect(b"BZh91AY&SY"), Codec::Bzip2);
+        assert_eq!(Codec::detect(b"\xfd7zXZ\x00\x00"), Codec::Xz);
+        assert_eq!(Codec::detect(b"# %ECSV 1.0"), Codec::Plain);
+        assert_eq!(Codec::detect(b""), Codec::Plain);
+    }
+
+    #[test]
+    fn test_plain_and_gzip() {
+        assert_eq!(read_all(&write_fixture("codec_plain.csv", SAMPLE_CSV.as_bytes())), SAMPLE_CSV);
+        assert_eq!(read_all(&write_gz_fixture("codec_gzip.csv.gz", SAMPLE_CSV)), SAMPLE_CSV);
+
+        // Too small to split, so read sequentially from memory
+        let path = write_gz_fixture("codec_parallel.csv.gz", SAMPLE_CSV);
+        let mut contents = String::new();
+        open_decompressed(path.to_str().unwrap(), 4).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, SAMPLE_CSV);
+    }
+
+    #[test]
+    fn test_gzip_members_and_truncation() {
+        let mut members = gzip_bytes("a,b\n");
+        members.extend(gzip_bytes("1,2\n"));
+        assert_eq!(read_all(&write_fixture("codec_members.csv.gz", &members)), "a,b\n1,2\n");
+
+        let whole = gzip_bytes(SAMPLE_CSV);
+        let path = write_fixture("codec_truncated.csv.gz", &whole[..whole.len() - 4]);
+        let mut contents = String::new();
+        let err = open_decompressed(path.to_str().unwrap(), 0).unwrap().read_to_string(&mut contents).unwrap_err();
+        assert_eq!(ParseError::from_io(err, "").code, ErrorCode::Truncated);
+
+        let path = write_fixture("codec_empty.csv.gz", b"");
+        assert_eq!(open_decompressed(path.to_str().unwrap(), 0).err().unwrap().code, ErrorCode::Truncated);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let compressed = zstd::encode_all(SAMPLE_CSV.as_bytes(), 3).unwrap();
+        assert_eq!(read_all(&write_fixture("codec.csv.zst", &compressed)), SAMPLE_CSV);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_disabled_codec() {
+        let path = write_fixture("codec_disabled.csv.zst", &[0x28, 0xb5, 0x2f, 0xfd, 0x00]);
+        let err = open_decompressed(path.to_str().unwrap(), 0).err().unwrap();
+        assert_eq!(err.code, ErrorCode::UnsupportedCompression);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn test_bzip2() {
+        let mut compressed = Vec::new();
+        bzip2::read::BzEncoder::new(SAMPLE_CSV.as_bytes(), bzip2::Compression::default())
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(read_all(&write_fixture("codec.csv.bz2", &compressed)), SAMPLE_CSV);
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn test_xz() {
+        let mut compressed = Vec::new();
+        xz2::read::XzEncoder::new(SAMPLE_CSV.as_bytes(), 6)
+            .read_to_end(&mut compressed)
+            .unwrap();
+        assert_eq!(read_all(&write_fixture("codec.csv.xz", &compressed)), SAMPLE_CSV);
+    }
+}
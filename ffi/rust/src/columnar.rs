@@ -0,0 +1,584 @@
+use std::ffi::CString;
+use crate::error::{ErrorCode, ParseError};
+use crate::options::ReaderOptions;
+
+/// Physical type of a column buffer
+///
+/// The numeric values are part of the C ABI and must not be reordered.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Contiguous `f64` values
+    Float64 = 0,
+    /// Contiguous `i64` values
+    Int64 = 1,
+    /// `i32` offsets (`len + 1` entries) into a UTF-8 byte buffer
+    Utf8 = 2,
+    /// LSB-first bitmap of `ceil(len / 8)` bytes
+    Bool = 3,
+    /// `i32` offsets (`len + 1` entries) into contiguous `f32` elements
+    Float32List = 4,
+    /// `i32` offsets (`len + 1` entries) into contiguous `f64` elements
+    Float64List = 5,
+}
+
+impl ColumnType {
+    /// Lower-case name used in JSON descriptions of a file's columns
+    pub fn name(self) -> &'static str {
+        match self {
+            ColumnType::Float64 => "float64",
+            ColumnType::Int64 => "int64",
+            ColumnType::Utf8 => "utf8",
+            ColumnType::Bool => "bool",
+            ColumnType::Float32List => "float32[]",
+            ColumnType::Float64List => "float64[]",
+        }
+    }
+}
+
+/// Whether a raw field is a null: empty, `null`, or `\N` as written by IRSA
+pub fn is_null_field(value: &str) -> bool {
+    is_null_bytes(value.as_bytes())
+}
+
+/// `is_null_field` for a field that has not been checked for UTF-8
+pub fn is_null_bytes(value: &[u8]) -> bool {
+    value.is_empty() || value.eq_ignore_ascii_case(b"null") || value == b"\\N"
+}
+
+/// Parse a decimal field as a float, without a UTF-8 check
+///
+/// Accepts the same syntax as `str::parse::<f64>`, including `NaN` and
+/// `inf`, but is several times faster on the long mantissas Gaia writes.
+pub fn parse_float<T: fast_float2::FastFloat>(value: &[u8]) -> Option<T> {
+    fast_float2::parse(value).ok()
+}
+
+/// Parse a decimal integer field, without a UTF-8 check
+pub fn parse_i64(value: &[u8]) -> Option<i64> {
+    let (negative, digits) = match value {
+        [b'-', rest @ ..] => (true, rest),
+        [b'+', rest @ ..] => (false, rest),
+        _ => (false, value),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    // Accumulate towards the sign so i64::MIN does not overflow
+    let mut num: i64 = 0;
+    for &byte in digits {
+        let digit = (byte as char).to_digit(10)? as i64;
+        num = num.checked_mul(10)?;
+        num = if negative { num.checked_sub(digit)? } else { num.checked_add(digit)? };
+    }
+    Some(num)
+}
+
+/// Elements of an array field such as `[1.5,null,2e3]`, or None if the
+/// field is not an array
+pub fn array_elements(value: &str) -> Option<impl Iterator<Item = &str>> {
+    let inner = value.trim().strip_prefix('[')?.strip_suffix(']')?.trim();
+    Some(inner.split(',').map(str::trim).filter(move |_| !inner.is_empty()))
+}
+
+/// Whether a buffer of `used` entries can grow by `added` and still be
+/// addressed by `i32` offsets
+pub(crate) fn fits_offsets(used: usize, added: usize) -> bool {
+    used.checked_add(added).is_some_and(|len| len <= i32::MAX as usize)
+}
+
+/// The error for a batch whose `column` outgrew its `i32` offsets
+pub(crate) fn offset_overflow(column: &str) -> ParseError {
+    ParseError::new(
+        ErrorCode::OutputEncoding,
+        format!("{} holds more than 2 GiB in one batch; read fewer rows at a time", column),
+    )
+}
+
+/// An offset that `fits_offsets` has already checked
+fn offset(len: usize) -> i32 {
+    i32::try_from(len).expect("offsets are checked before appending")
+}
+
+/// Values of a single column
+#[derive(Debug)]
+pub enum ColumnData {
+    Float64(Vec<f64>),
+    Int64(Vec<i64>),
+    Utf8 { offsets: Vec<i32>, data: Vec<u8> },
+    Bool(Vec<u8>),
+    Float32List { offsets: Vec<i32>, values: Vec<f32> },
+    Float64List { offsets: Vec<i32>, values: Vec<f64> },
+}
+
+/// A typed column with an LSB-first validity bitmap (bit set = not null)
+#[derive(Debug)]
+pub struct Column {
+    pub name: CString,
+    pub data: ColumnData,
+    pub validity: Vec<u8>,
+    pub null_count: usize,
+    len: usize,
+}
+
+impl Column {
+    /// An empty column, or an `OutputEncoding` error if `name` contains a
+    /// NUL byte and so cannot be handed to C
+    pub fn new(name: &str, column_type: ColumnType) -> Result<Self, ParseError> {
+        let name = CString::new(name).map_err(|_| {
+            ParseError::new(
+                ErrorCode::OutputEncoding,
+                format!("column name {:?} contains a NUL byte", name),
+            )
+        })?;
+        Ok(Self::with_name(name, column_type))
+    }
+
+    fn with_name(name: CString, column_type: ColumnType) -> Self {
+        let data = match column_type {
+            ColumnType::Float64 => ColumnData::Float64(Vec::new()),
+            ColumnType::Int64 => ColumnData::Int64(Vec::new()),
+            ColumnType::Utf8 => ColumnData::Utf8 {
+                offsets: vec![0],
+                data: Vec::new(),
+            },
+            ColumnType::Bool => ColumnData::Bool(Vec::new()),
+            ColumnType::Float32List => ColumnData::Float32List {
+                offsets: vec![0],
+                values: Vec::new(),
+            },
+            ColumnType::Float64List => ColumnData::Float64List {
+                offsets: vec![0],
+                values: Vec::new(),
+            },
+        };
+
+        Self {
+            name,
+            data,
+            validity: Vec::new(),
+            null_count: 0,
+            len: 0,
+        }
+    }
+
+    pub fn column_type(&self) -> ColumnType {
+        match self.data {
+            ColumnData::Float64(_) => ColumnType::Float64,
+            ColumnData::Int64(_) => ColumnType::Int64,
+            ColumnData::Utf8 { .. } => ColumnType::Utf8,
+            ColumnData::Bool(_) => ColumnType::Bool,
+            ColumnData::Float32List { .. } => ColumnType::Float32List,
+            ColumnData::Float64List { .. } => ColumnType::Float64List,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pointer to the value buffer (`f64`, `i64`, UTF-8 bytes, bitmap or
+    /// list elements)
+    pub fn values_ptr(&self) -> *const u8 {
+        match &self.data {
+            ColumnData::Float64(values) => values.as_ptr() as *const u8,
+            ColumnData::Int64(values) => values.as_ptr() as *const u8,
+            ColumnData::Utf8 { data, .. } => data.as_ptr(),
+            ColumnData::Bool(bits) => bits.as_ptr(),
+            ColumnData::Float32List { values, .. } => values.as_ptr() as *const u8,
+            ColumnData::Float64List { values, .. } => values.as_ptr() as *const u8,
+        }
+    }
+
+    /// Size of the value buffer in bytes
+    pub fn values_byte_len(&self) -> usize {
+        match &self.data {
+            ColumnData::Float64(values) => std::mem::size_of_val(values.as_slice()),
+            ColumnData::Int64(values) => std::mem::size_of_val(values.as_slice()),
+            ColumnData::Utf8 { data, .. } => data.len(),
+            ColumnData::Bool(bits) => bits.len(),
+            ColumnData::Float32List { values, .. } => std::mem::size_of_val(values.as_slice()),
+            ColumnData::Float64List { values, .. } => std::mem::size_of_val(values.as_slice()),
+        }
+    }
+
+    /// The `i32` offsets of a UTF-8 or list column
+    pub fn offsets(&self) -> Option<&[i32]> {
+        match &self.data {
+            ColumnData::Utf8 { offsets, .. }
+            | ColumnData::Float32List { offsets, .. }
+            | ColumnData::Float64List { offsets, .. } => Some(offsets),
+            _ => None,
+        }
+    }
+
+    /// Pointer to the `i32` offsets of a UTF-8 or list column (null otherwise)
+    pub fn offsets_ptr(&self) -> *const i32 {
+        self.offsets().map_or(std::ptr::null(), <[i32]>::as_ptr)
+    }
+
+    /// Whether the value at `index` is not null
+    pub fn is_valid(&self, index: usize) -> bool {
+        self.validity[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Bytes of the value at `index` of a UTF-8 column, or None if it is
+    /// null or the column is not UTF-8
+    pub fn utf8_value(&self, index: usize) -> Option<&[u8]> {
+        match &self.data {
+            ColumnData::Utf8 { offsets, data } if self.is_valid(index) => {
+                Some(&data[offsets[index] as usize..offsets[index + 1] as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// Convert and append a raw CSV field
+    ///
+    /// Null fields (see `is_null_field`) and values that do not parse as the
+    /// column type are stored as nulls. Null or unparseable elements of an
+    /// array are stored as NaN.
+    pub fn push_str(&mut self, value: &str) {
+        self.append(value.as_bytes());
+    }
+
+    /// Convert and append a raw CSV field that has not been checked for UTF-8
+    ///
+    /// Only fields of a UTF-8 column are checked. If one is invalid, or would
+    /// take the column past what `i32` offsets can address, nothing is
+    /// appended and the error is returned; in any other column invalid bytes
+    /// just fail to parse and are stored as a null.
+    pub fn push_bytes(&mut self, value: &[u8]) -> Result<(), ParseError> {
+        self.check_field(value)?;
+        self.append(value);
+        Ok(())
+    }
+
+    /// Check a field before it is appended: text must be valid UTF-8, and
+    /// text and arrays must leave the column's offsets within `i32`
+    fn check_field(&self, value: &[u8]) -> Result<(), ParseError> {
+        let used = match &self.data {
+            ColumnData::Utf8 { data, .. } => {
+                std::str::from_utf8(value).map_err(|e| {
+                    ParseError::new(
+                        ErrorCode::CsvSyntax,
+                        format!("{} is not valid UTF-8: {}", self.name.to_string_lossy(), e),
+                    )
+                })?;
+                data.len()
+            }
+            ColumnData::Float32List { values, .. } => values.len(),
+            ColumnData::Float64List { values, .. } => values.len(),
+            _ => return Ok(()),
+        };
+
+        // A field adds at most one byte or array element per byte
+        if fits_offsets(used, value.len()) {
+            Ok(())
+        } else {
+            Err(offset_overflow(&self.name.to_string_lossy()))
+        }
+    }
+
+    fn append(&mut self, value: &[u8]) {
+        let is_null = is_null_bytes(value);
+
+        let valid = match &mut self.data {
+            ColumnData::Float64(values) => {
+                let parsed = if is_null { None } else { parse_f64(value) };
+                values.push(parsed.unwrap_or(f64::NAN));
+                parsed.is_some()
+            }
+            ColumnData::Int64(values) => {
+                let parsed = if is_null { None } else { parse_i64(value) };
+                values.push(parsed.unwrap_or(0));
+                parsed.is_some()
+            }
+            ColumnData::Utf8 { offsets, data } => {
+                if !is_null {
+                    data.extend_from_slice(value);
+                }
+                offsets.push(offset(data.len()));
+                !is_null
+            }
+            ColumnData::Bool(bits) => {
+                let parsed = if is_null { None } else { parse_bool(value) };
+                set_bit(bits, self.len, parsed.unwrap_or(false));
+                parsed.is_some()
+            }
+            ColumnData::Float32List { offsets, values } => {
+                let valid = !is_null && push_elements(values, value, f32::NAN);
+                offsets.push(offset(values.len()));
+                valid
+            }
+            ColumnData::Float64List { offsets, values } => {
+                let valid = !is_null && push_elements(values, value, f64::NAN);
+                offsets.push(offset(values.len()));
+                valid
+            }
+        };
+
+        set_bit(&mut self.validity, self.len, valid);
+        if !valid {
+            self.null_count += 1;
+        }
+        self.len += 1;
+    }
+}
+
+/// Set bit `index` of a growing LSB-first bitmap, appending bytes as needed
+fn set_bit(bitmap: &mut Vec<u8>, index: usize, value: bool) {
+    if index.is_multiple_of(8) {
+        bitmap.push(0);
+    }
+    if value {
+        bitmap[index / 8] |= 1 << (index % 8);
+    }
+}
+
+/// Append the elements of an array field, returning false if it is not an array
+fn push_elements<T: fast_float2::FastFloat>(values: &mut Vec<T>, value: &[u8], nan: T) -> bool {
+    match std::str::from_utf8(value).ok().and_then(array_elements) {
+        Some(elements) => {
+            values.extend(elements.map(|element| parse_float(element.as_bytes()).unwrap_or(nan)));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Parse a boolean field as written by the Gaia archive (`True`/`False`)
+fn parse_bool(value: &[u8]) -> Option<bool> {
+    if value.eq_ignore_ascii_case(b"true") || value == b"1" {
+        Some(true)
+    } else if value.eq_ignore_ascii_case(b"false") || value == b"0" {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Parse a float field, treating booleans as 1/0
+fn parse_f64(value: &[u8]) -> Option<f64> {
+    match parse_float(value) {
+        Some(num) => Some(num),
+        None if value.eq_ignore_ascii_case(b"true") => Some(1.0),
+        None if value.eq_ignore_ascii_case(b"false") => Some(0.0),
+        None => None,
+    }
+}
+
+/// A chunk of rows stored column by column
+///
+/// Buffers stay owned by Rust; callers read them through the accessor
+/// functions and release the batch with `free_column_batch`.
+#[derive(Debug, Default)]
+pub struct ColumnBatch {
+    pub len: usize,
+    pub columns: Vec<Column>,
+}
+
+impl ColumnBatch {
+    pub fn column(&self, index: usize) -> Option<&Column> {
+        self.columns.get(index)
+    }
+}
+
+/// Accumulates rows into typed columns for the selected CSV fields
+pub struct BatchBuilder {
+    indices: Vec<usize>,
+    columns: Vec<Column>,
+    len: usize,
+}
+
+impl BatchBuilder {
+    /// Create a builder for the fields at `indices` of each record
+    ///
+    /// Fails if a selected header cannot be a C string column name.
+    pub fn new<'a>(
+        headers: impl Fn(usize) -> &'a str,
+        indices: &[usize],
+        options: &ReaderOptions,
+    ) -> Result<Self, ParseError> {
+        let columns = indices
+            .iter()
+            .map(|&idx| {
+                let header = headers(idx);
+                Column::new(header, options.column_type(header))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            indices: indices.to_vec(),
+            columns,
+            len: 0,
+        })
+    }
+
+    /// Append one record, looking its raw fields up by index
+    ///
+    /// Fails, describing the field, if a text column's field is not valid
+    /// UTF-8 or a column would outgrow its `i32` offsets, in which case none
+    /// of the row is appended.
+    pub fn push_row<'a>(&mut self, field: impl Fn(usize) -> Option<&'a [u8]>) -> Result<(), ParseError> {
+        for (column, &idx) in self.columns.iter().zip(&self.indices) {
+            column.check_field(field(idx).unwrap_or(b""))?;
+        }
+
+        for (column, &idx) in self.columns.iter_mut().zip(&self.indices) {
+            column.append(field(idx).unwrap_or(b""));
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Take the rows built so far, leaving an empty builder for the same columns
+    pub fn take(&mut self) -> ColumnBatch {
+        let columns = self
+            .columns
+            .iter_mut()
+            .map(|column| {
+                let empty = Column::with_name(column.name.clone(), column.column_type());
+                std::mem::replace(column, empty)
+            })
+            .collect();
+
+        ColumnBatch {
+            len: std::mem::take(&mut self.len),
+            columns,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_str_tracks_validity() {
+        let mut column = Column::new("parallax", ColumnType::Float64).unwrap();
+        for value in ["1.5", "", "null", "true", "abc", "-2", "\\N"] {
+            column.push_str(value);
+        }
+
+        assert_eq!(column.len(), 7);
+        assert_eq!(column.null_count, 4);
+        assert_eq!(column.validity, vec![0b0010_1001]);
+        match &column.data {
+            ColumnData::Float64(values) => {
+                assert_eq!(values[0], 1.5);
+                assert_eq!(values[3], 1.0);
+                assert_eq!(values[5], -2.0);
+            }
+            other => panic!("unexpected column data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bool_column() {
+        let mut column = Column::new("has_rvs", ColumnType::Bool).unwrap();
+        for value in ["True", "False", "null", "True"] {
+            column.push_str(value);
+        }
+
+        assert_eq!(column.null_count, 1);
+        match &column.data {
+            ColumnData::Bool(bits) => assert_eq!(bits, &vec![0b1001]),
+            other => panic!("unexpected column data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_int64_and_utf8_columns() {
+        let mut ids = Column::new("source_id", ColumnType::Int64).unwrap();
+        ids.push_str("6917529027641081856");
+        ids.push_str("");
+        assert!(ids.is_valid(0) && !ids.is_valid(1));
+        match &ids.data {
+            ColumnData::Int64(values) => assert_eq!(values[0], 6917529027641081856),
+            other => panic!("unexpected column data {:?}", other),
+        }
+
+        let mut names = Column::new("designation", ColumnType::Utf8).unwrap();
+        names.push_str("Gaia DR3 1");
+        names.push_str("null");
+        names.push_str("Gaia DR3 22");
+        match &names.data {
+            ColumnData::Utf8 { offsets, data } => {
+                assert_eq!(offsets, &vec![0, 10, 10, 21]);
+                assert_eq!(data.len(), 21);
+            }
+            other => panic!("unexpected column data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_list_columns() {
+        let mut coefficients = Column::new("bp_coefficients", ColumnType::Float32List).unwrap();
+        for value in ["[1.5,-2e3,null]", "", "[ ]", "[4.25, NaN]", "not an array"] {
+            coefficients.push_str(value);
+        }
+
+        assert_eq!(coefficients.null_count, 2);
+        assert_eq!(coefficients.validity, vec![0b0_1101]);
+        match &coefficients.data {
+            ColumnData::Float32List { offsets, values } => {
+                assert_eq!(offsets, &vec![0, 3, 3, 3, 5, 5]);
+                assert_eq!(&values[..2], &[1.5, -2000.0]);
+                assert!(values[2].is_nan() && values[4].is_nan());
+                assert_eq!(values[3], 4.25);
+            }
+            other => panic!("unexpected column data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_push_bytes() {
+        assert_eq!(parse_i64(b"-9223372036854775808"), Some(i64::MIN));
+        assert_eq!(parse_i64(b"+42"), Some(42));
+        for invalid in [&b""[..], b"-", b"9223372036854775808", b"1.0", b"1e3", b" 1"] {
+            assert_eq!(parse_i64(invalid), None);
+        }
+        assert_eq!(parse_float::<f64>(b"44.99615537864534"), Some(44.99615537864534));
+        assert!(parse_float::<f64>(b"NaN").unwrap().is_nan());
+        assert_eq!(parse_float::<f64>(b"1e"), None);
+
+        let mut names = Column::new("designation", ColumnType::Utf8).unwrap();
+        names.push_bytes(b"Gaia DR3 1").unwrap();
+        assert_eq!(names.push_bytes(b"\xff").unwrap_err().code, ErrorCode::CsvSyntax);
+        assert_eq!(names.len(), 1);
+
+        // Invalid bytes in a numeric column are just a value that does not parse
+        let mut flux = Column::new("phot_g_mean_flux", ColumnType::Float64).unwrap();
+        flux.push_bytes(b"\xff").unwrap();
+        assert_eq!(flux.null_count, 1);
+    }
+
+    #[test]
+    fn test_fits_offsets() {
+        assert!(fits_offsets(0, 10));
+        assert!(fits_offsets(i32::MAX as usize - 10, 10));
+        assert!(!fits_offsets(i32::MAX as usize - 10, 11));
+        assert!(!fits_offsets(usize::MAX, 1));
+    }
+
+    #[test]
+    fn test_name_with_nul() {
+        let err = Column::new("source\0id", ColumnType::Int64).unwrap_err();
+        assert_eq!(err.code, ErrorCode::OutputEncoding);
+        assert!(err.message.contains("NUL"));
+    }
+}
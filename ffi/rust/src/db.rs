@@ -0,0 +1,233 @@
+use std::time::Duration;
+use rusqlite::types::{ToSqlOutput, Value, ValueRef};
+use rusqlite::{Connection, Statement};
+use crate::columnar::{Column, ColumnBatch, ColumnData, ColumnType};
+use crate::error::{ErrorCode, ParseError};
+use crate::schema::{GaiaType, TMASS_PSC};
+use crate::tables::TableProfile;
+use crate::tmass::TmassOptions;
+
+// How long to wait for a lock held by another connection, such as the
+// TypeScript side's
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An open SQLite database and the path used in error messages
+pub struct Database {
+    pub(crate) conn: Connection,
+    pub(crate) path: String,
+}
+
+impl Database {
+    /// Open or create the database at `path`
+    pub fn open(path: &str) -> Result<Self, ParseError> {
+        let conn = Connection::open(path).map_err(|e| ParseError::from_sqlite(e, path))?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| ParseError::from_sqlite(e, path))?;
+
+        Ok(Self {
+            conn,
+            path: path.to_string(),
+        })
+    }
+
+    /// Wrap an error from this database
+    pub(crate) fn error(&self, err: rusqlite::Error) -> ParseError {
+        ParseError::from_sqlite(err, &self.path)
+    }
+
+    /// Create `gaiadr3` as `GaiaDatabase.initialize()` in `src/database.ts`
+    /// does: `source_id TEXT PRIMARY KEY` and every other column `REAL`
+    ///
+    /// `columns` must match those the database was populated with, if any
+    /// (see `check_stored_columns`).
+    pub fn create_gaiadr3(&self, columns: &[String]) -> Result<(), ParseError> {
+        if columns.is_empty() {
+            return Err(ParseError::new(ErrorCode::InvalidArgument, "gaiadr3 needs at least one column"));
+        }
+        self.check_stored_columns(columns)?;
+
+        let column_defs: Vec<String> = columns
+            .iter()
+            .map(|column| match column.as_str() {
+                "source_id" => format!("{} TEXT PRIMARY KEY", quote_identifier(column)),
+                _ => format!("{} REAL", quote_identifier(column)),
+            })
+            .collect();
+
+        self.conn
+            .execute_batch(&format!("CREATE TABLE IF NOT EXISTS gaiadr3 ({});", column_defs.join(", ")))
+            .map_err(|e| self.error(e))
+    }
+
+    /// Create `tmass_xmatch` as `GaiaDatabase.initialize()` does
+    pub fn create_tmass_xmatch(&self) -> Result<(), ParseError> {
+        self.conn
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS tmass_xmatch (
+                    gaiadr3_source_id TEXT PRIMARY KEY,
+                    tmass_source_id TEXT NOT NULL
+                );",
+            )
+            .map_err(|e| self.error(e))
+    }
+
+    /// Create `tmass` as `GaiaDatabase.initialize()` does, adding the
+    /// columns for any optional fields in `options`
+    pub fn create_tmass(&self, options: &TmassOptions) -> Result<(), ParseError> {
+        self.conn
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS tmass (
+                    gaiadr3_source_id TEXT PRIMARY KEY,
+                    tmass_source_id TEXT NOT NULL,
+                    j_m REAL,
+                    h_m REAL,
+                    k_m REAL
+                );",
+            )
+            .map_err(|e| self.error(e))?;
+
+        let columns: Vec<(&str, &str)> = options
+            .columns()
+            .into_iter()
+            .map(|column| match TMASS_PSC.iter().find(|(name, _)| *name == column) {
+                Some((_, GaiaType::String)) => (column, "TEXT"),
+                _ => (column, "REAL"),
+            })
+            .collect();
+        self.add_missing_columns("tmass", &columns)
+    }
+
+    /// Add any of `columns` (name and SQL type) that `table` lacks
+    pub(crate) fn add_missing_columns(&self, table: &str, columns: &[(&str, &str)]) -> Result<(), ParseError> {
+        add_missing_columns(&self.conn, table, columns).map_err(|e| self.error(e))
+    }
+
+    /// Names of the columns of `table`, in order
+    pub(crate) fn table_columns(&self, table: &str) -> Result<Vec<String>, ParseError> {
+        table_columns(&self.conn, table).map_err(|e| self.error(e))
+    }
+}
+
+/// Create the local table of a profile other than `gaia_source`, keyed on
+/// `source_id` so it joins to `gaiadr3`, and add any of `columns` it lacks
+///
+/// Columns are typed as they are read: integers and bools as `INTEGER`,
+/// floats as `REAL`, and text and arrays as `TEXT`. The key is `TEXT`, as in
+/// `gaiadr3`.
+pub(crate) fn create_profile_table(
+    conn: &Connection,
+    profile: TableProfile,
+    columns: &[(&str, ColumnType)],
+) -> rusqlite::Result<()> {
+    let table = profile.local_table();
+    let key = profile.key_column();
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} ({} TEXT PRIMARY KEY);",
+        quote_identifier(table),
+        quote_identifier(key)
+    ))?;
+
+    let columns: Vec<(&str, &str)> = columns
+        .iter()
+        .filter(|(name, _)| *name != key)
+        .map(|&(name, column_type)| {
+            let sql_type = match column_type {
+                ColumnType::Int64 | ColumnType::Bool => "INTEGER",
+                ColumnType::Float64 => "REAL",
+                ColumnType::Utf8 | ColumnType::Float32List | ColumnType::Float64List => "TEXT",
+            };
+            (name, sql_type)
+        })
+        .collect();
+    add_missing_columns(conn, table, &columns)
+}
+
+fn add_missing_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> rusqlite::Result<()> {
+    let existing = table_columns(conn, table)?;
+    for (column, sql_type) in columns {
+        if !existing.iter().any(|c| c == column) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {};",
+                quote_identifier(table),
+                quote_identifier(column),
+                sql_type
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut statement = conn.prepare(&format!("PRAGMA table_info({})", quote_identifier(table)))?;
+    let columns = statement.query_map([], |row| row.get::<_, String>(1))?.collect();
+    columns
+}
+
+/// Quote a column or table name for use in SQL
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// `INSERT OR IGNORE` statement for the columns of a batch
+pub(crate) fn insert_sql(table: &str, batch: &ColumnBatch) -> String {
+    let names: Vec<String> = batch
+        .columns
+        .iter()
+        .map(|column| quote_identifier(&column.name.to_string_lossy()))
+        .collect();
+    let placeholders = vec!["?"; names.len()].join(", ");
+
+    format!(
+        "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+        quote_identifier(table),
+        names.join(", "),
+        placeholders
+    )
+}
+
+/// Insert every row of a batch with a statement from `insert_sql`,
+/// returning how many were new
+pub(crate) fn insert_batch(statement: &mut Statement, batch: &ColumnBatch) -> rusqlite::Result<usize> {
+    let mut inserted = 0;
+
+    for row in 0..batch.len {
+        for (index, column) in batch.columns.iter().enumerate() {
+            statement.raw_bind_parameter(index + 1, sql_value(column, row))?;
+        }
+        inserted += statement.raw_execute()?;
+    }
+
+    Ok(inserted)
+}
+
+/// A value as `insertGaiaRecords` would store it from the records
+/// `columnBatchToRecords` builds: 64-bit integers as text to match
+/// `source_id TEXT`, booleans as 1/0 and arrays as JSON with NaN as null
+pub(crate) fn sql_value(column: &Column, row: usize) -> ToSqlOutput<'_> {
+    if !column.is_valid(row) {
+        return ToSqlOutput::Borrowed(ValueRef::Null);
+    }
+
+    match &column.data {
+        ColumnData::Float64(values) => ToSqlOutput::Borrowed(ValueRef::Real(values[row])),
+        ColumnData::Int64(values) => ToSqlOutput::Owned(Value::Text(values[row].to_string())),
+        ColumnData::Utf8 { .. } => ToSqlOutput::Borrowed(ValueRef::Text(column.utf8_value(row).unwrap_or_default())),
+        ColumnData::Bool(bits) => {
+            ToSqlOutput::Borrowed(ValueRef::Integer(i64::from(bits[row / 8] & (1 << (row % 8)) != 0)))
+        }
+        ColumnData::Float32List { offsets, values } => {
+            let elements = &values[offsets[row] as usize..offsets[row + 1] as usize];
+            ToSqlOutput::Owned(Value::Text(json_list(elements.iter().map(|&v| f64::from(v)))))
+        }
+        ColumnData::Float64List { offsets, values } => {
+            let elements = &values[offsets[row] as usize..offsets[row + 1] as usize];
+            ToSqlOutput::Owned(Value::Text(json_list(elements.iter().copied())))
+        }
+    }
+}
+
+fn json_list(elements: impl Iterator<Item = f64>) -> String {
+    let elements: Vec<serde_json::Value> = elements
+        .map(|v| serde_json::Number::from_f64(v).map_or(serde_json::Value::Null, serde_json::Value::Number))
+        .collect();
+    serde_json::Value::Array(elements).to_string()
+}
@@ -0,0 +1,420 @@
+use std::collections::VecDeque;
+use csv_core::{ReadRecordResult, Reader as CsvTokenizer, ReaderBuilder as CsvTokenizerBuilder};
+use crate::columnar::{BatchBuilder, ColumnBatch};
+use crate::ecsv::EcsvHeader;
+use crate::error::{ErrorCode, ParseError};
+use crate::filter::RowFilter;
+use crate::gzip::GzipInflater;
+use crate::options::ReaderOptions;
+use crate::reader::resolve_columns;
+
+/// Push-based decoder for a gzipped Gaia CSV arriving in arbitrary slices
+///
+/// Compressed bytes are fed as they arrive (e.g. from a download stream).
+/// Inflate and CSV tokenizer state carry over between calls, and rows are
+/// grouped into batches of `batch_size` that can be pulled at any time.
+pub struct StreamDecoder {
+    source: String,
+    options: ReaderOptions,
+    batch_size: usize,
+    inflater: GzipInflater,
+    // Reused buffer for the bytes inflated by each feed
+    decompressed: Vec<u8>,
+    // Leading `#` lines, collected until the first data line starts
+    preamble: Option<Vec<u8>>,
+    ecsv: Option<EcsvHeader>,
+    tokenizer: CsvTokenizer,
+    // Fields of the record being tokenized, which may span several feeds
+    fields: Vec<u8>,
+    field_ends: Vec<usize>,
+    fields_len: usize,
+    field_ends_len: usize,
+    headers: Option<Vec<String>>,
+    builder: Option<BatchBuilder>,
+    filter: RowFilter,
+    ready: VecDeque<ColumnBatch>,
+    // Lines passed to the tokenizer so far, counting the preamble's, and
+    // whether the last byte passed ended one
+    lines: u64,
+    at_line_start: bool,
+    // 1-based line on which the record being handled starts
+    record_line: u64,
+    finished: bool,
+}
+
+impl StreamDecoder {
+    /// Create a decoder; `source` names the stream in error messages
+    pub fn new(source: &str, options: &ReaderOptions, batch_size: usize) -> Self {
+        Self {
+            source: source.to_string(),
+            options: options.clone(),
+            batch_size: if batch_size == 0 { usize::MAX } else { batch_size },
+            inflater: GzipInflater::new(),
+            decompressed: Vec::new(),
+            preamble: Some(Vec::new()),
+            ecsv: None,
+            tokenizer: tokenizer(options.format.delimiter()),
+            fields: vec![0; 1024],
+            field_ends: vec![0; 256],
+            fields_len: 0,
+            field_ends_len: 0,
+            headers: None,
+            builder: None,
+            filter: RowFilter::default(),
+            ready: VecDeque::new(),
+            lines: 0,
+            at_line_start: true,
+            record_line: 0,
+            finished: false,
+        }
+    }
+
+    /// Feed the next slice of compressed bytes
+    pub fn feed(&mut self, compressed: &[u8]) -> Result<(), ParseError> {
+        if self.finished {
+            return Err(ParseError::new(
+                ErrorCode::InvalidArgument,
+                format!("{}: cannot feed a finished decoder", self.source),
+            ));
+        }
+
+        let mut decompressed = std::mem::take(&mut self.decompressed);
+        self.inflater
+            .inflate(compressed, &mut decompressed)
+            .map_err(|e| ParseError::from_io(e, &self.source))?;
+
+        let body = self.strip_preamble(&decompressed)?;
+        if !body.is_empty() {
+            self.tokenize(body)?;
+        }
+
+        // Hand the allocation back so it is reused for the next feed
+        decompressed.clear();
+        self.decompressed = decompressed;
+        Ok(())
+    }
+
+    /// Signal the end of input, flushing the last record and partial batch
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        // A stream that stops partway through a gzip member is an incomplete
+        // download, not a short file
+        self.inflater
+            .finish()
+            .map_err(|e| ParseError::from_io(e, &self.source))?;
+        if self.preamble.is_some() {
+            self.end_preamble()?;
+        }
+
+        // An empty input tells the tokenizer the stream has ended
+        self.tokenize(&[])?;
+
+        if let Some(builder) = self.builder.as_mut().filter(|b| !b.is_empty()) {
+            self.ready.push_back(builder.take());
+        }
+        Ok(())
+    }
+
+    /// Take the next completed batch, if any
+    pub fn next_batch(&mut self) -> Option<ColumnBatch> {
+        self.ready.pop_front()
+    }
+
+    /// Collect leading `#` lines into the preamble, returning the rest of
+    /// `input` once the first data line starts
+    fn strip_preamble<'a>(&mut self, mut input: &'a [u8]) -> Result<&'a [u8], ParseError> {
+        loop {
+            let (Some(preamble), Some(&first)) = (self.preamble.as_mut(), input.first()) else {
+                return Ok(input);
+            };
+
+            if first != b'#' && preamble.last().is_none_or(|&b| b == b'\n') {
+                self.end_preamble()?;
+                return Ok(input);
+            }
+
+            let end = input.iter().position(|&b| b == b'\n').map_or(input.len(), |idx| idx + 1);
+            preamble.extend_from_slice(&input[..end]);
+            input = &input[end..];
+        }
+    }
+
+    /// Parse the collected preamble as an ECSV header, switching to its
+    /// delimiter before any data is tokenized
+    fn end_preamble(&mut self) -> Result<(), ParseError> {
+        let preamble = self.preamble.take().unwrap_or_default();
+        self.lines = memchr::memchr_iter(b'\n', &preamble).count() as u64;
+        self.ecsv = EcsvHeader::parse(&String::from_utf8_lossy(&preamble), &self.source)?;
+
+        if let Some(ecsv) = &self.ecsv {
+            self.tokenizer = tokenizer(ecsv.delimiter);
+        }
+        Ok(())
+    }
+
+    /// Run decompressed bytes through the tokenizer
+    ///
+    /// Must only be called with an empty slice from `finish`, since csv-core
+    /// treats empty input as end of stream.
+    fn tokenize(&mut self, mut input: &[u8]) -> Result<(), ParseError> {
+        let at_eof = input.is_empty();
+
+        loop {
+            let (result, nin, nout, nend) = self.tokenizer.read_record(
+                input,
+                &mut self.fields[self.fields_len..],
+                &mut self.field_ends[self.field_ends_len..],
+            );
+            if let Some(&last) = input[..nin].last() {
+                self.lines += memchr::memchr_iter(b'\n', &input[..nin]).count() as u64;
+                self.at_line_start = last == b'\n';
+            }
+            input = &input[nin..];
+            self.fields_len += nout;
+            self.field_ends_len += nend;
+
+            match result {
+                ReadRecordResult::InputEmpty => return Ok(()),
+                ReadRecordResult::OutputFull => {
+                    let len = self.fields.len();
+                    self.fields.resize(len * 2, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    let len = self.field_ends.len();
+                    self.field_ends.resize(len * 2, 0);
+                }
+                ReadRecordResult::Record => {
+                    self.handle_record()?;
+                    self.fields_len = 0;
+                    self.field_ends_len = 0;
+                }
+                ReadRecordResult::End => return Ok(()),
+            }
+
+            if input.is_empty() && !at_eof {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Turn a completed record into either the header or a row
+    fn handle_record(&mut self) -> Result<(), ParseError> {
+        // The record ends on the last line passed, and starts as many lines
+        // earlier as its quoted fields span, which is how `RecordReader`
+        // numbers rows too
+        let end_line = self.lines + u64::from(!self.at_line_start);
+        let spanned = memchr::memchr_iter(b'\n', &self.fields[..self.fields_len]).count() as u64;
+        self.record_line = end_line - spanned;
+
+        // Headerless formats start with data on the first record
+        if self.headers.is_none() {
+            if let Some(names) = self.options.format.fixed_headers() {
+                self.set_headers(names)?;
+            }
+        }
+
+        let record = &self.fields[..self.fields_len];
+        let ends = &self.field_ends[..self.field_ends_len];
+        let invalid_utf8 = |e: std::str::Utf8Error| {
+            ParseError::new(
+                ErrorCode::CsvSyntax,
+                format!("{}: row {}: invalid UTF-8: {}", self.source, self.record_line, e),
+            )
+        };
+        let field = |idx: usize| -> Option<&[u8]> {
+            let start = if idx == 0 { 0 } else { *ends.get(idx - 1)? };
+            Some(&record[start..*ends.get(idx)?])
+        };
+
+        let Some(headers) = &self.headers else {
+            let headers = (0..ends.len())
+                .map(|idx| std::str::from_utf8(field(idx).unwrap_or_default()).map(str::to_string))
+                .collect::<Result<Vec<String>, _>>()
+                .map_err(invalid_utf8)?;
+            return self.set_headers(headers);
+        };
+
+        if ends.len() != headers.len() {
+            return Err(ParseError::new(
+                ErrorCode::CsvSyntax,
+                format!(
+                    "{}: row {}: expected {} fields, found {}",
+                    self.source,
+                    self.record_line,
+                    headers.len(),
+                    ends.len()
+                ),
+            ));
+        }
+
+        // Only the fields that are filtered on or kept are checked for UTF-8
+        if !self.filter.accepts(|idx| field(idx).and_then(|f| std::str::from_utf8(f).ok())) {
+            return Ok(());
+        }
+
+        // The builder always exists once headers have been read
+        if let Some(builder) = self.builder.as_mut() {
+            builder.push_row(field).map_err(|err| {
+                ParseError::new(err.code, format!("{}: row {}: {}", self.source, self.record_line, err.message))
+            })?;
+            if builder.len() >= self.batch_size {
+                self.ready.push_back(builder.take());
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the selected columns and row filter against the header
+    fn set_headers(&mut self, headers: Vec<String>) -> Result<(), ParseError> {
+        self.options = self.options.with_ecsv(
+            self.ecsv.take(),
+            headers.iter().map(String::as_str),
+            &self.source,
+        )?;
+        let indices = resolve_columns(
+            headers.iter().map(String::as_str),
+            &self.options.columns,
+            self.options.strict_columns,
+            &self.source,
+        )?;
+        self.filter = RowFilter::compile(
+            headers.iter().map(String::as_str),
+            &self.options,
+            &self.source,
+        )?;
+        self.builder = Some(BatchBuilder::new(|idx| &headers[idx], &indices, &self.options)?);
+        self.headers = Some(headers);
+        Ok(())
+    }
+}
+
+fn tokenizer(delimiter: u8) -> CsvTokenizer {
+    CsvTokenizerBuilder::new()
+        .comment(Some(b'#'))
+        .delimiter(delimiter)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FileFormat;
+    use crate::columnar::ColumnType;
+    use crate::test_support::{gzip_bytes, SAMPLE_CSV, SAMPLE_ECSV, SAMPLE_TMASS_PSC};
+
+    #[test]
+    fn test_feed_byte_by_byte() {
+        let compressed = gzip_bytes(SAMPLE_CSV);
+        let columns = vec!["source_id".to_string(), "ra".to_string()];
+        let mut decoder = StreamDecoder::new("stream", &ReaderOptions::with_columns(&columns), 2);
+
+        for byte in &compressed {
+            decoder.feed(std::slice::from_ref(byte)).unwrap();
+        }
+        assert_eq!(decoder.next_batch().unwrap().len, 2);
+        assert!(decoder.next_batch().is_none());
+
+        decoder.finish().unwrap();
+        let last = decoder.next_batch().unwrap();
+        assert_eq!(last.len, 1);
+        assert_eq!(last.columns[0].name.to_str().unwrap(), "source_id");
+        assert!(decoder.next_batch().is_none());
+    }
+
+    #[test]
+    fn test_ecsv_header_split_across_feeds() {
+        let compressed = gzip_bytes(SAMPLE_ECSV);
+        let columns = vec!["classprob_dsc_combmod_star".to_string(), "spectraltype_esphs".to_string()];
+        let mut decoder = StreamDecoder::new("stream", &ReaderOptions::with_columns(&columns), 0);
+
+        for chunk in compressed.chunks(7) {
+            decoder.feed(chunk).unwrap();
+        }
+        decoder.finish().unwrap();
+
+        let batch = decoder.next_batch().unwrap();
+        assert_eq!(batch.len, 2);
+        assert_eq!(batch.columns[0].column_type(), ColumnType::Float64);
+        assert_eq!(batch.columns[1].column_type(), ColumnType::Utf8);
+        assert_eq!(decoder.options.ecsv_column("ra").unwrap().unit.as_deref(), Some("deg"));
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let compressed = gzip_bytes(SAMPLE_CSV);
+        let columns = vec!["source_id".to_string()];
+        let mut decoder = StreamDecoder::new("stream", &ReaderOptions::with_columns(&columns), 0);
+
+        // Everything but the trailer inflates, so only finish can tell
+        decoder.feed(&compressed[..compressed.len() - 8]).unwrap();
+        let err = decoder.finish().unwrap_err();
+        assert_eq!(err.code, ErrorCode::Truncated);
+        assert!(decoder.next_batch().is_none());
+    }
+
+    #[test]
+    fn test_last_record_without_newline() {
+        let contents = SAMPLE_CSV.trim_end();
+        let compressed = gzip_bytes(contents);
+        let columns = vec!["ra".to_string()];
+        let mut decoder = StreamDecoder::new("stream", &ReaderOptions::with_columns(&columns), 0);
+
+        decoder.feed(&compressed).unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(decoder.next_batch().unwrap().len, 3);
+    }
+
+    #[test]
+    fn test_headerless_format() {
+        let compressed = gzip_bytes(SAMPLE_TMASS_PSC);
+        let options = ReaderOptions {
+            format: FileFormat::TmassPsc,
+            columns: vec!["designation".to_string(), "k_m".to_string()],
+            ..ReaderOptions::default()
+        };
+        let mut decoder = StreamDecoder::new("psc_aaa.gz", &options, 0);
+
+        decoder.feed(&compressed).unwrap();
+        decoder.finish().unwrap();
+        let batch = decoder.next_batch().unwrap();
+        assert_eq!(batch.len, 3);
+        assert_eq!(batch.columns[1].null_count, 1);
+    }
+
+    #[test]
+    fn test_ragged_row_is_csv_error() {
+        let contents = format!("{}1,2\n", SAMPLE_CSV);
+        let compressed = gzip_bytes(&contents);
+        let columns = vec!["ra".to_string()];
+        let mut decoder = StreamDecoder::new("https://example/file.csv.gz", &ReaderOptions::with_columns(&columns), 0);
+
+        let err = decoder.feed(&compressed).unwrap_err();
+        assert_eq!(err.code, ErrorCode::CsvSyntax);
+        assert!(err.message.starts_with("https://example/file.csv.gz: row 7:"));
+    }
+
+    #[test]
+    fn test_error_row_is_file_line() {
+        let contents = format!(
+            "{}\r\n# comment\r\n\r\n1,\"multi\r\nline\",3\r\n1,2\r\n",
+            SAMPLE_CSV.replace('\n', "\r\n")
+        );
+        let compressed = gzip_bytes(&contents);
+        let columns = vec!["ra".to_string()];
+        let mut decoder = StreamDecoder::new("stream", &ReaderOptions::with_columns(&columns), 0);
+
+        let mut err = None;
+        for chunk in compressed.chunks(5) {
+            if let Err(e) = decoder.feed(chunk) {
+                err = Some(e);
+                break;
+            }
+        }
+        // The quoted field starts on line 10 and spans two lines
+        assert!(err.unwrap().message.starts_with("stream: row 10:"));
+    }
+}
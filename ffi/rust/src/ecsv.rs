@@ -0,0 +1,585 @@
+use std::io::{self, BufRead};
+use serde_json::{Map, Value};
+use crate::error::{ErrorCode, ParseError};
+use crate::schema::GaiaType;
+
+/// Metadata of one column declared in an ECSV header
+#[derive(Debug, Clone, PartialEq)]
+pub struct EcsvColumn {
+    pub name: String,
+    /// ECSV datatype such as `int64`, `float32` or `string`
+    pub datatype: String,
+    /// Element type of array-valued columns, e.g. `float32[55]`
+    pub subtype: Option<String>,
+    pub unit: Option<String>,
+    /// IVOA Unified Content Descriptor, e.g. `pos.eq.ra;meta.main`
+    pub ucd: Option<String>,
+    pub description: Option<String>,
+}
+
+impl EcsvColumn {
+    /// Logical type of the column, or None for datatypes we do not convert
+    ///
+    /// Array-valued columns are declared as strings with an array `subtype`
+    /// such as `float32[55]` or `float64[null]`. Arrays of other element
+    /// types are read as plain strings.
+    pub fn gaia_type(&self) -> Option<GaiaType> {
+        let element_type = self
+            .subtype
+            .as_deref()
+            .and_then(|subtype| subtype.split_once('['))
+            .map(|(element_type, _)| element_type);
+
+        match self.datatype.as_str() {
+            "string" if matches!(element_type, Some("float16" | "float32")) => {
+                Some(GaiaType::Float32Array)
+            }
+            "string" if element_type == Some("float64") => Some(GaiaType::Float64Array),
+            "bool" => Some(GaiaType::Bool),
+            "int8" | "int16" | "int32" | "int64" | "uint8" | "uint16" | "uint32" | "uint64" => {
+                Some(GaiaType::Int64)
+            }
+            "float16" | "float32" | "float64" => Some(GaiaType::Float),
+            "string" => Some(GaiaType::String),
+            _ => None,
+        }
+    }
+}
+
+/// The YAML header of an ECSV file, which Gaia archive files carry as
+/// leading `#` comment lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct EcsvHeader {
+    /// ECSV format version from the `# %ECSV 1.0` line
+    pub version: String,
+    pub delimiter: u8,
+    pub columns: Vec<EcsvColumn>,
+    /// Table level metadata, as found under the `meta` key
+    pub meta: Value,
+}
+
+impl EcsvHeader {
+    /// Parse the `#` comment lines at the start of a file
+    ///
+    /// Returns None if they do not start with an `%ECSV` line. `source`
+    /// names the file or stream in error messages.
+    pub fn parse(preamble: &str, source: &str) -> Result<Option<Self>, ParseError> {
+        let mut lines = preamble.lines().filter_map(|line| {
+            let line = line.strip_prefix('#')?;
+            Some(line.strip_prefix(' ').unwrap_or(line))
+        });
+
+        let Some(version) = lines.next().and_then(|line| line.strip_prefix("%ECSV")) else {
+            return Ok(None);
+        };
+
+        let invalid = |message: String| {
+            ParseError::new(
+                ErrorCode::CsvSyntax,
+                format!("{}: invalid ECSV header: {}", source, message),
+            )
+        };
+
+        let body: Vec<&str> = lines.skip_while(|line| line.trim() != "---").skip(1).collect();
+        let root = BlockParser::new(&body).parse().map_err(invalid)?;
+
+        let delimiter = match root.get("delimiter").and_then(Yaml::as_str) {
+            None => b',',
+            Some(d) if d.len() == 1 => d.as_bytes()[0],
+            Some(d) => return Err(invalid(format!("unsupported delimiter {:?}", d))),
+        };
+
+        let columns = match root.get("datatype") {
+            None => Vec::new(),
+            Some(Yaml::Seq(items)) => items
+                .iter()
+                .map(column_from_yaml)
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?,
+            Some(_) => return Err(invalid("datatype is not a list".to_string())),
+        };
+
+        Ok(Some(Self {
+            version: version.trim().to_string(),
+            delimiter,
+            columns,
+            meta: root.get("meta").map_or(Value::Null, Yaml::to_json),
+        }))
+    }
+
+    /// Metadata of the column called `name`
+    pub fn column(&self, name: &str) -> Option<&EcsvColumn> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    /// Logical type declared for the column called `name`
+    pub fn declared_type(&self, name: &str) -> Option<GaiaType> {
+        self.column(name).and_then(EcsvColumn::gaia_type)
+    }
+
+    /// Check that the header describes the columns of the header row
+    ///
+    /// A header without a `datatype` list describes nothing and always passes.
+    pub fn check_headers<'a>(
+        &self,
+        headers: impl IntoIterator<Item = &'a str>,
+        source: &str,
+    ) -> Result<(), ParseError> {
+        if self.columns.is_empty() {
+            return Ok(());
+        }
+
+        let headers: Vec<&str> = headers.into_iter().collect();
+        let declared: Vec<&str> = self.columns.iter().map(|c| c.name.as_str()).collect();
+        if headers == declared {
+            return Ok(());
+        }
+
+        let message = match headers.iter().zip(&declared).position(|(h, d)| h != d) {
+            Some(idx) => format!(
+                "ECSV header declares column {} at position {}, header row has {}",
+                declared[idx],
+                idx + 1,
+                headers[idx]
+            ),
+            None => format!(
+                "ECSV header declares {} columns, header row has {}",
+                declared.len(),
+                headers.len()
+            ),
+        };
+        Err(ParseError::new(ErrorCode::CsvSyntax, format!("{}: {}", source, message)))
+    }
+
+    /// Describe each column whose declared type differs from a built-in
+    /// schema, such as a column whose type changed between data releases
+    pub fn schema_mismatches(&self, schema_type: impl Fn(&str) -> Option<GaiaType>) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter_map(|column| {
+                let expected = schema_type(&column.name)?;
+                (column.gaia_type() != Some(expected)).then(|| {
+                    format!(
+                        "{} is {}, expected {}",
+                        column.name,
+                        column.datatype,
+                        expected.column_type().name()
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// Consume the leading `#` lines of a reader, leaving it at the first
+/// data line
+pub(crate) fn read_preamble(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut preamble = Vec::new();
+    while reader.fill_buf()?.first() == Some(&b'#') {
+        reader.read_until(b'\n', &mut preamble)?;
+    }
+    Ok(String::from_utf8_lossy(&preamble).into_owned())
+}
+
+fn column_from_yaml(item: &Yaml) -> Result<EcsvColumn, String> {
+    let text = |key: &str| item.get(key).and_then(Yaml::as_str).map(str::to_string);
+
+    let name = text("name").ok_or_else(|| "datatype entry without a name".to_string())?;
+    // astropy keeps the UCD in the column's `meta` mapping
+    let ucd = text("ucd").or_else(|| {
+        item.get("meta")
+            .and_then(|meta| meta.get("ucd"))
+            .and_then(Yaml::as_str)
+            .map(str::to_string)
+    });
+
+    Ok(EcsvColumn {
+        name,
+        datatype: text("datatype").unwrap_or_else(|| "string".to_string()),
+        subtype: text("subtype"),
+        unit: text("unit"),
+        ucd,
+        description: text("description"),
+    })
+}
+
+/// The subset of YAML written in ECSV headers: block and flow mappings and
+/// sequences of plain or quoted scalars
+#[derive(Debug, Clone, PartialEq)]
+enum Yaml {
+    Scalar(String),
+    Seq(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Yaml::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Yaml::Scalar(s) => Value::String(s.clone()),
+            Yaml::Seq(items) => Value::Array(items.iter().map(Yaml::to_json).collect()),
+            Yaml::Map(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.to_json()))
+                    .collect::<Map<_, _>>(),
+            ),
+        }
+    }
+}
+
+/// Indentation based parser for block YAML
+struct BlockParser<'a> {
+    // (indent, text) of each non-blank line
+    lines: Vec<(usize, &'a str)>,
+    pos: usize,
+}
+
+impl<'a> BlockParser<'a> {
+    fn new(lines: &[&'a str]) -> Self {
+        let lines = lines
+            .iter()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let text = line.trim_start();
+                (line.len() - text.len(), text)
+            })
+            .collect();
+        Self { lines, pos: 0 }
+    }
+
+    fn parse(mut self) -> Result<Yaml, String> {
+        let root = self.node(0)?;
+        match self.lines.get(self.pos) {
+            Some((_, text)) => Err(format!("unexpected line {:?}", text)),
+            None => Ok(root),
+        }
+    }
+
+    /// Parse the node starting at the current line, if indented by at least
+    /// `min_indent`
+    fn node(&mut self, min_indent: usize) -> Result<Yaml, String> {
+        let Some(&(indent, text)) = self.lines.get(self.pos).filter(|(i, _)| *i >= min_indent) else {
+            return Ok(Yaml::Scalar(String::new()));
+        };
+
+        if is_seq_item(text) {
+            self.seq(indent)
+        } else if split_key(text).is_some() {
+            self.map(indent)
+        } else {
+            self.pos += 1;
+            parse_flow(text)
+        }
+    }
+
+    fn seq(&mut self, indent: usize) -> Result<Yaml, String> {
+        let mut items = Vec::new();
+
+        while let Some(&(i, text)) = self.lines.get(self.pos) {
+            if i != indent || !is_seq_item(text) {
+                break;
+            }
+
+            let rest = text[1..].trim_start();
+            if rest.is_empty() {
+                self.pos += 1;
+            } else {
+                // Treat `- key: value` as if the item started on its own line
+                self.lines[self.pos] = (indent + text.len() - rest.len(), rest);
+            }
+            items.push(self.node(indent + 1)?);
+        }
+
+        Ok(Yaml::Seq(items))
+    }
+
+    fn map(&mut self, indent: usize) -> Result<Yaml, String> {
+        let mut entries = Vec::new();
+
+        while let Some(&(i, text)) = self.lines.get(self.pos) {
+            if i != indent || is_seq_item(text) {
+                break;
+            }
+            let (key, value) = split_key(text).ok_or_else(|| format!("expected a key in {:?}", text))?;
+            self.pos += 1;
+
+            let value = strip_tag(value);
+            let node = if value.is_empty() {
+                // Block sequences may sit at the same indent as their key
+                match self.lines.get(self.pos) {
+                    Some(&(next, t)) if next > indent || (next == indent && is_seq_item(t)) => self.node(next)?,
+                    _ => Yaml::Scalar(String::new()),
+                }
+            } else {
+                // Long values may be folded onto more indented lines
+                let mut value = value.to_string();
+                while let Some(&(_, t)) = self.lines.get(self.pos).filter(|(next, _)| *next > indent) {
+                    value.push(' ');
+                    value.push_str(t);
+                    self.pos += 1;
+                }
+                parse_flow(&value)?
+            };
+
+            entries.push((key.to_string(), node));
+        }
+
+        Ok(Yaml::Map(entries))
+    }
+}
+
+fn is_seq_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Split a block mapping entry into its key and (possibly empty) value
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with(['{', '[', '\'', '"']) {
+        return None;
+    }
+    let end = match text.find(": ") {
+        Some(end) => end,
+        None if text.ends_with(':') => text.len() - 1,
+        None => return None,
+    };
+    Some((text[..end].trim(), text[end + 1..].trim()))
+}
+
+/// Drop a YAML tag such as `!!omap` from the start of a value
+fn strip_tag(value: &str) -> &str {
+    if value.starts_with('!') {
+        value.split_once(char::is_whitespace).map_or("", |(_, rest)| rest.trim_start())
+    } else {
+        value
+    }
+}
+
+/// Parse a single-line value, which may be a flow mapping or sequence
+fn parse_flow(text: &str) -> Result<Yaml, String> {
+    let mut flow = Flow { text, pos: 0 };
+    let value = flow.value(false)?;
+    flow.skip_whitespace();
+    if flow.pos < text.len() {
+        return Err(format!("unexpected {:?} after value", &text[flow.pos..]));
+    }
+    Ok(value)
+}
+
+struct Flow<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Flow<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(format!("expected {:?} in {:?}", c, self.text));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, in_flow: bool) -> Result<Yaml, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.map(),
+            Some('[') => self.seq(),
+            Some('\'' | '"') => self.quoted().map(Yaml::Scalar),
+            _ => Ok(Yaml::Scalar(self.plain(if in_flow { ",]}" } else { "" }).to_string())),
+        }
+    }
+
+    fn map(&mut self) -> Result<Yaml, String> {
+        self.pos += 1;
+        let mut entries = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Yaml::Map(entries));
+            }
+
+            let key = match self.peek() {
+                Some('\'' | '"') => self.quoted()?,
+                _ => self.plain(":,}").to_string(),
+            };
+            self.expect(':')?;
+            entries.push((key, self.value(true)?));
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                _ => return Err(format!("unterminated mapping in {:?}", self.text)),
+            }
+        }
+    }
+
+    fn seq(&mut self) -> Result<Yaml, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Yaml::Seq(items));
+            }
+
+            items.push(self.value(true)?);
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err(format!("unterminated sequence in {:?}", self.text)),
+            }
+        }
+    }
+
+    /// A plain scalar running up to any of `terminators` or the end of text
+    fn plain(&mut self, terminators: &str) -> &str {
+        let rest = &self.text[self.pos..];
+        let end = rest.find(|c| terminators.contains(c)).unwrap_or(rest.len());
+        self.pos += end;
+        rest[..end].trim()
+    }
+
+    /// A single or double quoted scalar
+    fn quoted(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut value = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                // '' is an escaped quote inside single quotes
+                '\'' if quote == '\'' => {
+                    if self.text[self.pos + offset + 1..].starts_with('\'') {
+                        chars.next();
+                        value.push('\'');
+                    } else {
+                        self.pos += offset + 1;
+                        return Ok(value);
+                    }
+                }
+                '"' if quote == '"' => {
+                    self.pos += offset + 1;
+                    return Ok(value);
+                }
+                '\\' if quote == '"' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, escaped)) => value.push(escaped),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+
+        Err(format!("unterminated string in {:?}", self.text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FileFormat;
+    use crate::test_support::SAMPLE_ECSV;
+
+    #[test]
+    fn test_parse_flow_columns() {
+        let preamble: String = SAMPLE_ECSV.lines().take_while(|l| l.starts_with('#')).map(|l| format!("{}\n", l)).collect();
+        let header = EcsvHeader::parse(&preamble, "a.csv.gz").unwrap().unwrap();
+
+        assert_eq!(header.version, "1.0");
+        assert_eq!(header.delimiter, b',');
+        assert_eq!(header.columns.len(), 5);
+
+        let ra = header.column("ra").unwrap();
+        assert_eq!(ra.datatype, "float64");
+        assert_eq!(ra.unit.as_deref(), Some("deg"));
+        assert_eq!(ra.ucd.as_deref(), Some("pos.eq.ra;meta.main"));
+        assert_eq!(ra.description.as_deref(), Some("Right ascension, ICRS"));
+
+        assert_eq!(header.declared_type("classprob_dsc_combmod_star"), Some(GaiaType::Float));
+        assert_eq!(header.declared_type("has_rvs"), Some(GaiaType::Bool));
+        assert_eq!(header.meta["name"], "astrophysical_parameters");
+    }
+
+    #[test]
+    fn test_parse_block_columns() {
+        let preamble = "\
+# %ECSV 1.0
+# ---
+# delimiter: ' '
+# datatype:
+# -
+#   name: source_id
+#   datatype: int64
+#   description: 'Unique source identifier (unique within a particular Data
+#     Release)'
+#   meta:
+#     ucd: meta.id
+# - name: teff_gspphot
+#   datatype: float32
+#   unit: K
+# meta: !!omap
+# - {name: astrophysical_parameters}
+";
+        let header = EcsvHeader::parse(preamble, "a.csv.gz").unwrap().unwrap();
+
+        assert_eq!(header.delimiter, b' ');
+        assert_eq!(header.columns[0].ucd.as_deref(), Some("meta.id"));
+        assert_eq!(
+            header.columns[0].description.as_deref(),
+            Some("Unique source identifier (unique within a particular Data Release)")
+        );
+        assert_eq!(header.columns[1].unit.as_deref(), Some("K"));
+        assert_eq!(header.meta[0]["name"], "astrophysical_parameters");
+
+        assert_eq!(EcsvHeader::parse("# just a comment\n", "a.csv.gz").unwrap(), None);
+        let err = EcsvHeader::parse("# %ECSV 1.0\n# ---\n# datatype: [{name: a\n", "a.csv.gz").unwrap_err();
+        assert_eq!(err.code, ErrorCode::CsvSyntax);
+    }
+
+    #[test]
+    fn test_checks_against_header_row_and_schema() {
+        let preamble = "# %ECSV 1.0\n# ---\n# datatype:\n# - {name: source_id, datatype: int64}\n# - {name: ra, datatype: int32}\n";
+        let header = EcsvHeader::parse(preamble, "a.csv.gz").unwrap().unwrap();
+
+        assert!(header.check_headers(["source_id", "ra"], "a.csv.gz").is_ok());
+        let err = header.check_headers(["source_id", "dec"], "a.csv.gz").unwrap_err();
+        assert_eq!(err.message, "a.csv.gz: ECSV header declares column ra at position 2, header row has dec");
+
+        let mismatches = header.schema_mismatches(|name| FileFormat::GaiaCsv.declared_type(name));
+        assert_eq!(mismatches, vec!["ra is int32, expected float64"]);
+    }
+}
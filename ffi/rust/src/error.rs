@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+
+/// Error categories reported across the FFI boundary
+///
+/// The numeric values are part of the C ABI and must not be reordered.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Ok = 0,
+    /// An argument was null or not valid UTF-8
+    InvalidArgument = 1,
+    /// A JSON argument (columns, reader, crossmatch or 2MASS options, or
+    /// dataset metadata) is invalid
+    InvalidOptionsJson = 2,
+    /// The file could not be opened or read
+    Io = 3,
+    /// The compressed stream is corrupt (the name predates codecs other
+    /// than gzip)
+    CorruptGzip = 4,
+    /// The decompressed data is not valid CSV
+    CsvSyntax = 5,
+    /// Requested columns are not present in the file header
+    MissingColumn = 6,
+    /// The row filter expression could not be parsed
+    InvalidFilter = 7,
+    /// The file's ECSV header declares types that differ from the built-in
+    /// schema, e.g. a file from another data release
+    SchemaMismatch = 8,
+    /// The file uses a compression codec this build was compiled without
+    UnsupportedCompression = 9,
+    /// The file ends partway through, as left by an interrupted download
+    Truncated = 10,
+    /// The SQLite database could not be opened or written
+    Database = 11,
+    /// A result could not be encoded for the caller, as a C string or with
+    /// `i32` Arrow offsets
+    OutputEncoding = 12,
+    /// The database was populated with different settings, or by a newer
+    /// version of gaiaoffline
+    SettingsMismatch = 13,
+}
+
+/// An error with its FFI category and a human readable message
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Classify an error raised while reading `file_path`
+    ///
+    /// `row` is the 1-based line number of the record being read, if known.
+    pub fn from_csv(err: csv::Error, file_path: &str, row: Option<u64>) -> Self {
+        let location = match row {
+            Some(row) => format!("{}: row {}", file_path, row),
+            None => file_path.to_string(),
+        };
+
+        let code = match err.kind() {
+            csv::ErrorKind::Io(io_err) => io_error_code(io_err),
+            _ => ErrorCode::CsvSyntax,
+        };
+
+        Self::new(code, format!("{}: {}", location, err))
+    }
+
+    /// Classify an IO error raised while opening or reading `file_path`
+    pub fn from_io(err: io::Error, file_path: &str) -> Self {
+        Self::new(io_error_code(&err), format!("{}: {}", file_path, err))
+    }
+
+    /// Wrap an error raised by SQLite while working on `db_path`
+    pub fn from_sqlite(err: rusqlite::Error, db_path: &str) -> Self {
+        Self::new(ErrorCode::Database, format!("{}: {}", db_path, err))
+    }
+
+    /// Classify an IO error raised while reading the record on line `row`
+    /// of `file_path`
+    pub fn from_io_at_row(err: io::Error, file_path: &str, row: u64) -> Self {
+        Self::new(io_error_code(&err), format!("{}: row {}: {}", file_path, row, err))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// flate2 and the other decoders report bad headers, bad compressed data and
+/// checksum mismatches as `InvalidInput`/`InvalidData`, and a stream cut
+/// short as `UnexpectedEof`
+fn io_error_code(err: &io::Error) -> ErrorCode {
+    match err.kind() {
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => ErrorCode::CorruptGzip,
+        io::ErrorKind::UnexpectedEof => ErrorCode::Truncated,
+        _ => ErrorCode::Io,
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<ParseError>> = const { RefCell::new(None) };
+}
+
+/// Record the error from the most recent failed FFI call on this thread
+pub fn set_last_error(err: ParseError) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(err));
+}
+
+/// Forget the previous error at the start of an FFI call
+pub fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// Get a copy of the last error recorded on this thread
+pub fn last_error() -> Option<ParseError> {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_classification() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "missing");
+        assert_eq!(ParseError::from_io(not_found, "a.csv.gz").code, ErrorCode::Io);
+
+        let corrupt = io::Error::new(io::ErrorKind::InvalidInput, "corrupt deflate stream");
+        assert_eq!(ParseError::from_io(corrupt, "a.csv.gz").code, ErrorCode::CorruptGzip);
+
+        let truncated = io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file");
+        assert_eq!(ParseError::from_io(truncated, "a.csv.gz").code, ErrorCode::Truncated);
+    }
+
+    #[test]
+    fn test_last_error_is_thread_local() {
+        set_last_error(ParseError::new(ErrorCode::Io, "boom"));
+        assert_eq!(last_error().unwrap().code, ErrorCode::Io);
+
+        std::thread::spawn(|| assert!(last_error().is_none()))
+            .join()
+            .unwrap();
+
+        clear_last_error();
+        assert!(last_error().is_none());
+    }
+}
@@ -0,0 +1,593 @@
+//! Row filter expressions such as `parallax > 5 AND ruwe < 1.4`
+//!
+//! ```text
+//! expr       := or
+//! or         := and ("OR" and)*
+//! and        := not ("AND" not)*
+//! not        := "NOT" not | comparison
+//! comparison := sum (("=" | "!=" | "<>" | "<" | "<=" | ">" | ">=") sum
+//!                    | "IS" ["NOT"] "NULL")?
+//! sum        := product (("+" | "-") product)*
+//! product    := unary (("*" | "/") unary)*
+//! unary      := "-" unary | primary
+//! primary    := number | 'string' | TRUE | FALSE | NULL | column | "(" expr ")"
+//! ```
+//!
+//! Keywords are case-insensitive. Nulls follow SQL three-valued logic: any
+//! comparison or arithmetic involving a null is unknown, and a row is only
+//! kept when the whole expression is true.
+
+use std::cmp::Ordering;
+use crate::columnar::{is_null_field, parse_float, parse_i64};
+use crate::error::{ErrorCode, ParseError};
+
+/// A filter expression with columns resolved to header indices
+///
+/// Chains of AND, OR and same-precedence arithmetic are kept flat rather than
+/// nested, so a filter with many terms is evaluated and dropped without deep
+/// recursion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(usize),
+    Literal(Literal),
+    Neg(Box<Expr>),
+    /// The first operand, then each operator applied left to right
+    Arith(Box<Expr>, Vec<(ArithOp, Expr)>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    IsNull(Box<Expr>, bool),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A value produced while evaluating an expression against one row
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value<'a> {
+    Null,
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(&'a str),
+}
+
+impl Expr {
+    /// Parse `source` and resolve its column names with `lookup`
+    ///
+    /// `location` names the file or stream in error messages.
+    pub fn compile(
+        source: &str,
+        lookup: impl Fn(&str) -> Option<usize>,
+        location: &str,
+    ) -> Result<Self, ParseError> {
+        let tokens = tokenize(source).map_err(|msg| filter_error(location, source, msg))?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+            lookup: &lookup,
+        };
+
+        let expr = parser.parse_or().map_err(|err| match err {
+            CompileError::Syntax(msg) => filter_error(location, source, msg),
+            CompileError::UnknownColumn(name) => ParseError::new(
+                ErrorCode::MissingColumn,
+                format!("{}: filter column {} is not in the file header", location, name),
+            ),
+        })?;
+
+        if let Some(token) = parser.peek() {
+            return Err(filter_error(location, source, format!("unexpected {:?}", token)));
+        }
+        Ok(expr)
+    }
+
+    /// Whether the row with the given raw fields satisfies the expression
+    pub fn matches<'a>(&self, field: &impl Fn(usize) -> Option<&'a str>) -> bool {
+        self.truth(field) == Some(true)
+    }
+
+    /// Evaluate as a condition, with `None` for unknown
+    fn truth<'a>(&self, field: &impl Fn(usize) -> Option<&'a str>) -> Option<bool> {
+        match self {
+            Expr::Not(inner) => inner.truth(field).map(|b| !b),
+            Expr::And(terms) => logic(terms, false, field),
+            Expr::Or(terms) => logic(terms, true, field),
+            Expr::IsNull(inner, negated) => {
+                Some((inner.eval(field) == Value::Null) != *negated)
+            }
+            Expr::Compare(op, lhs, rhs) => {
+                let ordering = compare(lhs.eval(field), rhs.eval(field))?;
+                Some(match op {
+                    CompareOp::Eq => ordering == Ordering::Equal,
+                    CompareOp::Ne => ordering != Ordering::Equal,
+                    CompareOp::Lt => ordering == Ordering::Less,
+                    CompareOp::Le => ordering != Ordering::Greater,
+                    CompareOp::Gt => ordering == Ordering::Greater,
+                    CompareOp::Ge => ordering != Ordering::Less,
+                })
+            }
+            _ => match self.eval(field) {
+                Value::Bool(b) => Some(b),
+                _ => None,
+            },
+        }
+    }
+
+    /// Evaluate as a value borrowing from both the row and the expression
+    fn eval<'v, 'a: 'v>(&'v self, field: &impl Fn(usize) -> Option<&'a str>) -> Value<'v> {
+        match self {
+            Expr::Column(idx) => field_value(field(*idx).unwrap_or("")),
+            Expr::Literal(literal) => match literal {
+                Literal::Null => Value::Null,
+                Literal::Int(num) => Value::Int(*num),
+                Literal::Float(num) => Value::Float(*num),
+                Literal::Bool(b) => Value::Bool(*b),
+                Literal::Str(s) => Value::Str(s),
+            },
+            Expr::Neg(inner) => match inner.eval(field) {
+                Value::Int(num) => num.checked_neg().map_or(Value::Float(-(num as f64)), Value::Int),
+                Value::Float(num) => Value::Float(-num),
+                _ => Value::Null,
+            },
+            Expr::Arith(first, rest) => rest
+                .iter()
+                .fold(first.eval(field), |acc, (op, rhs)| arith(*op, acc, rhs.eval(field))),
+            _ => self.truth(field).map_or(Value::Null, Value::Bool),
+        }
+    }
+}
+
+/// Combine `terms` with AND, or with OR when `decisive` is true
+///
+/// A term equal to `decisive` settles the result; otherwise any unknown term
+/// makes the result unknown.
+fn logic<'a>(terms: &[Expr], decisive: bool, field: &impl Fn(usize) -> Option<&'a str>) -> Option<bool> {
+    let mut result = Some(!decisive);
+    for term in terms {
+        match term.truth(field) {
+            Some(b) if b == decisive => return Some(decisive),
+            Some(_) => {}
+            None => result = None,
+        }
+    }
+    result
+}
+
+/// Interpret a raw CSV field without knowing its column type
+fn field_value(raw: &str) -> Value<'_> {
+    if is_null_field(raw) {
+        Value::Null
+    } else if let Some(num) = parse_i64(raw.as_bytes()) {
+        Value::Int(num)
+    } else if let Some(num) = parse_float(raw.as_bytes()) {
+        Value::Float(num)
+    } else if raw.eq_ignore_ascii_case("true") {
+        Value::Bool(true)
+    } else if raw.eq_ignore_ascii_case("false") {
+        Value::Bool(false)
+    } else {
+        Value::Str(raw)
+    }
+}
+
+/// Order two values, or `None` if either is null or their types differ
+///
+/// Integers are compared exactly so 64-bit IDs keep full precision.
+fn compare(lhs: Value, rhs: Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(&b)),
+        (Value::Int(a), Value::Float(b)) => (a as f64).partial_cmp(&b),
+        (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(&b),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(&b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn arith<'a>(op: ArithOp, lhs: Value<'a>, rhs: Value<'a>) -> Value<'a> {
+    if let (Value::Int(a), Value::Int(b)) = (lhs, rhs) {
+        let exact = match op {
+            ArithOp::Add => a.checked_add(b),
+            ArithOp::Sub => a.checked_sub(b),
+            ArithOp::Mul => a.checked_mul(b),
+            ArithOp::Div => None,
+        };
+        if let Some(num) = exact {
+            return Value::Int(num);
+        }
+    }
+
+    let as_float = |value| match value {
+        Value::Int(num) => Some(num as f64),
+        Value::Float(num) => Some(num),
+        _ => None,
+    };
+    let (Some(a), Some(b)) = (as_float(lhs), as_float(rhs)) else {
+        return Value::Null;
+    };
+
+    Value::Float(match op {
+        ArithOp::Add => a + b,
+        ArithOp::Sub => a - b,
+        ArithOp::Mul => a * b,
+        ArithOp::Div => a / b,
+    })
+}
+
+fn filter_error(location: &str, source: &str, message: impl std::fmt::Display) -> ParseError {
+    ParseError::new(
+        ErrorCode::InvalidFilter,
+        format!("{}: invalid filter {:?}: {}", location, source, message),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+const OPERATORS: &[&str] = &["<=", ">=", "<>", "!=", "=", "<", ">", "+", "-", "*", "/"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::LParen } else { Token::RParen });
+            rest = &rest[1..];
+        } else if c == '\'' {
+            // Quotes inside a string are doubled, as in SQL
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '\'')) if rest[1 + i + 1..].starts_with('\'') => {
+                        value.push('\'');
+                        chars.next();
+                    }
+                    Some((i, '\'')) => break 1 + i + 1,
+                    Some((_, ch)) => value.push(ch),
+                    None => return Err("unterminated string".to_string()),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = number_len(rest);
+            let text = &rest[..len];
+            let token = match text.parse::<i64>() {
+                Ok(num) => Token::Int(num),
+                Err(_) => Token::Float(
+                    text.parse().map_err(|_| format!("invalid number {}", text))?,
+                ),
+            };
+            tokens.push(token);
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected character {:?}", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Length of the number literal at the start of `s`, including any exponent
+fn number_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut len = 0;
+    while len < bytes.len() && (bytes[len].is_ascii_digit() || bytes[len] == b'.') {
+        len += 1;
+    }
+
+    if len < bytes.len() && (bytes[len] == b'e' || bytes[len] == b'E') {
+        let mut exp = len + 1;
+        if exp < bytes.len() && (bytes[exp] == b'+' || bytes[exp] == b'-') {
+            exp += 1;
+        }
+        if exp < bytes.len() && bytes[exp].is_ascii_digit() {
+            len = exp;
+            while len < bytes.len() && bytes[len].is_ascii_digit() {
+                len += 1;
+            }
+        }
+    }
+
+    len
+}
+
+/// Deepest nesting of parentheses, NOT and unary minus a filter may use,
+/// which keeps the recursive parser well within the stack
+const MAX_DEPTH: usize = 256;
+
+enum CompileError {
+    Syntax(String),
+    UnknownColumn(String),
+}
+
+struct Parser<'l, L: Fn(&str) -> Option<usize>> {
+    tokens: Vec<Token>,
+    pos: usize,
+    // Nesting of the expression being parsed
+    depth: usize,
+    lookup: &'l L,
+}
+
+impl<L: Fn(&str) -> Option<usize>> Parser<'_, L> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the next token if it is the keyword `word`
+    fn keyword(&mut self, word: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(word) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Consume the next token if it is one of `ops`
+    fn operator(&mut self, ops: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// Run `parse` one level deeper, failing past `MAX_DEPTH`
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, CompileError>) -> Result<Expr, CompileError> {
+        if self.depth == MAX_DEPTH {
+            return Err(CompileError::Syntax(format!("nested more than {} levels deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, CompileError> {
+        let mut terms = vec![self.parse_and()?];
+        while self.keyword("OR") {
+            terms.push(self.parse_and()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::Or(terms) })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, CompileError> {
+        let mut terms = vec![self.parse_not()?];
+        while self.keyword("AND") {
+            terms.push(self.parse_not()?);
+        }
+        Ok(if terms.len() == 1 { terms.remove(0) } else { Expr::And(terms) })
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, CompileError> {
+        if self.keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.nested(Self::parse_not)?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, CompileError> {
+        let lhs = self.parse_sum()?;
+
+        if self.keyword("IS") {
+            let negated = self.keyword("NOT");
+            if !self.keyword("NULL") {
+                return Err(CompileError::Syntax("expected NULL after IS".to_string()));
+            }
+            return Ok(Expr::IsNull(Box::new(lhs), negated));
+        }
+
+        let op = match self.operator(&["=", "!=", "<>", "<", "<=", ">", ">="]) {
+            Some("=") => CompareOp::Eq,
+            Some("!=") | Some("<>") => CompareOp::Ne,
+            Some("<") => CompareOp::Lt,
+            Some("<=") => CompareOp::Le,
+            Some(">") => CompareOp::Gt,
+            Some(">=") => CompareOp::Ge,
+            _ => return Ok(lhs),
+        };
+        Ok(Expr::Compare(op, Box::new(lhs), Box::new(self.parse_sum()?)))
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, CompileError> {
+        let first = self.parse_product()?;
+        let mut rest = Vec::new();
+        while let Some(op) = self.operator(&["+", "-"]) {
+            let op = if op == "+" { ArithOp::Add } else { ArithOp::Sub };
+            rest.push((op, self.parse_product()?));
+        }
+        Ok(arith_chain(first, rest))
+    }
+
+    fn parse_product(&mut self) -> Result<Expr, CompileError> {
+        let first = self.parse_unary()?;
+        let mut rest = Vec::new();
+        while let Some(op) = self.operator(&["*", "/"]) {
+            let op = if op == "*" { ArithOp::Mul } else { ArithOp::Div };
+            rest.push((op, self.parse_unary()?));
+        }
+        Ok(arith_chain(first, rest))
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, CompileError> {
+        if self.operator(&["-"]).is_some() {
+            return Ok(Expr::Neg(Box::new(self.nested(Self::parse_unary)?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, CompileError> {
+        let literal = match self.next() {
+            Some(Token::Int(num)) => Literal::Int(num),
+            Some(Token::Float(num)) => Literal::Float(num),
+            Some(Token::Str(s)) => Literal::Str(s),
+            Some(Token::LParen) => {
+                let expr = self.nested(Self::parse_or)?;
+                if self.next() != Some(Token::RParen) {
+                    return Err(CompileError::Syntax("expected )".to_string()));
+                }
+                return Ok(expr);
+            }
+            Some(Token::Ident(ident)) => {
+                if ident.eq_ignore_ascii_case("NULL") {
+                    Literal::Null
+                } else if ident.eq_ignore_ascii_case("TRUE") {
+                    Literal::Bool(true)
+                } else if ident.eq_ignore_ascii_case("FALSE") {
+                    Literal::Bool(false)
+                } else {
+                    return (self.lookup)(&ident)
+                        .map(Expr::Column)
+                        .ok_or(CompileError::UnknownColumn(ident));
+                }
+            }
+            Some(token) => return Err(CompileError::Syntax(format!("unexpected {:?}", token))),
+            None => return Err(CompileError::Syntax("unexpected end of expression".to_string())),
+        };
+        Ok(Expr::Literal(literal))
+    }
+}
+
+fn arith_chain(first: Expr, rest: Vec<(ArithOp, Expr)>) -> Expr {
+    if rest.is_empty() {
+        first
+    } else {
+        Expr::Arith(Box::new(first), rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADERS: [&str; 5] = ["source_id", "parallax", "ruwe", "dec", "phot_variable_flag"];
+
+    fn compile(source: &str) -> Result<Expr, ParseError> {
+        Expr::compile(source, |name| HEADERS.iter().position(|h| *h == name), "a.csv.gz")
+    }
+
+    fn matches(source: &str, row: [&str; 5]) -> bool {
+        compile(source).unwrap().matches(&|idx| row.get(idx).copied())
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let row = ["4295806720", "6.2", "1.1", "-45.5", "VARIABLE"];
+        assert!(matches("parallax > 5 AND ruwe < 1.4", row));
+        assert!(matches("dec < -30", row));
+        assert!(!matches("NOT (dec < -30) or parallax <= 5", row));
+        assert!(matches("phot_variable_flag = 'VARIABLE'", row));
+        assert!(matches("1000 / parallax < 200 and parallax * 2 - 1 >= 11.4", row));
+        assert!(matches("source_id = 4295806720 AND source_id <> 4295806721", row));
+        assert!(matches("ruwe < 1.4e0", row));
+    }
+
+    #[test]
+    fn test_nulls_are_unknown() {
+        let row = ["1", "null", "", "0", "NOT_AVAILABLE"];
+        assert!(!matches("parallax > 5", row));
+        assert!(!matches("NOT parallax > 5", row));
+        assert!(matches("parallax IS NULL AND ruwe is null", row));
+        assert!(matches("parallax > 5 OR dec = 0", row));
+        assert!(!matches("parallax IS NOT NULL", row));
+    }
+
+    #[test]
+    fn test_large_ids_compare_exactly() {
+        let row = ["6917529027641081857", "", "", "", ""];
+        assert!(matches("source_id > 6917529027641081856", row));
+    }
+
+    #[test]
+    fn test_long_chains() {
+        let row = ["1", "6.2", "1.1", "-45.5", ""];
+        let terms = 100_000;
+        assert!(matches(&vec!["ruwe < 1.4"; terms].join(" AND "), row));
+        assert!(!matches(&format!("{} AND ruwe > 2", vec!["ruwe < 1.4"; terms].join(" AND ")), row));
+        assert!(matches(&format!("{} OR dec < 0", vec!["ruwe > 2"; terms].join(" OR ")), row));
+        assert!(matches(&format!("{}0 > 0", "ruwe + ".repeat(terms)), row));
+        assert!(matches(&format!("{}1 > 0", "ruwe * ".repeat(terms)), row));
+        assert!(matches("10 - 2 - 3 = 5 AND 100 / 10 / 5 = 2", row));
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let err = compile("parallax >").unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidFilter);
+        assert!(err.message.starts_with("a.csv.gz: invalid filter"));
+
+        assert_eq!(compile("(ruwe < 1.4").unwrap_err().code, ErrorCode::InvalidFilter);
+        assert_eq!(compile("ruwe < 1.4 ruwe").unwrap_err().code, ErrorCode::InvalidFilter);
+        assert_eq!(compile("name = 'x").unwrap_err().code, ErrorCode::InvalidFilter);
+        assert_eq!(compile("ruwe ? 1").unwrap_err().code, ErrorCode::InvalidFilter);
+
+        let deep = format!("{}ruwe < 1.4{}", "(".repeat(300), ")".repeat(300));
+        assert_eq!(compile(&deep).unwrap_err().code, ErrorCode::InvalidFilter);
+        assert_eq!(compile(&"NOT ".repeat(100_000)).unwrap_err().code, ErrorCode::InvalidFilter);
+        assert!(compile(&format!("{}ruwe < 1.4{}", "(".repeat(200), ")".repeat(200))).is_ok());
+
+        let err = compile("pmra > 0").unwrap_err();
+        assert_eq!(err.code, ErrorCode::MissingColumn);
+        assert!(err.message.contains("pmra"));
+    }
+}
@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use crate::columnar::parse_float;
+use crate::error::{ErrorCode, ParseError};
+use crate::expr::Expr;
+use crate::options::ReaderOptions;
+
+/// Photometric band of a Gaia mean flux
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Band {
+    #[default]
+    G,
+    #[serde(rename = "BP")]
+    Bp,
+    #[serde(rename = "RP")]
+    Rp,
+}
+
+impl Band {
+    /// The `gaia_source` column holding the mean flux in this band
+    pub fn flux_column(self) -> &'static str {
+        match self {
+            Band::G => "phot_g_mean_flux",
+            Band::Bp => "phot_bp_mean_flux",
+            Band::Rp => "phot_rp_mean_flux",
+        }
+    }
+}
+
+/// Keep only sources brighter than `limit` in `band`
+///
+/// Magnitudes are computed from the mean flux as
+/// `zeropoint - 2.5 * log10(flux)`, matching `filterByMagnitude` in
+/// `src/utils.ts`. Rows with a missing or non-positive flux are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MagnitudeCut {
+    pub limit: f64,
+    #[serde(default)]
+    pub band: Band,
+    pub zeropoint: f64,
+}
+
+impl MagnitudeCut {
+    /// Whether a raw flux field passes the cut
+    pub fn accepts(&self, flux: &str) -> bool {
+        match parse_float::<f64>(flux.as_bytes()) {
+            Some(flux) if flux > 0.0 => self.zeropoint - 2.5 * flux.log10() < self.limit,
+            _ => false,
+        }
+    }
+}
+
+/// Row predicates resolved against a file's header
+///
+/// Built once per file so rejecting a row only looks at the raw fields it
+/// needs, before any of the kept columns are converted.
+#[derive(Debug, Clone, Default)]
+pub struct RowFilter {
+    magnitude: Option<(usize, MagnitudeCut)>,
+    expr: Option<Expr>,
+}
+
+impl RowFilter {
+    /// Compile the magnitude cut and filter expression of `options`,
+    /// resolving their columns to header indices
+    ///
+    /// `source` names the file or stream in error messages.
+    pub fn compile<'a>(
+        headers: impl IntoIterator<Item = &'a str> + Clone,
+        options: &ReaderOptions,
+        source: &str,
+    ) -> Result<Self, ParseError> {
+        let position = |column: &str| headers.clone().into_iter().position(|h| h == column);
+
+        let magnitude = match options.magnitude_cut {
+            Some(cut) => {
+                let column = cut.band.flux_column();
+                let index = position(column).ok_or_else(|| {
+                    ParseError::new(
+                        ErrorCode::MissingColumn,
+                        format!("{}: magnitude cut needs column {}", source, column),
+                    )
+                })?;
+                Some((index, cut))
+            }
+            None => None,
+        };
+
+        let expr = options
+            .filter
+            .as_deref()
+            .map(|filter| Expr::compile(filter, position, source))
+            .transpose()?;
+
+        Ok(Self { magnitude, expr })
+    }
+
+    /// Whether the record with the given fields should be kept
+    pub fn accepts<'a>(&self, field: impl Fn(usize) -> Option<&'a str>) -> bool {
+        if let Some((index, cut)) = &self.magnitude {
+            if !cut.accepts(field(*index).unwrap_or("")) {
+                return false;
+            }
+        }
+
+        self.expr.as_ref().is_none_or(|expr| expr.matches(&field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const G_CUT: MagnitudeCut = MagnitudeCut {
+        limit: 16.0,
+        band: Band::G,
+        zeropoint: 25.6873668671,
+    };
+
+    #[test]
+    fn test_magnitude_cut() {
+        // G = 25.687 - 2.5 * log10(flux), so the 16 mag limit is at flux ~7700
+        assert!(G_CUT.accepts("12345.6789"));
+        assert!(!G_CUT.accepts("5000"));
+        assert!(!G_CUT.accepts("0"));
+        assert!(!G_CUT.accepts("-12.5"));
+        assert!(!G_CUT.accepts("null"));
+        assert!(!G_CUT.accepts(""));
+    }
+
+    fn with_cut(cut: MagnitudeCut) -> ReaderOptions {
+        ReaderOptions {
+            magnitude_cut: Some(cut),
+            ..ReaderOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_compile_resolves_flux_column() {
+        let headers = ["source_id", "phot_bp_mean_flux", "phot_g_mean_flux"];
+        let filter = RowFilter::compile(headers, &with_cut(G_CUT), "a.csv.gz").unwrap();
+        let fields = ["1", "5000", "12345.6789"];
+        assert!(filter.accepts(|idx| fields.get(idx).copied()));
+
+        let bp_cut = MagnitudeCut { band: Band::Bp, ..G_CUT };
+        let filter = RowFilter::compile(headers, &with_cut(bp_cut), "a.csv.gz").unwrap();
+        assert!(!filter.accepts(|idx| fields.get(idx).copied()));
+
+        let err = RowFilter::compile(["source_id"], &with_cut(G_CUT), "a.csv.gz").unwrap_err();
+        assert_eq!(err.code, ErrorCode::MissingColumn);
+    }
+
+    #[test]
+    fn test_cut_and_expression_combine() {
+        let headers = ["source_id", "phot_g_mean_flux", "dec"];
+        let options = ReaderOptions {
+            filter: Some("dec < -30".to_string()),
+            ..with_cut(G_CUT)
+        };
+        let filter = RowFilter::compile(headers, &options, "a.csv.gz").unwrap();
+
+        let accepts = |fields: [&str; 3]| filter.accepts(|idx| fields.get(idx).copied());
+        assert!(accepts(["1", "12345.6789", "-45.5"]));
+        assert!(!accepts(["2", "12345.6789", "10.0"]));
+        assert!(!accepts(["3", "5000", "-45.5"]));
+    }
+}
@@ -0,0 +1,251 @@
+use std::io;
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+// Spare output capacity kept free before each inflate call
+const MIN_OUTPUT_SPACE: usize = 32 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Body,
+    Trailer,
+}
+
+/// Push-based gzip decompressor that checks every member's trailer
+///
+/// Handles multi-member streams (as written by `pigz` or by concatenating
+/// `.gz` files). Each member's CRC32 and ISIZE are verified against the
+/// inflated data, and `finish` tells a stream cut off partway through a
+/// member apart from a complete one.
+///
+/// Corruption is reported as `InvalidData` and truncation as
+/// `UnexpectedEof`, the same kinds flate2's readers use.
+pub(crate) struct GzipInflater {
+    state: State,
+    inflate: Decompress,
+    crc: Crc,
+    // Header or trailer bytes held back until the whole header or trailer
+    // has arrived
+    pending: Vec<u8>,
+    members: u64,
+}
+
+impl GzipInflater {
+    pub fn new() -> Self {
+        Self {
+            state: State::Header,
+            inflate: Decompress::new(false),
+            crc: Crc::new(),
+            pending: Vec::new(),
+            members: 0,
+        }
+    }
+
+    /// Inflate the next slice of compressed bytes, appending to `out`
+    pub fn inflate(&mut self, mut input: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        while !input.is_empty() {
+            match self.state {
+                State::Header => {
+                    let buffered = self.pending.len();
+                    self.pending.extend_from_slice(input);
+
+                    match header_len(&self.pending)? {
+                        Some(len) => {
+                            input = &input[len - buffered..];
+                            self.pending.clear();
+                            self.state = State::Body;
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                State::Body => {
+                    if out.capacity() - out.len() < MIN_OUTPUT_SPACE {
+                        out.reserve(MIN_OUTPUT_SPACE.max(input.len() * 4));
+                    }
+
+                    let (total_in, start) = (self.inflate.total_in(), out.len());
+                    let status = self
+                        .inflate
+                        .decompress_vec(input, out, FlushDecompress::None)
+                        .map_err(|e| self.corrupt(e))?;
+                    input = &input[(self.inflate.total_in() - total_in) as usize..];
+                    self.crc.update(&out[start..]);
+
+                    if status == Status::StreamEnd {
+                        self.state = State::Trailer;
+                    }
+                }
+                State::Trailer => {
+                    let take = (8 - self.pending.len()).min(input.len());
+                    self.pending.extend_from_slice(&input[..take]);
+                    input = &input[take..];
+
+                    if self.pending.len() == 8 {
+                        self.end_member()?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the input ended on a member boundary
+    pub fn finish(&self) -> io::Result<()> {
+        let part = match self.state {
+            State::Header if self.pending.is_empty() && self.members > 0 => return Ok(()),
+            State::Header => "header",
+            State::Body => "compressed data",
+            State::Trailer => "trailer",
+        };
+
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("gzip stream truncated in the {} of member {}", part, self.members + 1),
+        ))
+    }
+
+    /// Verify the 8-byte trailer in `pending` and get ready for another member
+    fn end_member(&mut self) -> io::Result<()> {
+        let word = |i: usize| u32::from_le_bytes(self.pending[i..i + 4].try_into().unwrap());
+        let (crc32, isize) = (word(0), word(4));
+
+        if crc32 != self.crc.sum() {
+            return Err(self.corrupt("CRC32 does not match the trailer"));
+        }
+        if isize != self.crc.amount() {
+            return Err(self.corrupt("length does not match the trailer ISIZE"));
+        }
+
+        self.members += 1;
+        self.state = State::Header;
+        self.pending.clear();
+        self.inflate.reset(false);
+        self.crc.reset();
+        Ok(())
+    }
+
+    fn corrupt(&self, reason: impl std::fmt::Display) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("corrupt gzip stream in member {}: {}", self.members + 1, reason),
+        )
+    }
+}
+
+/// Length of the gzip member header at the start of `buf`, or `None` if
+/// more bytes are needed to tell
+pub(crate) fn header_len(buf: &[u8]) -> io::Result<Option<usize>> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid gzip header: {}", reason));
+
+    let magic_len = buf.len().min(2);
+    if buf[..magic_len] != [0x1f, 0x8b][..magic_len] {
+        return Err(invalid("bad magic bytes"));
+    }
+    if buf.len() < 10 {
+        return Ok(None);
+    }
+    if buf[2] != 8 {
+        return Err(invalid("compression method is not deflate"));
+    }
+
+    let flags = buf[3];
+    if flags & 0xe0 != 0 {
+        return Err(invalid("reserved flags are set"));
+    }
+
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let Some(xlen) = buf.get(pos..pos + 2) else {
+            return Ok(None);
+        };
+        pos += 2 + u16::from_le_bytes([xlen[0], xlen[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            // Zero-terminated file name or comment
+            match buf.get(pos..).and_then(|rest| rest.iter().position(|&b| b == 0)) {
+                Some(end) => pos += end + 1,
+                None => return Ok(None),
+            }
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    Ok((buf.len() >= pos).then_some(pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{gzip_bytes, SAMPLE_CSV};
+
+    fn inflate_in_slices(compressed: &[u8], slice_len: usize) -> io::Result<Vec<u8>> {
+        let mut inflater = GzipInflater::new();
+        let mut out = Vec::new();
+        for slice in compressed.chunks(slice_len) {
+            inflater.inflate(slice, &mut out)?;
+        }
+        inflater.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_multi_member() {
+        let mut compressed = gzip_bytes(SAMPLE_CSV);
+        compressed.extend(gzip_bytes("4295806721,1\n"));
+
+        // A header with a file name, split at every possible point
+        let mut named = Vec::new();
+        flate2::GzBuilder::new()
+            .filename("gaia_source.csv")
+            .comment("part 3")
+            .write(&mut named, flate2::Compression::fast())
+            .finish()
+            .unwrap();
+        compressed.extend(named);
+
+        let expected = format!("{}4295806721,1\n", SAMPLE_CSV);
+        for slice_len in [1, 7, 64, compressed.len()] {
+            assert_eq!(inflate_in_slices(&compressed, slice_len).unwrap(), expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let compressed = gzip_bytes(SAMPLE_CSV);
+        for cut in 0..compressed.len() {
+            let err = inflate_in_slices(&compressed[..cut], 16).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", cut);
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let compressed = gzip_bytes(SAMPLE_CSV);
+
+        let mut bad_crc = compressed.clone();
+        let crc_offset = bad_crc.len() - 8;
+        bad_crc[crc_offset] ^= 0xff;
+        let err = inflate_in_slices(&bad_crc, 64).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("CRC32"));
+
+        let mut bad_isize = compressed.clone();
+        let isize_offset = bad_isize.len() - 1;
+        bad_isize[isize_offset] ^= 0x01;
+        assert!(inflate_in_slices(&bad_isize, 64).unwrap_err().to_string().contains("ISIZE"));
+
+        let mut trailing_garbage = compressed;
+        trailing_garbage.extend_from_slice(b"<html>");
+        let err = inflate_in_slices(&trailing_garbage, 64).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
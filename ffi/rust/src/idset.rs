@@ -0,0 +1,79 @@
+use rusqlite::types::ValueRef;
+use crate::db::Database;
+use crate::error::ParseError;
+
+/// The `source_id`s of every row in `gaiadr3`, held as a sorted array
+///
+/// At 8 bytes per source this is the most compact form that still allows
+/// a fast lookup, so even a full-sky table fits in memory.
+#[derive(Debug, Default)]
+pub struct SourceIdSet {
+    ids: Vec<u64>,
+}
+
+impl SourceIdSet {
+    /// Read every `source_id` from `gaiadr3`
+    ///
+    /// `source_id` is stored as text; values that are not 64-bit integers
+    /// cannot match a crossmatch pair and are skipped.
+    pub fn load(db: &Database) -> Result<Self, ParseError> {
+        let mut statement = db.conn.prepare("SELECT source_id FROM gaiadr3").map_err(|e| db.error(e))?;
+        let mut rows = statement.query([]).map_err(|e| db.error(e))?;
+
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().map_err(|e| db.error(e))? {
+            let id = match row.get_ref(0).map_err(|e| db.error(e))? {
+                ValueRef::Text(text) => std::str::from_utf8(text).ok().and_then(|t| t.parse().ok()),
+                ValueRef::Integer(value) => u64::try_from(value).ok(),
+                _ => None,
+            };
+            ids.extend(id);
+        }
+
+        Ok(Self::from_ids(ids))
+    }
+
+    pub fn from_ids(mut ids: Vec<u64>) -> Self {
+        ids.sort_unstable();
+        ids.dedup();
+        ids.shrink_to_fit();
+        Self { ids }
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.ids.binary_search(&id).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture_path;
+
+    #[test]
+    fn test_load() {
+        let path = fixture_path("idset.db");
+        let _ = std::fs::remove_file(&path);
+        let db = Database::open(path.to_str().unwrap()).unwrap();
+        db.create_gaiadr3(&["source_id".to_string(), "ra".to_string()]).unwrap();
+        db.conn
+            .execute_batch(
+                "INSERT INTO gaiadr3 VALUES ('34361129088', 1.0), ('4295806720', 2.0), ('not-an-id', 3.0);",
+            )
+            .unwrap();
+
+        let ids = SourceIdSet::load(&db).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(4295806720));
+        assert!(ids.contains(34361129088));
+        assert!(!ids.contains(38655544960));
+    }
+}
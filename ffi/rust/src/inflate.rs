@@ -0,0 +1,491 @@
+//! Deflate decoding that can start at a block boundary without knowing the
+//! preceding 32 KiB window
+//!
+//! Output is kept as `u16` symbols. Bytes are stored as is, and bytes copied
+//! from the unknown window are stored as markers `MARKER + i`, where `i`
+//! indexes the window. The markers are replaced once the window is known
+//! (see `Inflated::resolve`). This is how rapidgzip decompresses a single
+//! gzip member on several threads: each thread guesses a block boundary in
+//! its share of the input and decodes from there.
+
+use std::sync::OnceLock;
+
+pub(crate) const WINDOW_SIZE: usize = 32 * 1024;
+
+const MARKER: u16 = 0x8000;
+
+// Codes up to this length are decoded with a single table lookup
+const FAST_BITS: u32 = 10;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+// Order in which a dynamic block header lists the code length code lengths
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Why a deflate stream could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InflateError {
+    /// The input ends partway through a block
+    Truncated,
+    /// The data is not valid deflate
+    Invalid(&'static str),
+}
+
+type Result<T> = std::result::Result<T, InflateError>;
+
+/// What precedes the first block being decoded
+pub(crate) enum Window<'a> {
+    /// The bytes output so far (only the last `WINDOW_SIZE` are used)
+    Known(&'a [u8]),
+    /// Decoding starts at a guessed boundary partway through the stream
+    Unknown,
+}
+
+/// Blocks decoded from bit offset `start` up to bit offset `end`
+pub(crate) struct Inflated {
+    pub start: u64,
+    pub end: u64,
+    /// The last block decoded has the final-block bit set
+    pub last_block: bool,
+    // The window the blocks were decoded against, then their output
+    symbols: Vec<u16>,
+    window_len: usize,
+    has_markers: bool,
+}
+
+impl Inflated {
+    /// Append the decoded bytes to `out`, taking bytes copied from the
+    /// window from `window`, the output preceding `start`
+    pub fn resolve(&self, window: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        let output = &self.symbols[self.window_len..];
+        out.reserve(output.len());
+
+        if !self.has_markers {
+            out.extend(output.iter().map(|&symbol| symbol as u8));
+            return Ok(());
+        }
+
+        let window = &window[window.len().saturating_sub(WINDOW_SIZE)..];
+        // Markers below this point refer to bytes before the stream started
+        let missing = WINDOW_SIZE - window.len();
+        for &symbol in output {
+            if symbol < 256 {
+                out.push(symbol as u8);
+            } else {
+                let byte = ((symbol - MARKER) as usize)
+                    .checked_sub(missing)
+                    .map(|index| window[index])
+                    .ok_or(InflateError::Invalid("distance too far back"))?;
+                out.push(byte);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode whole blocks starting at bit offset `start` until one ends at or
+/// after bit offset `stop`, or the final block has been decoded
+pub(crate) fn inflate_blocks(data: &[u8], start: u64, window: Window, stop: u64) -> Result<Inflated> {
+    let mut symbols = Vec::with_capacity(WINDOW_SIZE * 4);
+    let has_markers = match window {
+        Window::Known(bytes) => {
+            symbols.extend(bytes[bytes.len().saturating_sub(WINDOW_SIZE)..].iter().map(|&b| b as u16));
+            false
+        }
+        Window::Unknown => {
+            symbols.extend(MARKER..=u16::MAX);
+            true
+        }
+    };
+    let window_len = symbols.len();
+
+    let mut bits = Bits { data, pos: start };
+    let mut last_block = false;
+    while bits.pos < stop && !last_block {
+        last_block = inflate_block(&mut bits, &mut symbols)?;
+    }
+
+    Ok(Inflated {
+        start,
+        end: bits.pos,
+        last_block,
+        symbols,
+        window_len,
+        has_markers,
+    })
+}
+
+/// Find the first bit offset in `from..stop` where a plausible non-final
+/// dynamic Huffman block header starts
+///
+/// Most candidates that are not real block boundaries fail to parse as a
+/// header; the rest are weeded out by decoding from them.
+pub(crate) fn find_block(data: &[u8], from: u64, stop: u64) -> Option<u64> {
+    let mut bits = Bits { data, pos: from };
+
+    (from..stop.min(data.len() as u64 * 8)).find(|&pos| {
+        bits.pos = pos;
+        // BFINAL = 0 and BTYPE = 2, read least significant bit first
+        if bits.peek(3) != 0b100 {
+            return false;
+        }
+        bits.consume(3);
+        read_dynamic_codes(&mut bits).is_ok()
+    })
+}
+
+/// Decode one block, returning whether it was the final block
+fn inflate_block(bits: &mut Bits, out: &mut Vec<u16>) -> Result<bool> {
+    let last_block = bits.take(1) == 1;
+
+    match bits.take(2) {
+        0 => inflate_stored(bits, out)?,
+        1 => {
+            let (litlen, dist) = fixed_codes();
+            inflate_codes(bits, out, litlen, dist)?;
+        }
+        2 => {
+            let (litlen, dist) = read_dynamic_codes(bits)?;
+            inflate_codes(bits, out, &litlen, &dist)?;
+        }
+        _ => return Err(InflateError::Invalid("invalid block type")),
+    }
+
+    Ok(last_block)
+}
+
+fn inflate_stored(bits: &mut Bits, out: &mut Vec<u16>) -> Result<()> {
+    bits.pos = bits.pos.div_ceil(8) * 8;
+    let (len, nlen) = (bits.take(16), bits.take(16));
+    if len != !nlen & 0xffff {
+        return Err(InflateError::Invalid("stored block length does not match its complement"));
+    }
+
+    let start = (bits.pos / 8) as usize;
+    let bytes = bits.data.get(start..start + len as usize).ok_or(InflateError::Truncated)?;
+    out.extend(bytes.iter().map(|&b| b as u16));
+    bits.pos += len as u64 * 8;
+    Ok(())
+}
+
+fn inflate_codes(bits: &mut Bits, out: &mut Vec<u16>, litlen: &Code, dist: &Code) -> Result<()> {
+    let end = bits.data.len() as u64 * 8;
+
+    loop {
+        // Past the end, the zero padding would keep decoding as symbols
+        if bits.pos > end {
+            return Err(InflateError::Truncated);
+        }
+
+        let symbol = litlen.decode(bits)?;
+        if symbol < 256 {
+            out.push(symbol);
+            continue;
+        }
+        if symbol == 256 {
+            return bits.check();
+        }
+
+        let symbol = (symbol - 257) as usize;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(InflateError::Invalid("invalid length symbol"));
+        }
+        let len = LENGTH_BASE[symbol] as usize + bits.take(LENGTH_EXTRA[symbol] as u32) as usize;
+
+        let symbol = dist.decode(bits)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(InflateError::Invalid("invalid distance symbol"));
+        }
+        let distance = DIST_BASE[symbol] as usize + bits.take(DIST_EXTRA[symbol] as u32) as usize;
+        if distance > out.len() {
+            return Err(InflateError::Invalid("distance too far back"));
+        }
+
+        let from = out.len() - distance;
+        if distance >= len {
+            out.extend_from_within(from..from + len);
+        } else {
+            // Overlapping copy, e.g. a run of one repeated byte
+            for index in from..from + len {
+                out.push(out[index]);
+            }
+        }
+    }
+}
+
+/// Read the code lengths at the start of a dynamic Huffman block
+fn read_dynamic_codes(bits: &mut Bits) -> Result<(Code, Code)> {
+    let litlen_count = bits.take(5) as usize + 257;
+    let dist_count = bits.take(5) as usize + 1;
+    let code_length_count = bits.take(4) as usize + 4;
+    if litlen_count > 286 || dist_count > 30 {
+        return Err(InflateError::Invalid("too many length or distance symbols"));
+    }
+
+    let mut lengths = [0u8; 286 + 30];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        lengths[symbol] = bits.take(3) as u8;
+    }
+    let code_lengths = Code::new(&lengths[..19], true)?;
+
+    let total = litlen_count + dist_count;
+    lengths[..19].fill(0);
+    let mut index = 0;
+    while index < total {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 if index == 0 => return Err(InflateError::Invalid("repeated length with no first length")),
+            16 => (lengths[index - 1], 3 + bits.take(2) as usize),
+            17 => (0, 3 + bits.take(3) as usize),
+            _ => (0, 11 + bits.take(7) as usize),
+        };
+        if index + repeat > total {
+            return Err(InflateError::Invalid("too many code lengths"));
+        }
+        lengths[index..index + repeat].fill(length);
+        index += repeat;
+    }
+    bits.check()?;
+
+    if lengths[256] == 0 {
+        return Err(InflateError::Invalid("missing end-of-block code"));
+    }
+
+    Ok((
+        Code::new(&lengths[..litlen_count], false)?,
+        Code::new(&lengths[litlen_count..total], false)?,
+    ))
+}
+
+fn fixed_codes() -> (&'static Code, &'static Code) {
+    static CODES: OnceLock<(Code, Code)> = OnceLock::new();
+
+    let (litlen, dist) = CODES.get_or_init(|| {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        // Symbols 286, 287 and distances 30, 31 have codes but are invalid
+        let litlen = Code::new(&lengths, true).unwrap();
+        let dist = Code::new(&[5; 32], true).unwrap();
+        (litlen, dist)
+    });
+    (litlen, dist)
+}
+
+/// A canonical Huffman code
+struct Code {
+    // Number of codes of each length, and symbols ordered by code
+    count: [u16; 16],
+    symbol: [u16; 288],
+    // Indexed by the next FAST_BITS input bits: `symbol << 4 | length`, or
+    // 0 if the code is longer than FAST_BITS
+    fast: [u16; 1 << FAST_BITS],
+}
+
+impl Code {
+    /// Build a code from the length of each symbol's code (0 if unused)
+    ///
+    /// Like zlib, an incomplete code is only accepted if it has a single
+    /// one-bit code, unless `strict` is set.
+    fn new(lengths: &[u8], strict: bool) -> Result<Self> {
+        let mut count = [0u16; 16];
+        for &length in lengths {
+            count[length as usize] += 1;
+        }
+        count[0] = 0;
+
+        let mut left = 1i32;
+        for &length_count in &count[1..] {
+            left = (left << 1) - length_count as i32;
+            if left < 0 {
+                return Err(InflateError::Invalid("over-subscribed code"));
+            }
+        }
+        let max_length = count.iter().rposition(|&c| c > 0).unwrap_or(0);
+        if left > 0 && (strict || max_length > 1) {
+            return Err(InflateError::Invalid("incomplete code"));
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + count[length];
+        }
+        let mut symbol = [0u16; 288];
+        let mut next_code = [0u32; 16];
+        let mut code = 0;
+        for length in 1..16 {
+            code = (code + count[length - 1] as u32) << 1;
+            next_code[length] = code;
+        }
+
+        let mut fast = [0u16; 1 << FAST_BITS];
+        for (value, &length) in lengths.iter().enumerate() {
+            let length = length as usize;
+            if length == 0 {
+                continue;
+            }
+            symbol[offsets[length] as usize] = value as u16;
+            offsets[length] += 1;
+
+            let code = next_code[length];
+            next_code[length] += 1;
+            if length as u32 <= FAST_BITS {
+                // Codes are packed starting from their most significant bit
+                let reversed = code.reverse_bits() >> (32 - length);
+                let entry = (value as u16) << 4 | length as u16;
+                for index in (reversed as usize..fast.len()).step_by(1 << length) {
+                    fast[index] = entry;
+                }
+            }
+        }
+
+        Ok(Self { count, symbol, fast })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let entry = self.fast[bits.peek(FAST_BITS) as usize];
+        if entry != 0 {
+            bits.consume((entry & 0xf) as u32);
+            return Ok(entry >> 4);
+        }
+
+        // One bit at a time, as in zlib's puff.c
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.count[1..] {
+            code |= bits.take(1) as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Invalid("invalid Huffman code"))
+    }
+}
+
+/// Least significant bit first reader over a byte slice
+///
+/// Reading past the end yields zero bits; `check` tells whether that has
+/// happened.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: u64,
+}
+
+impl Bits<'_> {
+    fn peek(&self, count: u32) -> u32 {
+        let byte = (self.pos / 8) as usize;
+        let word = match self.data.get(byte..byte + 8) {
+            Some(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+            None => {
+                let mut bytes = [0u8; 8];
+                let rest = self.data.get(byte..).unwrap_or_default();
+                bytes[..rest.len()].copy_from_slice(rest);
+                u64::from_le_bytes(bytes)
+            }
+        };
+        ((word >> (self.pos % 8)) & ((1 << count) - 1)) as u32
+    }
+
+    fn consume(&mut self, count: u32) {
+        self.pos += count as u64;
+    }
+
+    fn take(&mut self, count: u32) -> u32 {
+        let value = self.peek(count);
+        self.consume(count);
+        value
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.pos > self.data.len() as u64 * 8 {
+            Err(InflateError::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use crate::test_support::sample_rows;
+
+    fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(level));
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn inflate_all(compressed: &[u8]) -> Result<Vec<u8>> {
+        let inflated = inflate_blocks(compressed, 0, Window::Known(&[]), u64::MAX)?;
+        assert!(inflated.last_block);
+        let mut out = Vec::new();
+        inflated.resolve(&[], &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_inflate_levels() {
+        let data = sample_rows(20_000);
+        for level in [0, 1, 6, 9] {
+            assert!(inflate_all(&deflate(&data, level)).unwrap() == data, "level {}", level);
+        }
+        // Short inputs are written as fixed Huffman blocks
+        assert_eq!(inflate_all(&deflate(b"source_id,ra\n", 6)).unwrap(), b"source_id,ra\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let compressed = deflate(&sample_rows(1_000), 6);
+        let err = inflate_all(&compressed[..compressed.len() / 2]).err().unwrap();
+        assert_eq!(err, InflateError::Truncated);
+
+        // BTYPE = 3 is reserved
+        assert!(matches!(inflate_all(&[0b111]), Err(InflateError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_decode_from_found_block() {
+        let data = sample_rows(20_000);
+        let compressed = deflate(&data, 6);
+
+        // Every block boundary after the first, from decoding the whole stream
+        let mut boundaries = Vec::new();
+        let mut bits = Bits { data: &compressed, pos: 0 };
+        let mut symbols = Vec::new();
+        let mut lengths = Vec::new();
+        while !inflate_block(&mut bits, &mut symbols).unwrap() {
+            boundaries.push(bits.pos);
+            lengths.push(symbols.len());
+        }
+        assert!(boundaries.len() > 2);
+
+        let (boundary, offset) = (boundaries[1], lengths[1]);
+        assert_eq!(find_block(&compressed, boundaries[0] + 1, u64::MAX), Some(boundary));
+
+        let inflated = inflate_blocks(&compressed, boundary, Window::Unknown, u64::MAX).unwrap();
+        let mut out = Vec::new();
+        inflated.resolve(&data[..offset], &mut out).unwrap();
+        assert!(out == data[offset..]);
+    }
+}
@@ -0,0 +1,484 @@
+use rusqlite::params;
+use crate::columnar::Column;
+use crate::db::{create_profile_table, insert_batch, insert_sql, quote_identifier, sql_value, Database};
+use crate::error::{ErrorCode, ParseError};
+use crate::idset::SourceIdSet;
+use crate::options::ReaderOptions;
+use crate::reader::GaiaCsvReader;
+use crate::schema::FileFormat;
+use crate::tables::TableProfile;
+use crate::tmass::{TmassOptions, TmassXmatchIndex};
+use crate::tracking::TrackingTable;
+use crate::xmatch::{XmatchOptions, XmatchReader};
+
+/// Rows parsed at a time when the caller passes 0
+pub const DEFAULT_INGEST_BATCH_SIZE: usize = 50_000;
+
+/// Row counts from ingesting one file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IngestCounts {
+    /// Rows that passed the file's filters and were written
+    pub rows: u64,
+    /// Rows that were new to the table; the rest were ignored as duplicates
+    pub inserted: u64,
+}
+
+/// Parse a Gaia file into the local table of its profile in a SQLite
+/// database: `gaiadr3` for `gaia_source` files (or if `options.table` is
+/// unset), and the profile's own table otherwise
+///
+/// `gaiadr3` is created with the same schema as `GaiaDatabase.initialize()`
+/// if it does not exist yet, using `options.columns`, and columns other than
+/// those the database was populated with are refused with `SettingsMismatch`.
+/// Other profiles' tables are keyed on `source_id`, which must be kept, and
+/// gain any kept column they lack (see `create_profile_table`). Rows are
+/// parsed `batch_size` at a time and inserted with `INSERT OR IGNORE`, all
+/// in one transaction. When `url` is given, that transaction also marks the
+/// file completed in the profile's tracking table with its row counts, so a
+/// file is either fully ingested and completed or not ingested at all; a
+/// file that fails is marked failed.
+pub fn ingest_gaia_csv(
+    db_path: &str,
+    file_path: &str,
+    url: Option<&str>,
+    options: &ReaderOptions,
+    batch_size: usize,
+) -> Result<IngestCounts, ParseError> {
+    let batch_size = if batch_size == 0 { DEFAULT_INGEST_BATCH_SIZE } else { batch_size };
+    let profile = options.table.unwrap_or(TableProfile::GaiaSource);
+
+    let mut db = Database::open(db_path)?;
+    if profile == TableProfile::GaiaSource {
+        db.create_gaiadr3(&options.columns)?;
+    }
+
+    db.ingest_file(TrackingTable::for_profile(profile), url, |tx| {
+        let mut reader = GaiaCsvReader::open(file_path, options)?;
+        if profile != TableProfile::GaiaSource {
+            let columns = reader.columns();
+            if !columns.iter().any(|(name, _)| *name == profile.key_column()) {
+                return Err(ParseError::new(
+                    ErrorCode::InvalidArgument,
+                    format!(
+                        "{}: {} rows need their {} column to join to gaiadr3",
+                        file_path,
+                        profile.name(),
+                        profile.key_column()
+                    ),
+                ));
+            }
+            create_profile_table(tx, profile, &columns).map_err(|e| ParseError::from_sqlite(e, db_path))?;
+        }
+        let mut counts = IngestCounts::default();
+
+        loop {
+            let batch = reader.next_columns(batch_size)?;
+            if batch.len == 0 {
+                return Ok(counts);
+            }
+
+            let inserted = tx
+                .prepare_cached(&insert_sql(profile.local_table(), &batch))
+                .and_then(|mut statement| insert_batch(&mut statement, &batch))
+                .map_err(|e| ParseError::from_sqlite(e, db_path))?;
+
+            counts.rows += batch.len as u64;
+            counts.inserted += inserted as u64;
+        }
+    })
+}
+
+/// Write the pairs of a `*_best_neighbour` file whose Gaia source is in
+/// `ids` to `tmass_xmatch`
+///
+/// `ids` is loaded once with `SourceIdSet::load` and shared by every file,
+/// so each pair is checked with an in-memory lookup rather than a query.
+/// Pairs are read `batch_size` at a time (50,000 if 0) and inserted with
+/// `INSERT OR IGNORE` in one transaction, which also marks `url` completed
+/// in `file_tracking_tmass_xmatch` as for `ingest_gaia_csv`.
+pub fn ingest_tmass_xmatch(
+    db_path: &str,
+    file_path: &str,
+    url: Option<&str>,
+    ids: &SourceIdSet,
+    options: &XmatchOptions,
+    batch_size: usize,
+) -> Result<IngestCounts, ParseError> {
+    let batch_size = if batch_size == 0 { DEFAULT_INGEST_BATCH_SIZE } else { batch_size };
+
+    let mut db = Database::open(db_path)?;
+    db.create_tmass_xmatch()?;
+
+    db.ingest_file(TrackingTable::TmassXmatch, url, |tx| {
+        let mut reader = XmatchReader::open(file_path, options)?;
+        let mut statement = tx
+            .prepare("INSERT OR IGNORE INTO tmass_xmatch (gaiadr3_source_id, tmass_source_id) VALUES (?, ?)")
+            .map_err(|e| ParseError::from_sqlite(e, db_path))?;
+        let mut counts = IngestCounts::default();
+
+        loop {
+            let batch = reader.next_pairs(batch_size)?;
+            if batch.is_empty() {
+                return Ok(counts);
+            }
+
+            for (index, &source_id) in batch.source_ids.iter().enumerate() {
+                if !ids.contains(source_id) {
+                    continue;
+                }
+
+                let inserted = statement
+                    .execute(params![source_id.to_string(), batch.designation(index)])
+                    .map_err(|e| ParseError::from_sqlite(e, db_path))?;
+                counts.rows += 1;
+                counts.inserted += inserted as u64;
+            }
+        }
+    })
+}
+
+/// Write a row to `tmass` for every Gaia source in `index` matched to a
+/// star of a 2MASS PSC file (`psc_*.gz`)
+///
+/// `index` is loaded once with `TmassXmatchIndex::load` and shared by every
+/// file, so the file is joined in a single pass with in-memory lookups.
+/// Rows are read `batch_size` at a time (50,000 if 0) and inserted with
+/// `INSERT OR IGNORE` in one transaction, which also marks `url` completed
+/// in `file_tracking_tmass` as for `ingest_gaia_csv`. The columns for the
+/// optional fields in `options` are added to `tmass` if it lacks them.
+pub fn ingest_tmass_psc(
+    db_path: &str,
+    file_path: &str,
+    url: Option<&str>,
+    index: &TmassXmatchIndex,
+    options: &TmassOptions,
+    batch_size: usize,
+) -> Result<IngestCounts, ParseError> {
+    let batch_size = if batch_size == 0 { DEFAULT_INGEST_BATCH_SIZE } else { batch_size };
+
+    let mut db = Database::open(db_path)?;
+    db.create_tmass(options)?;
+
+    let stored = options.columns();
+    let mut columns = vec!["designation".to_string()];
+    columns.extend(stored.iter().map(|column| column.to_string()));
+    let reader_options = ReaderOptions {
+        format: FileFormat::TmassPsc,
+        columns,
+        strict_columns: true,
+        ..ReaderOptions::default()
+    };
+
+    let names: Vec<String> = stored.iter().map(|column| quote_identifier(column)).collect();
+    let sql = format!(
+        "INSERT OR IGNORE INTO tmass (gaiadr3_source_id, tmass_source_id, {}) VALUES (?, ?{})",
+        names.join(", "),
+        ", ?".repeat(names.len())
+    );
+
+    db.ingest_file(TrackingTable::Tmass, url, |tx| {
+        let mut reader = GaiaCsvReader::open(file_path, &reader_options)?;
+        let mut statement = tx.prepare(&sql).map_err(|e| ParseError::from_sqlite(e, db_path))?;
+        let mut counts = IngestCounts::default();
+
+        loop {
+            let batch = reader.next_columns(batch_size)?;
+            if batch.len == 0 {
+                return Ok(counts);
+            }
+
+            let (designations, values) = batch.columns.split_first().expect("designation is requested");
+            for row in 0..batch.len {
+                let Some(designation) = designations.utf8_value(row) else {
+                    continue;
+                };
+
+                for source_id in index.source_ids(designation) {
+                    let inserted = bind_tmass_row(&mut statement, source_id, designations, values, row)
+                        .and_then(|()| statement.raw_execute())
+                        .map_err(|e| ParseError::from_sqlite(e, db_path))?;
+                    counts.rows += 1;
+                    counts.inserted += inserted as u64;
+                }
+            }
+        }
+    })
+}
+
+fn bind_tmass_row(
+    statement: &mut rusqlite::Statement,
+    source_id: u64,
+    designations: &Column,
+    values: &[Column],
+    row: usize,
+) -> rusqlite::Result<()> {
+    statement.raw_bind_parameter(1, source_id.to_string())?;
+    statement.raw_bind_parameter(2, sql_value(designations, row))?;
+    for (index, column) in values.iter().enumerate() {
+        statement.raw_bind_parameter(index + 3, sql_value(column, row))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use crate::filter::{Band, MagnitudeCut};
+    use crate::test_support::{
+        fixture_path, write_gz_fixture, SAMPLE_BEST_NEIGHBOUR, SAMPLE_CSV, SAMPLE_ECSV, SAMPLE_TMASS_PSC,
+    };
+
+    fn stored_options() -> ReaderOptions {
+        let columns = ["source_id", "ra", "dec", "phot_g_mean_flux"];
+        ReaderOptions::with_columns(&columns.map(str::to_string))
+    }
+
+    #[test]
+    fn test_ingest_gaia_csv() {
+        let csv = write_gz_fixture("ingest_sample.csv.gz", SAMPLE_CSV);
+        let db_path = fixture_path("ingest_sample.db");
+        let _ = std::fs::remove_file(&db_path);
+        let (csv, db_path) = (csv.to_str().unwrap(), db_path.to_str().unwrap());
+
+        let counts = ingest_gaia_csv(db_path, csv, None, &stored_options(), 2).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 3, inserted: 3 });
+
+        let conn = Connection::open(db_path).unwrap();
+        let rows: Vec<(String, f64, Option<f64>)> = conn
+            .prepare("SELECT source_id, ra, phot_g_mean_flux FROM gaiadr3 ORDER BY ra")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows[0], ("4295806720".to_string(), 44.99615537864534, Some(12345.6789)));
+        assert_eq!(rows[1], ("34361129088".to_string(), 45.00432028915398, None));
+
+        let source_id_type: String = conn
+            .query_row("SELECT typeof(source_id) FROM gaiadr3 LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(source_id_type, "text");
+
+        // Ingesting the same file again only finds duplicates
+        let counts = ingest_gaia_csv(db_path, csv, None, &stored_options(), 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 3, inserted: 0 });
+
+        // Other columns cannot be appended to the table
+        let other = ReaderOptions::with_columns(&["source_id".to_string(), "ra".to_string()]);
+        let err = ingest_gaia_csv(db_path, csv, None, &other, 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::SettingsMismatch);
+    }
+
+    #[test]
+    fn test_ingest_tracked_file() {
+        let good = write_gz_fixture("ingest_tracked.csv.gz", SAMPLE_CSV);
+        let ragged = write_gz_fixture(
+            "ingest_ragged.csv.gz",
+            "source_id,ra,dec,phot_g_mean_flux\n1,2.0,3.0,4.0\n5,6.0\n",
+        );
+        let db_path = fixture_path("ingest_tracked.db");
+        let _ = std::fs::remove_file(&db_path);
+        let db_path = db_path.to_str().unwrap();
+
+        let counts = ingest_gaia_csv(db_path, good.to_str().unwrap(), Some("good"), &stored_options(), 1).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 3, inserted: 3 });
+
+        let err = ingest_gaia_csv(db_path, ragged.to_str().unwrap(), Some("ragged"), &stored_options(), 1).unwrap_err();
+        assert_eq!(err.code, ErrorCode::CsvSyntax);
+
+        // The ragged file's first row was rolled back along with it
+        let conn = Connection::open(db_path).unwrap();
+        let total: i64 = conn.query_row("SELECT COUNT(*) FROM gaiadr3", [], |row| row.get(0)).unwrap();
+        assert_eq!(total, 3);
+
+        let tracked: Vec<(String, String, Option<i64>)> = conn
+            .prepare("SELECT url, status, row_count FROM file_tracking_gaiadr3 ORDER BY url")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tracked, [("good".to_string(), "completed".to_string(), Some(3))]);
+    }
+
+    #[test]
+    fn test_ingest_table_profile() {
+        let gaia = write_gz_fixture("ingest_profile_gaia.csv.gz", SAMPLE_CSV);
+        let params = write_gz_fixture("ingest_profile_ap.csv.gz", SAMPLE_ECSV);
+        let db_path = fixture_path("ingest_profile.db");
+        let _ = std::fs::remove_file(&db_path);
+        let (params, db_path) = (params.to_str().unwrap(), db_path.to_str().unwrap());
+
+        ingest_gaia_csv(db_path, gaia.to_str().unwrap(), None, &stored_options(), 0).unwrap();
+        let mut options = ReaderOptions {
+            table: Some(TableProfile::AstrophysicalParameters),
+            ..ReaderOptions::with_columns(&["source_id", "classprob_dsc_combmod_star"].map(str::to_string))
+        };
+        let counts = ingest_gaia_csv(db_path, params, Some("ap"), &options, 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 2, inserted: 2 });
+
+        // Joins to gaiadr3 on the text source_id
+        let conn = Connection::open(db_path).unwrap();
+        let joined: (f64, f64) = conn
+            .query_row(
+                "SELECT g.ra, ap.classprob_dsc_combmod_star FROM gaiadr3 g \
+                 JOIN astrophysical_parameters ap ON ap.source_id = g.source_id \
+                 WHERE ap.classprob_dsc_combmod_star IS NOT NULL",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(joined.0, 44.99615537864534);
+        assert!((joined.1 - 0.99).abs() < 1e-6);
+
+        let status: String = conn
+            .query_row(
+                "SELECT status FROM file_tracking_astrophysical_parameters WHERE url = 'ap'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(status, "completed");
+
+        // Kept columns the table lacks are added, typed as read
+        options.columns.push("spectraltype_esphs".to_string());
+        let counts = ingest_gaia_csv(db_path, params, None, &options, 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 2, inserted: 0 });
+        let column_type: String = conn
+            .query_row(
+                "SELECT type FROM pragma_table_info('astrophysical_parameters') WHERE name = 'spectraltype_esphs'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(column_type, "TEXT");
+
+        // Rows that cannot be joined are refused
+        options.columns = vec!["classprob_dsc_combmod_star".to_string()];
+        let err = ingest_gaia_csv(db_path, params, None, &options, 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn test_ingest_magnitude_cut() {
+        let csv = write_gz_fixture("ingest_cut.csv.gz", SAMPLE_CSV);
+        let db_path = fixture_path("ingest_cut.db");
+        let _ = std::fs::remove_file(&db_path);
+
+        let mut options = stored_options();
+        // G = 25.6874 - 2.5 log10(flux): only the brightest row is under 12
+        options.magnitude_cut = Some(MagnitudeCut {
+            band: Band::G,
+            limit: 12.0,
+            zeropoint: 25.6874,
+        });
+
+        let counts = ingest_gaia_csv(db_path.to_str().unwrap(), csv.to_str().unwrap(), None, &options, 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 1, inserted: 1 });
+    }
+
+    #[test]
+    fn test_ingest_tmass_xmatch() {
+        let gaia = write_gz_fixture("ingest_xmatch_gaia.csv.gz", SAMPLE_CSV);
+        let xmatch = write_gz_fixture("ingest_xmatch.csv.gz", SAMPLE_BEST_NEIGHBOUR);
+        let db_path = fixture_path("ingest_xmatch.db");
+        let _ = std::fs::remove_file(&db_path);
+        let (xmatch, db_path) = (xmatch.to_str().unwrap(), db_path.to_str().unwrap());
+
+        ingest_gaia_csv(db_path, gaia.to_str().unwrap(), None, &stored_options(), 0).unwrap();
+        let ids = SourceIdSet::load(&Database::open(db_path).unwrap()).unwrap();
+        assert_eq!(ids.len(), 3);
+
+        // Only 4295806720 is in gaiadr3 and has a designation
+        let counts =
+            ingest_tmass_xmatch(db_path, xmatch, Some("xmatch"), &ids, &XmatchOptions::default(), 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 1, inserted: 1 });
+
+        let conn = Connection::open(db_path).unwrap();
+        let pair: (String, String) = conn
+            .query_row("SELECT gaiadr3_source_id, tmass_source_id FROM tmass_xmatch", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(pair, ("4295806720".to_string(), "00000049-1733487".to_string()));
+
+        let status: String = conn
+            .query_row("SELECT status FROM file_tracking_tmass_xmatch WHERE url = 'xmatch'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(status, "completed");
+
+        let counts = ingest_tmass_xmatch(db_path, xmatch, None, &ids, &XmatchOptions::default(), 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 1, inserted: 0 });
+    }
+
+    #[test]
+    fn test_ingest_tmass_psc() {
+        let gaia = write_gz_fixture("ingest_psc_gaia.csv.gz", SAMPLE_CSV);
+        let xmatch = write_gz_fixture("ingest_psc_xmatch.csv.gz", SAMPLE_BEST_NEIGHBOUR);
+        let psc = write_gz_fixture("ingest_psc.gz", SAMPLE_TMASS_PSC);
+        let db_path = fixture_path("ingest_psc.db");
+        let _ = std::fs::remove_file(&db_path);
+        let (psc, db_path) = (psc.to_str().unwrap(), db_path.to_str().unwrap());
+
+        ingest_gaia_csv(db_path, gaia.to_str().unwrap(), None, &stored_options(), 0).unwrap();
+        let ids = SourceIdSet::load(&Database::open(db_path).unwrap()).unwrap();
+        ingest_tmass_xmatch(db_path, xmatch.to_str().unwrap(), None, &ids, &XmatchOptions::default(), 0).unwrap();
+
+        let index = TmassXmatchIndex::load(&Database::open(db_path).unwrap()).unwrap();
+        assert_eq!(index.len(), 1);
+
+        // Only 00000049-1733487 is matched to an ingested source
+        let counts = ingest_tmass_psc(db_path, psc, Some("psc"), &index, &TmassOptions::default(), 2).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 1, inserted: 1 });
+
+        let conn = Connection::open(db_path).unwrap();
+        let row: (String, String, f64, f64, f64) = conn
+            .query_row("SELECT gaiadr3_source_id, tmass_source_id, j_m, h_m, k_m FROM tmass", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+            })
+            .unwrap();
+        assert_eq!(row, ("4295806720".to_string(), "00000049-1733487".to_string(), 13.245, 12.831, 12.779));
+
+        let status: String = conn
+            .query_row("SELECT status FROM file_tracking_tmass WHERE url = 'psc'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(status, "completed");
+
+        // The optional columns are added to the existing table
+        let options = TmassOptions { with_errors: true, with_quality_flags: true };
+        conn.execute_batch("DELETE FROM tmass").unwrap();
+        let counts = ingest_tmass_psc(db_path, psc, None, &index, &options, 0).unwrap();
+        assert_eq!(counts, IngestCounts { rows: 1, inserted: 1 });
+
+        let row: (f64, String, String) = conn
+            .query_row("SELECT j_msigcom, ph_qual, cc_flg FROM tmass", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(row, (0.029, "AAA".to_string(), "000".to_string()));
+    }
+
+    #[test]
+    fn test_ingest_errors() {
+        let db_path = fixture_path("ingest_errors.db");
+        let db_path = db_path.to_str().unwrap();
+
+        let err = ingest_gaia_csv(db_path, "/nonexistent/ingest.csv.gz", None, &stored_options(), 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Io);
+
+        let err = ingest_gaia_csv(db_path, "/nonexistent/ingest.csv.gz", None, &ReaderOptions::default(), 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidArgument);
+
+        let gaia_source = ReaderOptions {
+            table: Some(TableProfile::GaiaSource),
+            ..stored_options()
+        };
+        let err = ingest_gaia_csv(db_path, "/nonexistent/ingest.csv.gz", None, &gaia_source, 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Io);
+
+        let err = ingest_gaia_csv("/nonexistent/dir/gaia.db", "unused.csv.gz", None, &stored_options(), 0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Database);
+        assert!(err.message.starts_with("/nonexistent/dir/gaia.db: "), "{}", err.message);
+    }
+}
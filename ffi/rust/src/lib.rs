@@ -1,13 +1,61 @@
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::BufReader;
 use std::os::raw::{c_char};
-use csv::ReaderBuilder;
-use flate2::read::GzDecoder;
 use serde_json::{json, Value};
 
+mod arrow;
+mod batch;
+mod codec;
+mod columnar;
+mod db;
+mod decoder;
+mod ecsv;
+mod error;
+mod expr;
+mod filter;
+mod gzip;
+mod idset;
+mod inflate;
+mod ingest;
+mod meta;
+mod options;
+mod pgzip;
+mod reader;
+mod records;
+mod schema;
+mod tables;
+mod tmass;
+mod tracking;
+#[cfg(test)]
+mod test_support;
+mod xmatch;
+
+pub use arrow::{ArrowArray, ArrowSchema};
+pub use batch::FileBatch;
+pub use columnar::{ColumnBatch, ColumnType};
+pub use db::Database;
+pub use decoder::StreamDecoder;
+pub use ecsv::{EcsvColumn, EcsvHeader};
+pub use error::{ErrorCode, ParseError};
+pub use filter::{Band, MagnitudeCut};
+pub use idset::SourceIdSet;
+pub use ingest::IngestCounts;
+pub use meta::{DatasetMeta, PARSER_VERSION, SCHEMA_VERSION};
+pub use options::ReaderOptions;
+pub use reader::GaiaCsvReader;
+pub use schema::{FileFormat, GaiaType};
+pub use tables::TableProfile;
+pub use tmass::{TmassOptions, TmassXmatchIndex};
+pub use tracking::{FileStatus, TrackingTable};
+pub use xmatch::{XmatchBatch, XmatchOptions, XmatchReader};
+
+
 /// Parse a gzipped CSV file and return JSON array as a string
 ///
+/// Like every function here that takes a file path, the file may also be
+/// plain, zstd, bzip2 or xz; the codec is detected from its magic bytes.
+/// 64-bit identifier columns are returned as strings. Returns null on
+/// failure; see `last_error_code` and `last_error_message`.
+///
 /// # Safety
 /// This function is unsafe because it:
 /// - Dereferences raw pointers
@@ -18,36 +66,951 @@ pub unsafe extern "C" fn parse_gzipped_csv(
     columns_json: *const c_char,
     chunk_size: usize,
 ) -> *mut c_char {
-    // Convert C strings to Rust strings
-    let file_path_str = match CStr::from_ptr(file_path).to_str() {
-        Ok(s) => s,
-        Err(_) => return std::ptr::null_mut(),
-    };
+    ffi_call(|| {
+        // Convert C strings to Rust strings
+        let file_path_str = str_arg(file_path, "file_path")?;
+        let options = legacy_options(parse_columns_arg(columns_json)?);
 
-    let columns_json_str = match CStr::from_ptr(columns_json).to_str() {
-        Ok(s) => s,
-        Err(_) => return std::ptr::null_mut(),
-    };
+        // Parse CSV file
+        let records = parse_csv_internal(file_path_str, &options, chunk_size)?;
+        into_json_c_string(&records)
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
 
-    // Parse columns array from JSON
-    let columns: Vec<String> = match serde_json::from_str(columns_json_str) {
-        Ok(cols) => cols,
-        Err(_) => return std::ptr::null_mut(),
-    };
+/// Open a CSV file for chunked reading
+///
+/// 64-bit identifier columns are returned as strings. Returns an opaque
+/// reader handle, or null if the file could not be opened.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will release the handle with `close_csv_reader`
+#[no_mangle]
+pub unsafe extern "C" fn open_gzipped_csv(
+    file_path: *const c_char,
+    columns_json: *const c_char,
+) -> *mut GaiaCsvReader {
+    ffi_call(|| {
+        let file_path_str = str_arg(file_path, "file_path")?;
+        let options = legacy_options(parse_columns_arg(columns_json)?);
+
+        let reader = GaiaCsvReader::open(file_path_str, &options)?;
+        Ok(Box::into_raw(Box::new(reader)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Open a CSV file for chunked reading with a JSON `ReaderOptions` object
+///
+/// Returns an opaque reader handle, or null if the file could not be opened.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will release the handle with `close_csv_reader`
+#[no_mangle]
+pub unsafe extern "C" fn open_csv_reader(
+    file_path: *const c_char,
+    options_json: *const c_char,
+) -> *mut GaiaCsvReader {
+    ffi_call(|| {
+        let file_path_str = str_arg(file_path, "file_path")?;
+        let options = options_arg(options_json)?;
+
+        let reader = GaiaCsvReader::open(file_path_str, &options)?;
+        Ok(Box::into_raw(Box::new(reader)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Describe the columns of a CSV file without reading any rows
+///
+/// Returns a JSON array of `{"name", "type", "datatype", "unit", "ucd",
+/// "description"}` objects in file order, or null on error. `type` is the
+/// name of the `ColumnType` the column is read as (e.g. `float64` or
+/// `float32[]`), taken from the file's ECSV header or else the `gaia_source`
+/// schema, and `null` for undeclared columns. The other fields come from the ECSV header and
+/// are `null` if it does not declare them.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will free the returned string
+#[no_mangle]
+pub unsafe extern "C" fn read_csv_header(file_path: *const c_char) -> *mut c_char {
+    ffi_call(|| {
+        let file_path_str = str_arg(file_path, "file_path")?;
+        let reader = GaiaCsvReader::open(file_path_str, &ReaderOptions::default())?;
+        into_json_c_string(&describe_columns(&reader))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Read the next chunk of at most `max_rows` records as a JSON array string
+///
+/// Returns `[]` once the file is exhausted, or null on error.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences a reader handle returned by `open_gzipped_csv`
+/// - Assumes the caller will free the returned string
+#[no_mangle]
+pub unsafe extern "C" fn read_csv_chunk(
+    reader: *mut GaiaCsvReader,
+    max_rows: usize,
+) -> *mut c_char {
+    ffi_call(|| {
+        let reader = handle_arg(reader)?;
+        let records = reader.next_chunk(max_rows)?;
+        into_json_c_string(&records)
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Read the next chunk of at most `max_rows` records as typed columns
+///
+/// Returns a batch with zero rows once the file is exhausted, or null on
+/// error. Column buffers can be wrapped without copying until the batch is
+/// released with `free_column_batch`.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences a reader handle returned by `open_gzipped_csv`
+/// - Assumes the caller will free the batch with `free_column_batch`
+#[no_mangle]
+pub unsafe extern "C" fn read_csv_columns(
+    reader: *mut GaiaCsvReader,
+    max_rows: usize,
+) -> *mut ColumnBatch {
+    ffi_call(|| {
+        let reader = handle_arg(reader)?;
+        let batch = reader.next_columns(max_rows)?;
+        Ok(Box::into_raw(Box::new(batch)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Number of rows in a column batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_len(batch: *const ColumnBatch) -> usize {
+    batch.as_ref().map_or(0, |b| b.len)
+}
+
+/// Number of columns in a column batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_num_columns(batch: *const ColumnBatch) -> usize {
+    batch.as_ref().map_or(0, |b| b.columns.len())
+}
+
+/// Name of a column, borrowed from the batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_column_name(
+    batch: *const ColumnBatch,
+    index: usize,
+) -> *const c_char {
+    column_arg(batch, index).map_or(std::ptr::null(), |c| c.name.as_ptr())
+}
+
+/// Type of a column (see `ColumnType`), or -1 if out of range
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_column_type(batch: *const ColumnBatch, index: usize) -> i32 {
+    column_arg(batch, index).map_or(-1, |c| c.column_type() as i32)
+}
+
+/// Pointer to a column's value buffer, borrowed from the batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_values(batch: *const ColumnBatch, index: usize) -> *const u8 {
+    column_arg(batch, index).map_or(std::ptr::null(), |c| c.values_ptr())
+}
+
+/// Size in bytes of a column's value buffer
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_values_len(batch: *const ColumnBatch, index: usize) -> usize {
+    column_arg(batch, index).map_or(0, |c| c.values_byte_len())
+}
+
+/// Pointer to the `len + 1` offsets of a UTF-8 or list column (null for
+/// other types)
+///
+/// UTF-8 offsets index the value bytes; list offsets index the elements.
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_offsets(batch: *const ColumnBatch, index: usize) -> *const i32 {
+    column_arg(batch, index).map_or(std::ptr::null(), |c| c.offsets_ptr())
+}
+
+/// Pointer to a column's LSB-first validity bitmap (`ceil(len / 8)` bytes)
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_validity(batch: *const ColumnBatch, index: usize) -> *const u8 {
+    column_arg(batch, index).map_or(std::ptr::null(), |c| c.validity.as_ptr())
+}
+
+/// Number of null values in a column
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_csv_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn column_batch_null_count(batch: *const ColumnBatch, index: usize) -> usize {
+    column_arg(batch, index).map_or(0, |c| c.null_count)
+}
+
+/// Free a batch returned by `read_csv_columns`
+///
+/// # Safety
+/// The batch and every buffer borrowed from it must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_column_batch(batch: *mut ColumnBatch) {
+    if !batch.is_null() {
+        drop(Box::from_raw(batch));
+    }
+}
+
+/// Parse several whole files on a pool of `threads` Rust threads
+///
+/// Takes a JSON array of paths and a JSON `ReaderOptions` object shared by
+/// every file; `threads` of 0 uses one thread per core. Each file gets its
+/// own result or error, read back with the `file_batch_*` functions. Bad
+/// options are reported as every file's error, so a caller on another
+/// thread never needs `last_error_message`. Returns null only if
+/// `paths_json` is not a JSON array of strings.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will free the result with `free_file_batch`
+#[no_mangle]
+pub unsafe extern "C" fn parse_csv_files(
+    paths_json: *const c_char,
+    options_json: *const c_char,
+    threads: usize,
+) -> *mut FileBatch {
+    ffi_call(|| {
+        let paths: Vec<String> = serde_json::from_str(str_arg(paths_json, "paths_json")?).map_err(|e| {
+            ParseError::new(
+                ErrorCode::InvalidArgument,
+                format!("paths_json must be a JSON array of strings: {}", e),
+            )
+        })?;
+
+        let batch = match options_arg(options_json) {
+            Ok(options) => batch::parse_files(&paths, &options, threads),
+            Err(err) => FileBatch::failed(paths.len(), err),
+        };
+        Ok(Box::into_raw(Box::new(batch)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Number of files in a file batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `parse_csv_files`.
+#[no_mangle]
+pub unsafe extern "C" fn file_batch_len(batch: *const FileBatch) -> usize {
+    batch.as_ref().map_or(0, |b| b.files.len())
+}
 
-    // Parse CSV file
-    match parse_csv_internal(file_path_str, &columns, chunk_size) {
-        Ok(json_str) => {
-            match CString::new(json_str) {
-                Ok(c_str) => c_str.into_raw(),
-                Err(_) => std::ptr::null_mut(),
-            }
+/// Error code for a file in a file batch, 0 if it parsed
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `parse_csv_files`.
+#[no_mangle]
+pub unsafe extern "C" fn file_batch_error_code(batch: *const FileBatch, index: usize) -> i32 {
+    match batch.as_ref().and_then(|b| b.files.get(index)) {
+        Some(Ok(_)) => ErrorCode::Ok as i32,
+        Some(Err(err)) => err.code as i32,
+        None => ErrorCode::InvalidArgument as i32,
+    }
+}
+
+/// Error message for a file in a file batch, or null if it parsed
+///
+/// # Safety
+/// Assumes the caller will free the returned string with `free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn file_batch_error_message(batch: *const FileBatch, index: usize) -> *mut c_char {
+    batch
+        .as_ref()
+        .and_then(|b| b.files.get(index))
+        .and_then(|file| file.as_ref().err())
+        .and_then(|e| CString::new(e.message.replace('\0', "")).ok())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Columns of a file that parsed, borrowed from the file batch, or null if
+/// it failed
+///
+/// The result is read with the `column_batch_*` functions but must not be
+/// passed to `free_column_batch`.
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `parse_csv_files`.
+#[no_mangle]
+pub unsafe extern "C" fn file_batch_columns(batch: *const FileBatch, index: usize) -> *const ColumnBatch {
+    batch
+        .as_ref()
+        .and_then(|b| b.files.get(index))
+        .and_then(|file| file.as_ref().ok())
+        .map_or(std::ptr::null(), |columns| columns as *const ColumnBatch)
+}
+
+/// Free a file batch returned by `parse_csv_files`
+///
+/// # Safety
+/// The batch and every column batch borrowed from it must not be used after
+/// this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_file_batch(batch: *mut FileBatch) {
+    if !batch.is_null() {
+        drop(Box::from_raw(batch));
+    }
+}
+
+/// Parse a Gaia file straight into a table of a SQLite database: `gaiadr3`,
+/// or the local table of the `table` profile in the options
+///
+/// Takes a JSON `ReaderOptions` object whose `columns` are the table's
+/// columns; `gaiadr3` is created as `GaiaDatabase.initialize()` would if it
+/// does not exist, and a profile's table is keyed on `source_id`. Rows are
+/// parsed `batch_size` at a time (50,000 if 0) and inserted with
+/// `INSERT OR IGNORE` in one transaction. Unless `url` is null, the same
+/// transaction marks the file completed in the profile's tracking table
+/// (`file_tracking_gaiadr3` for `gaia_source`), and a failure marks it
+/// failed. The number of rows read and of rows that were new to the table
+/// are written to `out_rows` and `out_inserted` unless they are null.
+/// Returns 0 on success or an error code.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Writes through `out_rows` and `out_inserted` if they are not null
+#[no_mangle]
+pub unsafe extern "C" fn ingest_gaia_csv(
+    db_path: *const c_char,
+    file_path: *const c_char,
+    url: *const c_char,
+    options_json: *const c_char,
+    batch_size: usize,
+    out_rows: *mut u64,
+    out_inserted: *mut u64,
+) -> i32 {
+    ffi_status(|| {
+        let db_path = str_arg(db_path, "db_path")?;
+        let file_path = str_arg(file_path, "file_path")?;
+        let url = optional_str_arg(url, "url")?;
+        let options = options_arg(options_json)?;
+
+        let counts = ingest::ingest_gaia_csv(db_path, file_path, url, &options, batch_size)?;
+        write_counts(counts, out_rows, out_inserted);
+        Ok(())
+    })
+}
+
+/// Load the `source_id` of every row in `gaiadr3` for `ingest_tmass_xmatch`
+///
+/// Returns null on error.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will free the set with `free_source_id_set`
+#[no_mangle]
+pub unsafe extern "C" fn load_gaia_source_ids(db_path: *const c_char) -> *mut SourceIdSet {
+    ffi_call(|| {
+        let db = Database::open(str_arg(db_path, "db_path")?)?;
+        Ok(Box::into_raw(Box::new(SourceIdSet::load(&db)?)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Number of IDs in a source ID set
+///
+/// # Safety
+/// The set must be null or a live pointer returned by `load_gaia_source_ids`.
+#[no_mangle]
+pub unsafe extern "C" fn source_id_set_len(ids: *const SourceIdSet) -> usize {
+    ids.as_ref().map_or(0, SourceIdSet::len)
+}
+
+/// Free a source ID set returned by `load_gaia_source_ids`
+///
+/// # Safety
+/// The set must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_source_id_set(ids: *mut SourceIdSet) {
+    if !ids.is_null() {
+        drop(Box::from_raw(ids));
+    }
+}
+
+/// Write the pairs of a Gaia `*_best_neighbour` file whose Gaia source is
+/// in `ids` straight into the `tmass_xmatch` table
+///
+/// Takes JSON `XmatchOptions`. Pairs are read `batch_size` at a time
+/// (50,000 if 0) and inserted with `INSERT OR IGNORE` in one transaction,
+/// which also marks `url` completed in `file_tracking_tmass_xmatch` unless
+/// it is null. Counts are written as for `ingest_gaia_csv`. Returns 0 on
+/// success or an error code.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers, including a set from `load_gaia_source_ids`
+/// - Writes through `out_rows` and `out_inserted` if they are not null
+#[no_mangle]
+pub unsafe extern "C" fn ingest_tmass_xmatch(
+    db_path: *const c_char,
+    file_path: *const c_char,
+    url: *const c_char,
+    ids: *const SourceIdSet,
+    options_json: *const c_char,
+    batch_size: usize,
+    out_rows: *mut u64,
+    out_inserted: *mut u64,
+) -> i32 {
+    ffi_status(|| {
+        let db_path = str_arg(db_path, "db_path")?;
+        let file_path = str_arg(file_path, "file_path")?;
+        let url = optional_str_arg(url, "url")?;
+        let ids = ids
+            .as_ref()
+            .ok_or_else(|| ParseError::new(ErrorCode::InvalidArgument, "ids is null"))?;
+        let options = XmatchOptions::from_json(str_arg(options_json, "options_json")?)?;
+
+        let counts = ingest::ingest_tmass_xmatch(db_path, file_path, url, ids, &options, batch_size)?;
+        write_counts(counts, out_rows, out_inserted);
+        Ok(())
+    })
+}
+
+/// Load the `tmass_xmatch` table as a designation index for
+/// `ingest_tmass_psc`
+///
+/// Returns null on error.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will free the index with `free_tmass_xmatch_index`
+#[no_mangle]
+pub unsafe extern "C" fn load_tmass_xmatch_index(db_path: *const c_char) -> *mut TmassXmatchIndex {
+    ffi_call(|| {
+        let db = Database::open(str_arg(db_path, "db_path")?)?;
+        Ok(Box::into_raw(Box::new(TmassXmatchIndex::load(&db)?)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Number of crossmatch pairs in a designation index
+///
+/// # Safety
+/// The index must be null or a live pointer returned by
+/// `load_tmass_xmatch_index`.
+#[no_mangle]
+pub unsafe extern "C" fn tmass_xmatch_index_len(index: *const TmassXmatchIndex) -> usize {
+    index.as_ref().map_or(0, TmassXmatchIndex::len)
+}
+
+/// Free a designation index returned by `load_tmass_xmatch_index`
+///
+/// # Safety
+/// The index must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_tmass_xmatch_index(index: *mut TmassXmatchIndex) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
+
+/// Write the photometry of every star in a 2MASS PSC file that is matched
+/// to a Gaia source in `index` straight into the `tmass` table
+///
+/// Takes JSON `TmassOptions`. Stars are read `batch_size` at a time (50,000
+/// if 0) and inserted with `INSERT OR IGNORE` in one transaction, which also
+/// marks `url` completed in `file_tracking_tmass` unless it is null. Counts
+/// are written as for `ingest_gaia_csv`. Returns 0 on success or an error
+/// code.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers, including an index from
+///   `load_tmass_xmatch_index`
+/// - Writes through `out_rows` and `out_inserted` if they are not null
+#[no_mangle]
+pub unsafe extern "C" fn ingest_tmass_psc(
+    db_path: *const c_char,
+    file_path: *const c_char,
+    url: *const c_char,
+    index: *const TmassXmatchIndex,
+    options_json: *const c_char,
+    batch_size: usize,
+    out_rows: *mut u64,
+    out_inserted: *mut u64,
+) -> i32 {
+    ffi_status(|| {
+        let db_path = str_arg(db_path, "db_path")?;
+        let file_path = str_arg(file_path, "file_path")?;
+        let url = optional_str_arg(url, "url")?;
+        let index = index
+            .as_ref()
+            .ok_or_else(|| ParseError::new(ErrorCode::InvalidArgument, "index is null"))?;
+        let options = TmassOptions::from_json(str_arg(options_json, "options_json")?)?;
+
+        let counts = ingest::ingest_tmass_psc(db_path, file_path, url, index, &options, batch_size)?;
+        write_counts(counts, out_rows, out_inserted);
+        Ok(())
+    })
+}
+
+/// Migrate a database and record the settings it is populated with in
+/// `gaiaoffline_meta`
+///
+/// Takes JSON `DatasetMeta`. Fails with `SettingsMismatch` if the database
+/// was populated with other settings, so an incompatible append is refused
+/// before anything is downloaded, or if a newer version of this library
+/// wrote it. Returns 0 on success or an error code.
+///
+/// # Safety
+/// This function is unsafe because it dereferences raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn init_database_meta(db_path: *const c_char, meta_json: *const c_char) -> i32 {
+    ffi_status(|| {
+        let db = Database::open(str_arg(db_path, "db_path")?)?;
+        db.init_meta(&DatasetMeta::from_json(str_arg(meta_json, "meta_json")?)?)
+    })
+}
+
+/// Read `gaiaoffline_meta` as a JSON object string
+///
+/// Holds `schema_version` (0 for a database never migrated),
+/// `parser_version`, `stored_columns`, `magnitude_limit`, `zeropoints` and
+/// `data_release`, each null if not recorded. Returns null on error.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will free the returned string
+#[no_mangle]
+pub unsafe extern "C" fn read_database_meta(db_path: *const c_char) -> *mut c_char {
+    ffi_call(|| {
+        let db = Database::open(str_arg(db_path, "db_path")?)?;
+        into_c_string(db.read_meta()?.to_string())
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Add files to a tracking table as pending
+///
+/// `table` is `file_tracking_gaiadr3`, `file_tracking_tmass_xmatch` or
+/// `file_tracking_tmass`, and `urls_json` a JSON array of URLs. Files
+/// already tracked keep their status unless `overwrite` is set. The table is
+/// created, or given its row count columns, if needed. Returns 0 on success
+/// or an error code.
+///
+/// # Safety
+/// This function is unsafe because it dereferences raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn init_file_tracking(
+    db_path: *const c_char,
+    table: *const c_char,
+    urls_json: *const c_char,
+    overwrite: bool,
+) -> i32 {
+    ffi_status(|| {
+        let mut db = Database::open(str_arg(db_path, "db_path")?)?;
+        let table = TrackingTable::from_name(str_arg(table, "table")?)?;
+        let urls: Vec<String> = serde_json::from_str(str_arg(urls_json, "urls_json")?).map_err(|e| {
+            ParseError::new(
+                ErrorCode::InvalidArgument,
+                format!("urls_json must be a JSON array of strings: {}", e),
+            )
+        })?;
+
+        db.initialize_tracking(table, &urls, overwrite)
+    })
+}
+
+/// Mark a file failed in a tracking table
+///
+/// Returns 0 on success or an error code.
+///
+/// # Safety
+/// This function is unsafe because it dereferences raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mark_file_failed(
+    db_path: *const c_char,
+    table: *const c_char,
+    url: *const c_char,
+) -> i32 {
+    ffi_status(|| {
+        let db = Database::open(str_arg(db_path, "db_path")?)?;
+        let table = TrackingTable::from_name(str_arg(table, "table")?)?;
+        db.mark_file_failed(table, str_arg(url, "url")?)
+    })
+}
+
+/// Parse a whole CSV file into an Arrow struct array
+///
+/// Takes a JSON `ReaderOptions` object. The selected columns are exported through the Arrow C Data Interface as
+/// the children of a struct array. Returns 0 on success or an error code.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will call the `release` callback of both outputs
+#[no_mangle]
+pub unsafe extern "C" fn parse_gzipped_csv_arrow(
+    file_path: *const c_char,
+    options_json: *const c_char,
+    out_schema: *mut ArrowSchema,
+    out_array: *mut ArrowArray,
+) -> i32 {
+    ffi_status(|| {
+        let file_path_str = str_arg(file_path, "file_path")?;
+        let options = options_arg(options_json)?;
+
+        let mut reader = GaiaCsvReader::open(file_path_str, &options)?;
+        let batch = reader.next_columns(0)?;
+        write_arrow_out(batch, out_schema, out_array)
+    })
+}
+
+/// Read the next chunk of at most `max_rows` records as an Arrow struct array
+///
+/// Exports an array of length 0 once the file is exhausted. Returns 0 on
+/// success or an error code.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences a reader handle returned by `open_gzipped_csv`
+/// - Assumes the caller will call the `release` callback of both outputs
+#[no_mangle]
+pub unsafe extern "C" fn read_csv_arrow(
+    reader: *mut GaiaCsvReader,
+    max_rows: usize,
+    out_schema: *mut ArrowSchema,
+    out_array: *mut ArrowArray,
+) -> i32 {
+    ffi_status(|| {
+        let reader = handle_arg(reader)?;
+        let batch = reader.next_columns(max_rows)?;
+        write_arrow_out(batch, out_schema, out_array)
+    })
+}
+
+/// Close a reader handle returned by `open_gzipped_csv`
+///
+/// # Safety
+/// The handle must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn close_csv_reader(reader: *mut GaiaCsvReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Create a push-based decoder for a gzipped CSV stream
+///
+/// Takes a JSON `ReaderOptions` object. `source` names the stream (e.g. its
+/// URL) in error messages. Rows are grouped into batches of `batch_size`
+/// (a single batch if 0).
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will release the decoder with `free_csv_decoder`
+#[no_mangle]
+pub unsafe extern "C" fn create_csv_decoder(
+    source: *const c_char,
+    options_json: *const c_char,
+    batch_size: usize,
+) -> *mut StreamDecoder {
+    ffi_call(|| {
+        let source_str = str_arg(source, "source")?;
+        let options = options_arg(options_json)?;
+
+        let decoder = StreamDecoder::new(source_str, &options, batch_size);
+        Ok(Box::into_raw(Box::new(decoder)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Feed `len` bytes of compressed input to a decoder
+///
+/// Returns 0 on success or an error code.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes and `decoder` must be a live
+/// handle returned by `create_csv_decoder`.
+#[no_mangle]
+pub unsafe extern "C" fn feed_csv_decoder(
+    decoder: *mut StreamDecoder,
+    data: *const u8,
+    len: usize,
+) -> i32 {
+    ffi_status(|| {
+        let decoder = handle_arg(decoder)?;
+        if len == 0 {
+            return Ok(());
         }
-        Err(_) => std::ptr::null_mut(),
+        if data.is_null() {
+            return Err(ParseError::new(ErrorCode::InvalidArgument, "data is null"));
+        }
+        decoder.feed(std::slice::from_raw_parts(data, len))
+    })
+}
+
+/// Signal the end of input, making the final partial batch available
+///
+/// Returns 0 on success or an error code.
+///
+/// # Safety
+/// `decoder` must be a live handle returned by `create_csv_decoder`.
+#[no_mangle]
+pub unsafe extern "C" fn finish_csv_decoder(decoder: *mut StreamDecoder) -> i32 {
+    ffi_status(|| handle_arg(decoder)?.finish())
+}
+
+/// Take the next completed batch from a decoder
+///
+/// Returns a batch with zero rows when no batch is ready, or null on error.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences a decoder handle returned by `create_csv_decoder`
+/// - Assumes the caller will free the batch with `free_column_batch`
+#[no_mangle]
+pub unsafe extern "C" fn next_decoder_batch(decoder: *mut StreamDecoder) -> *mut ColumnBatch {
+    ffi_call(|| {
+        let batch = handle_arg(decoder)?.next_batch().unwrap_or_default();
+        Ok(Box::into_raw(Box::new(batch)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Release a decoder returned by `create_csv_decoder`
+///
+/// # Safety
+/// The handle must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_csv_decoder(decoder: *mut StreamDecoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
+
+/// Open a Gaia `*_best_neighbour` crossmatch table with a JSON `XmatchOptions` object
+///
+/// Returns an opaque reader handle, or null if the file could not be opened
+/// or lacks the `source_id`/`original_ext_source_id` columns.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences raw pointers
+/// - Assumes the caller will release the handle with `close_xmatch_reader`
+#[no_mangle]
+pub unsafe extern "C" fn open_xmatch_reader(
+    file_path: *const c_char,
+    options_json: *const c_char,
+) -> *mut XmatchReader {
+    ffi_call(|| {
+        let file_path_str = str_arg(file_path, "file_path")?;
+        let options = XmatchOptions::from_json(str_arg(options_json, "options_json")?)?;
+
+        let reader = XmatchReader::open(file_path_str, &options)?;
+        Ok(Box::into_raw(Box::new(reader)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Read the next `max_pairs` crossmatch pairs (all remaining pairs if 0)
+///
+/// Returns a batch with zero pairs once the file is exhausted, or null on
+/// error.
+///
+/// # Safety
+/// This function is unsafe because it:
+/// - Dereferences a reader handle returned by `open_xmatch_reader`
+/// - Assumes the caller will free the batch with `free_xmatch_batch`
+#[no_mangle]
+pub unsafe extern "C" fn read_xmatch_pairs(
+    reader: *mut XmatchReader,
+    max_pairs: usize,
+) -> *mut XmatchBatch {
+    ffi_call(|| {
+        let reader = handle_arg(reader)?;
+        let batch = reader.next_pairs(max_pairs)?;
+        Ok(Box::into_raw(Box::new(batch)))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Number of pairs in a crossmatch batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_len(batch: *const XmatchBatch) -> usize {
+    batch.as_ref().map_or(0, |b| b.len())
+}
+
+/// Pointer to the `len` Gaia source IDs, borrowed from the batch
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_source_ids(batch: *const XmatchBatch) -> *const u64 {
+    batch.as_ref().map_or(std::ptr::null(), |b| b.source_ids.as_ptr())
+}
+
+/// Pointer to the `len + 1` offsets into the designation bytes
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_designation_offsets(batch: *const XmatchBatch) -> *const i32 {
+    batch.as_ref().map_or(std::ptr::null(), |b| b.designation_offsets.as_ptr())
+}
+
+/// Pointer to the UTF-8 bytes of the 2MASS designations
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_designations(batch: *const XmatchBatch) -> *const u8 {
+    batch.as_ref().map_or(std::ptr::null(), |b| b.designations.as_ptr())
+}
+
+/// Size in bytes of the designation buffer
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_designations_len(batch: *const XmatchBatch) -> usize {
+    batch.as_ref().map_or(0, |b| b.designations.len())
+}
+
+/// Pointer to the `len` angular distances (null unless match quality was requested)
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_angular_distance(batch: *const XmatchBatch) -> *const f64 {
+    batch
+        .as_ref()
+        .filter(|b| !b.angular_distance.is_empty())
+        .map_or(std::ptr::null(), |b| b.angular_distance.as_ptr())
+}
+
+/// Pointer to the `len` neighbour counts (null unless match quality was requested)
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_number_of_neighbours(batch: *const XmatchBatch) -> *const i32 {
+    batch
+        .as_ref()
+        .filter(|b| !b.number_of_neighbours.is_empty())
+        .map_or(std::ptr::null(), |b| b.number_of_neighbours.as_ptr())
+}
+
+/// Pointer to the `len` mate counts (null unless match quality was requested)
+///
+/// # Safety
+/// The batch must be null or a live pointer returned by `read_xmatch_pairs`.
+#[no_mangle]
+pub unsafe extern "C" fn xmatch_batch_number_of_mates(batch: *const XmatchBatch) -> *const i32 {
+    batch
+        .as_ref()
+        .filter(|b| !b.number_of_mates.is_empty())
+        .map_or(std::ptr::null(), |b| b.number_of_mates.as_ptr())
+}
+
+/// Free a batch returned by `read_xmatch_pairs`
+///
+/// # Safety
+/// The batch and every buffer borrowed from it must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_xmatch_batch(batch: *mut XmatchBatch) {
+    if !batch.is_null() {
+        drop(Box::from_raw(batch));
+    }
+}
+
+/// Close a reader handle returned by `open_xmatch_reader`
+///
+/// # Safety
+/// The handle must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn close_xmatch_reader(reader: *mut XmatchReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
     }
 }
 
+/// Describe the built-in Gaia table profiles as a JSON array string
+///
+/// Each entry is `{"name", "local_table", "key", "default_columns",
+/// "columns"}`, where `columns` lists the `{"name", "type"}` of the
+/// profile's schema. Pass a profile's `name` as the `table` reader option;
+/// `ingest_gaia_csv` then writes to its `local_table`. Free the result with
+/// `free_string`.
+#[no_mangle]
+pub extern "C" fn list_table_profiles() -> *mut c_char {
+    ffi_call(|| {
+        let profiles: Vec<Value> = TableProfile::ALL.iter().map(|p| p.describe()).collect();
+        into_json_c_string(&profiles)
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Get the error code of the last failed call on this thread (0 if none)
+#[no_mangle]
+pub extern "C" fn last_error_code() -> i32 {
+    error::last_error().map_or(ErrorCode::Ok, |e| e.code) as i32
+}
+
+/// Get the message of the last failed call on this thread
+///
+/// Returns null if the last call succeeded.
+///
+/// # Safety
+/// Assumes the caller will free the returned string with `free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn last_error_message() -> *mut c_char {
+    error::last_error()
+        .and_then(|e| CString::new(e.message.replace('\0', "")).ok())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
 /// Free a string allocated by Rust
+///
+/// # Safety
+/// The pointer must have been returned by this library and not freed yet.
 #[no_mangle]
 pub unsafe extern "C" fn free_string(ptr: *mut c_char) {
     if !ptr.is_null() {
@@ -55,78 +1018,482 @@ pub unsafe extern "C" fn free_string(ptr: *mut c_char) {
     }
 }
 
-fn parse_csv_internal(
-    file_path: &str,
-    columns_to_keep: &[String],
-    _chunk_size: usize,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let file = File::open(file_path)?;
-    let decoder = GzDecoder::new(file);
-    let buf_reader = BufReader::new(decoder);
+/// Run the body of an FFI call, recording any error as the thread's last error
+fn ffi_call<T>(body: impl FnOnce() -> Result<T, ParseError>) -> Option<T> {
+    error::clear_last_error();
+    body().map_err(error::set_last_error).ok()
+}
+
+/// Run the body of an FFI call that reports an error code instead of a pointer
+fn ffi_status(body: impl FnOnce() -> Result<(), ParseError>) -> i32 {
+    match ffi_call(body) {
+        Some(()) => ErrorCode::Ok as i32,
+        None => last_error_code(),
+    }
+}
+
+/// Borrow a C string argument that may be null as UTF-8
+unsafe fn optional_str_arg<'a>(ptr: *const c_char, name: &str) -> Result<Option<&'a str>, ParseError> {
+    if ptr.is_null() {
+        Ok(None)
+    } else {
+        str_arg(ptr, name).map(Some)
+    }
+}
+
+/// Borrow a C string argument as UTF-8
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, ParseError> {
+    if ptr.is_null() {
+        return Err(ParseError::new(
+            ErrorCode::InvalidArgument,
+            format!("{} is null", name),
+        ));
+    }
+
+    CStr::from_ptr(ptr).to_str().map_err(|e| {
+        ParseError::new(
+            ErrorCode::InvalidArgument,
+            format!("{} is not valid UTF-8: {}", name, e),
+        )
+    })
+}
+
+/// Borrow the object behind an opaque handle
+unsafe fn handle_arg<'a, T>(ptr: *mut T) -> Result<&'a mut T, ParseError> {
+    ptr.as_mut()
+        .ok_or_else(|| ParseError::new(ErrorCode::InvalidArgument, "handle is null"))
+}
+
+/// Borrow a column of a batch handle
+unsafe fn column_arg<'a>(batch: *const ColumnBatch, index: usize) -> Option<&'a columnar::Column> {
+    batch.as_ref()?.column(index)
+}
+
+/// Parse a JSON `ReaderOptions` object passed from the caller
+unsafe fn options_arg(options_json: *const c_char) -> Result<ReaderOptions, ParseError> {
+    ReaderOptions::from_json(str_arg(options_json, "options_json")?)
+}
+
+/// Write ingest counts to the optional output pointers
+unsafe fn write_counts(counts: IngestCounts, out_rows: *mut u64, out_inserted: *mut u64) {
+    if let Some(out) = out_rows.as_mut() {
+        *out = counts.rows;
+    }
+    if let Some(out) = out_inserted.as_mut() {
+        *out = counts.inserted;
+    }
+}
+
+/// Options for the entry points that predate `ReaderOptions`
+fn legacy_options(columns: Vec<String>) -> ReaderOptions {
+    ReaderOptions {
+        columns,
+        ids_as_strings: true,
+        ..ReaderOptions::default()
+    }
+}
+
+/// Export a batch into caller-provided Arrow structs
+unsafe fn write_arrow_out(
+    batch: ColumnBatch,
+    out_schema: *mut ArrowSchema,
+    out_array: *mut ArrowArray,
+) -> Result<(), ParseError> {
+    if out_schema.is_null() || out_array.is_null() {
+        return Err(ParseError::new(
+            ErrorCode::InvalidArgument,
+            "Arrow output pointers must not be null",
+        ));
+    }
+
+    let (schema, array) = arrow::export_batch(batch);
+    out_schema.write(schema);
+    out_array.write(array);
+    Ok(())
+}
+
+/// Parse the JSON array of column names passed from the caller
+unsafe fn parse_columns_arg(columns_json: *const c_char) -> Result<Vec<String>, ParseError> {
+    let columns_json_str = str_arg(columns_json, "columns_json")?;
+    serde_json::from_str(columns_json_str).map_err(|e| {
+        ParseError::new(
+            ErrorCode::InvalidOptionsJson,
+            format!("columns_json must be a JSON array of strings: {}", e),
+        )
+    })
+}
 
-    let mut csv_reader = ReaderBuilder::new()
-        .comment(Some(b'#'))
-        .from_reader(buf_reader);
+/// Serialize records into a C string owned by the caller
+fn into_json_c_string(records: &[Value]) -> Result<*mut c_char, ParseError> {
+    let json_str = serde_json::to_string(records)
+        .map_err(|e| ParseError::new(ErrorCode::OutputEncoding, format!("could not serialize records: {}", e)))?;
+    into_c_string(json_str)
+}
 
-    let headers = csv_reader.headers()?.clone();
+/// Move a string into a C string owned by the caller
+fn into_c_string(value: String) -> Result<*mut c_char, ParseError> {
+    // serde_json escapes control characters, so its output never contains NUL
+    CString::new(value)
+        .map(CString::into_raw)
+        .map_err(|e| {
+            ParseError::new(
+                ErrorCode::OutputEncoding,
+                format!("output contains NUL at byte {}", e.nul_position()),
+            )
+        })
+}
 
-    // Find indices of columns to keep
-    let column_indices: Vec<usize> = columns_to_keep
-        .iter()
-        .filter_map(|col| headers.iter().position(|h| h == col))
-        .collect();
+/// Name and declared type of each header column
+fn describe_columns(reader: &GaiaCsvReader) -> Vec<Value> {
+    let options = reader.options();
+    reader
+        .headers()
+        .map(|name| {
+            let column_type = options.declared_type(name).map(|t| t.column_type().name());
+            let ecsv = options.ecsv_column(name);
+            json!({
+                "name": name,
+                "type": column_type,
+                "datatype": ecsv.map(|c| c.datatype.as_str()),
+                "unit": ecsv.and_then(|c| c.unit.as_deref()),
+                "ucd": ecsv.and_then(|c| c.ucd.as_deref()),
+                "description": ecsv.and_then(|c| c.description.as_deref()),
+            })
+        })
+        .collect()
+}
 
+fn parse_csv_internal(
+    file_path: &str,
+    options: &ReaderOptions,
+    chunk_size: usize,
+) -> Result<Vec<Value>, ParseError> {
+    let mut reader = GaiaCsvReader::open(file_path, options)?;
     let mut records = Vec::new();
 
-    for result in csv_reader.records() {
-        let record = result?;
-        let mut obj = serde_json::Map::new();
-
-        for &idx in &column_indices {
-            if let Some(value) = record.get(idx) {
-                let header = &headers[idx];
-
-                // Convert to appropriate type
-                let json_value = if value.is_empty() {
-                    Value::String(value.to_string())
-                } else if header == "source_id" || header == "solution_id" || header == "designation" {
-                    Value::String(value.to_string())
-                } else {
-                    // Try to parse as number
-                    match value.parse::<f64>() {
-                        Ok(num) => json!(num),
-                        Err(_) => {
-                            match value.to_lowercase().as_str() {
-                                "null" => Value::Null,
-                                "true" => Value::Bool(true),
-                                "false" => Value::Bool(false),
-                                _ => Value::String(value.to_string()),
-                            }
-                        }
-                    }
-                };
-
-                obj.insert(header.to_string(), json_value);
-            }
+    loop {
+        let chunk = reader.next_chunk(chunk_size)?;
+        if chunk.is_empty() {
+            break;
         }
-
-        records.push(Value::Object(obj));
+        records.extend(chunk);
     }
 
-    Ok(serde_json::to_string(&records)?)
+    Ok(records)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{
+        fixture_path, write_gz_fixture, SAMPLE_BEST_NEIGHBOUR, SAMPLE_CSV, SAMPLE_ECSV, SAMPLE_TMASS_PSC,
+    };
 
     #[test]
     fn test_parse_csv() {
+        let path = write_gz_fixture("parse_csv.csv.gz", SAMPLE_CSV);
         let result = parse_csv_internal(
-            "../tests/test.csv.gz",
-            &["source_id".to_string(), "ra".to_string(), "dec".to_string()],
+            path.to_str().unwrap(),
+            &legacy_options(vec!["source_id".to_string(), "ra".to_string(), "dec".to_string()]),
             1000,
         );
         assert!(result.is_ok());
+        assert_eq!(result.unwrap()[0]["source_id"], "4295806720");
+    }
+
+    #[test]
+    fn test_read_csv_header() {
+        let path = write_gz_fixture("read_header.csv.gz", &SAMPLE_CSV.replace("has_rvs\n", "has_rvs,extra\n"));
+        let path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let result = read_csv_header(path.as_ptr());
+            let columns: Vec<Value> = serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            free_string(result);
+
+            assert_eq!(columns.len(), 8);
+            assert_eq!(columns[1]["name"], "source_id");
+            assert_eq!(columns[1]["type"], "int64");
+            assert_eq!(columns[6]["type"], "bool");
+            assert_eq!(columns[7]["name"], "extra");
+            assert_eq!(columns[7]["type"], Value::Null);
+            assert_eq!(columns[7]["unit"], Value::Null);
+        }
+
+        let path = write_gz_fixture("read_header_ecsv.csv.gz", SAMPLE_ECSV);
+        let path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let result = read_csv_header(path.as_ptr());
+            let columns: Vec<Value> = serde_json::from_str(CStr::from_ptr(result).to_str().unwrap()).unwrap();
+            free_string(result);
+
+            assert_eq!(
+                columns[1],
+                json!({
+                    "name": "ra",
+                    "type": "float64",
+                    "datatype": "float64",
+                    "unit": "deg",
+                    "ucd": "pos.eq.ra;meta.main",
+                    "description": "Right ascension, ICRS",
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_ffi_reports_last_error() {
+        let path = CString::new("/nonexistent/file.csv.gz").unwrap();
+        let columns = CString::new("not json").unwrap();
+
+        unsafe {
+            let result = parse_gzipped_csv(path.as_ptr(), columns.as_ptr(), 0);
+            assert!(result.is_null());
+            assert_eq!(last_error_code(), ErrorCode::InvalidOptionsJson as i32);
+
+            let message = last_error_message();
+            assert!(CStr::from_ptr(message).to_str().unwrap().contains("columns_json"));
+            free_string(message);
+        }
+    }
+
+    #[test]
+    fn test_parse_csv_files() {
+        let sample = write_gz_fixture("parse_files.csv.gz", SAMPLE_CSV);
+        let paths = CString::new(json!([sample.to_str().unwrap(), "/nonexistent/file.csv.gz"]).to_string()).unwrap();
+        let options = CString::new(r#"{"columns": ["source_id", "ra"]}"#).unwrap();
+        let bad_options = CString::new(r#"{"columns": 1}"#).unwrap();
+
+        unsafe {
+            let batch = parse_csv_files(paths.as_ptr(), options.as_ptr(), 2);
+            assert_eq!(file_batch_len(batch), 2);
+            assert_eq!(file_batch_error_code(batch, 0), ErrorCode::Ok as i32);
+            assert!(file_batch_error_message(batch, 0).is_null());
+            assert_eq!(column_batch_len(file_batch_columns(batch, 0)), 3);
+            assert_eq!(column_batch_num_columns(file_batch_columns(batch, 0)), 2);
+
+            assert_eq!(file_batch_error_code(batch, 1), ErrorCode::Io as i32);
+            assert!(file_batch_columns(batch, 1).is_null());
+            let message = file_batch_error_message(batch, 1);
+            assert!(CStr::from_ptr(message).to_str().unwrap().contains("/nonexistent/file.csv.gz"));
+            free_string(message);
+            free_file_batch(batch);
+
+            let batch = parse_csv_files(paths.as_ptr(), bad_options.as_ptr(), 0);
+            assert_eq!(file_batch_error_code(batch, 0), ErrorCode::InvalidOptionsJson as i32);
+            assert_eq!(file_batch_error_code(batch, 1), ErrorCode::InvalidOptionsJson as i32);
+            free_file_batch(batch);
+
+            let not_a_list = CString::new("{}").unwrap();
+            assert!(parse_csv_files(not_a_list.as_ptr(), options.as_ptr(), 0).is_null());
+            assert_eq!(last_error_code(), ErrorCode::InvalidArgument as i32);
+        }
+    }
+
+    #[test]
+    fn test_ingest_gaia_csv() {
+        let sample = write_gz_fixture("ffi_ingest.csv.gz", SAMPLE_CSV);
+        let db = fixture_path("ffi_ingest.db");
+        let _ = std::fs::remove_file(&db);
+        let sample = CString::new(sample.to_str().unwrap()).unwrap();
+        let db = CString::new(db.to_str().unwrap()).unwrap();
+        let table = CString::new("file_tracking_gaiadr3").unwrap();
+        let urls = CString::new(r#"["https://example.com/a.csv.gz", "https://example.com/b.csv.gz"]"#).unwrap();
+        let url = CString::new("https://example.com/a.csv.gz").unwrap();
+        let options = CString::new(r#"{"columns": ["source_id", "ra"]}"#).unwrap();
+
+        unsafe {
+            assert_eq!(init_file_tracking(db.as_ptr(), table.as_ptr(), urls.as_ptr(), false), ErrorCode::Ok as i32);
+
+            let (mut rows, mut inserted) = (0u64, 0u64);
+            let status = ingest_gaia_csv(
+                db.as_ptr(),
+                sample.as_ptr(),
+                url.as_ptr(),
+                options.as_ptr(),
+                0,
+                &mut rows,
+                &mut inserted,
+            );
+            assert_eq!(status, ErrorCode::Ok as i32);
+            assert_eq!((rows, inserted), (3, 3));
+
+            let status = ingest_gaia_csv(
+                db.as_ptr(),
+                sample.as_ptr(),
+                std::ptr::null(),
+                options.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                &mut inserted,
+            );
+            assert_eq!(status, ErrorCode::Ok as i32);
+            assert_eq!(inserted, 0);
+
+            let status = ingest_gaia_csv(
+                std::ptr::null(),
+                sample.as_ptr(),
+                std::ptr::null(),
+                options.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(status, ErrorCode::InvalidArgument as i32);
+
+            let other = CString::new("https://example.com/b.csv.gz").unwrap();
+            assert_eq!(mark_file_failed(db.as_ptr(), table.as_ptr(), other.as_ptr()), ErrorCode::Ok as i32);
+
+            let unknown = CString::new("gaiadr3").unwrap();
+            assert_eq!(
+                mark_file_failed(db.as_ptr(), unknown.as_ptr(), other.as_ptr()),
+                ErrorCode::InvalidArgument as i32
+            );
+
+            let tracking = Database::open(db.to_str().unwrap()).unwrap();
+            assert_eq!(
+                tracking.file_status(TrackingTable::Gaiadr3, url.to_str().unwrap()).unwrap(),
+                Some(FileStatus::Completed)
+            );
+            assert_eq!(
+                tracking.file_status(TrackingTable::Gaiadr3, other.to_str().unwrap()).unwrap(),
+                Some(FileStatus::Failed)
+            );
+        }
+    }
+
+    #[test]
+    fn test_ingest_tmass_xmatch() {
+        let sample = write_gz_fixture("ffi_xmatch_gaia.csv.gz", SAMPLE_CSV);
+        let xmatch = write_gz_fixture("ffi_xmatch.csv.gz", SAMPLE_BEST_NEIGHBOUR);
+        let db = fixture_path("ffi_xmatch.db");
+        let _ = std::fs::remove_file(&db);
+        let xmatch = CString::new(xmatch.to_str().unwrap()).unwrap();
+        let db = CString::new(db.to_str().unwrap()).unwrap();
+        let options = CString::new("{}").unwrap();
+
+        unsafe {
+            assert!(load_gaia_source_ids(db.as_ptr()).is_null());
+            assert_eq!(last_error_code(), ErrorCode::Database as i32);
+
+            let gaia_options = ReaderOptions::with_columns(&["source_id".to_string()]);
+            ingest::ingest_gaia_csv(db.to_str().unwrap(), sample.to_str().unwrap(), None, &gaia_options, 0).unwrap();
+
+            let ids = load_gaia_source_ids(db.as_ptr());
+            assert_eq!(source_id_set_len(ids), 3);
+
+            let mut inserted = 0u64;
+            let status = ingest_tmass_xmatch(
+                db.as_ptr(),
+                xmatch.as_ptr(),
+                std::ptr::null(),
+                ids,
+                options.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                &mut inserted,
+            );
+            assert_eq!(status, ErrorCode::Ok as i32);
+            assert_eq!(inserted, 1);
+            free_source_id_set(ids);
+
+            let status = ingest_tmass_xmatch(
+                db.as_ptr(),
+                xmatch.as_ptr(),
+                std::ptr::null(),
+                std::ptr::null(),
+                options.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(status, ErrorCode::InvalidArgument as i32);
+        }
+    }
+
+    #[test]
+    fn test_database_meta() {
+        let db = fixture_path("ffi_meta.db");
+        let _ = std::fs::remove_file(&db);
+        let db = CString::new(db.to_str().unwrap()).unwrap();
+        let meta = CString::new(
+            r#"{"stored_columns": ["source_id", "ra"], "magnitude_limit": 16, "zeropoints": [25.7, 25.3, 24.7], "data_release": "DR3"}"#,
+        )
+        .unwrap();
+
+        unsafe {
+            assert_eq!(init_database_meta(db.as_ptr(), meta.as_ptr()), ErrorCode::Ok as i32);
+
+            let json = read_database_meta(db.as_ptr());
+            let recorded: Value = serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            free_string(json);
+            assert_eq!(recorded["schema_version"], SCHEMA_VERSION);
+            assert_eq!(recorded["stored_columns"], json!(["source_id", "ra"]));
+
+            let other = CString::new(
+                r#"{"stored_columns": ["source_id", "ra"], "magnitude_limit": 16, "zeropoints": [25.7, 25.3, 24.7], "data_release": "DR4"}"#,
+            )
+            .unwrap();
+            assert_eq!(init_database_meta(db.as_ptr(), other.as_ptr()), ErrorCode::SettingsMismatch as i32);
+
+            let invalid = CString::new(r#"{"stored_columns": []}"#).unwrap();
+            assert_eq!(init_database_meta(db.as_ptr(), invalid.as_ptr()), ErrorCode::InvalidOptionsJson as i32);
+        }
+    }
+
+    #[test]
+    fn test_ingest_tmass_psc() {
+        let sample = write_gz_fixture("ffi_psc_gaia.csv.gz", SAMPLE_CSV);
+        let xmatch = write_gz_fixture("ffi_psc_xmatch.csv.gz", SAMPLE_BEST_NEIGHBOUR);
+        let psc = write_gz_fixture("ffi_psc.gz", SAMPLE_TMASS_PSC);
+        let db = fixture_path("ffi_psc.db");
+        let _ = std::fs::remove_file(&db);
+        let psc = CString::new(psc.to_str().unwrap()).unwrap();
+        let db = CString::new(db.to_str().unwrap()).unwrap();
+        let db_path = db.to_str().unwrap();
+
+        unsafe {
+            assert!(load_tmass_xmatch_index(db.as_ptr()).is_null());
+            assert_eq!(last_error_code(), ErrorCode::Database as i32);
+
+            let gaia_options = ReaderOptions::with_columns(&["source_id".to_string()]);
+            ingest::ingest_gaia_csv(db_path, sample.to_str().unwrap(), None, &gaia_options, 0).unwrap();
+            let ids = SourceIdSet::load(&Database::open(db_path).unwrap()).unwrap();
+            let xmatch_options = XmatchOptions::default();
+            ingest::ingest_tmass_xmatch(db_path, xmatch.to_str().unwrap(), None, &ids, &xmatch_options, 0).unwrap();
+
+            let index = load_tmass_xmatch_index(db.as_ptr());
+            assert_eq!(tmass_xmatch_index_len(index), 1);
+
+            let (mut rows, mut inserted) = (0u64, 0u64);
+            let options = CString::new(r#"{"with_errors": true}"#).unwrap();
+            let status = ingest_tmass_psc(
+                db.as_ptr(),
+                psc.as_ptr(),
+                std::ptr::null(),
+                index,
+                options.as_ptr(),
+                0,
+                &mut rows,
+                &mut inserted,
+            );
+            assert_eq!(status, ErrorCode::Ok as i32);
+            assert_eq!((rows, inserted), (1, 1));
+
+            let options = CString::new(r#"{"with_magnitudes": true}"#).unwrap();
+            let status = ingest_tmass_psc(
+                db.as_ptr(),
+                psc.as_ptr(),
+                std::ptr::null(),
+                index,
+                options.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(status, ErrorCode::InvalidOptionsJson as i32);
+            free_tmass_xmatch_index(index);
+        }
     }
 }
@@ -0,0 +1,366 @@
+use rusqlite::OptionalExtension;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::db::Database;
+use crate::error::{ErrorCode, ParseError};
+
+/// Version of this library, recorded as the parser that last wrote a database
+pub const PARSER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The settings that determine what a database holds, passed over FFI as
+/// JSON
+///
+/// ```json
+/// {
+///   "stored_columns": ["source_id", "ra", "dec"],
+///   "magnitude_limit": 16,
+///   "zeropoints": [25.6873668671, 25.3385422158, 24.7478955012],
+///   "data_release": "DR3"
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetMeta {
+    /// Columns of `gaiadr3`
+    pub stored_columns: Vec<String>,
+    /// G magnitude limit sources were filtered with
+    pub magnitude_limit: f64,
+    /// G, BP and RP zeropoints the limit was applied with
+    pub zeropoints: Vec<f64>,
+    /// Gaia data release the sources came from
+    pub data_release: String,
+}
+
+impl DatasetMeta {
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        serde_json::from_str(json).map_err(|e| {
+            ParseError::new(
+                ErrorCode::InvalidOptionsJson,
+                format!("invalid database metadata: {}", e),
+            )
+        })
+    }
+}
+
+/// A change to the database layout, applied once to every database
+#[derive(Clone, Copy)]
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Database) -> Result<(), ParseError>,
+}
+
+/// Every migration in the order it was added. A database records the
+/// version of the last one it has had as `schema_version`; new ones are
+/// appended with the next version.
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "add row counts to the file tracking tables",
+    apply: Database::add_tracking_counts,
+}];
+
+/// Schema version of a database with every migration applied
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+impl Database {
+    fn create_meta(&self) -> Result<(), ParseError> {
+        self.conn
+            .execute_batch("CREATE TABLE IF NOT EXISTS gaiaoffline_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);")
+            .map_err(|e| self.error(e))
+    }
+
+    /// Value of a `gaiaoffline_meta` key, or None if it is not recorded
+    fn meta_value(&self, key: &str) -> Result<Option<String>, ParseError> {
+        let exists = !self.table_columns("gaiaoffline_meta")?.is_empty();
+        if !exists {
+            return Ok(None);
+        }
+
+        self.conn
+            .query_row("SELECT value FROM gaiaoffline_meta WHERE key = ?", [key], |row| row.get(0))
+            .optional()
+            .map_err(|e| self.error(e))
+    }
+
+    fn set_meta_value(&self, key: &str, value: &str) -> Result<(), ParseError> {
+        self.conn
+            .execute("INSERT OR REPLACE INTO gaiaoffline_meta (key, value) VALUES (?, ?)", [key, value])
+            .map(|_| ())
+            .map_err(|e| self.error(e))
+    }
+
+    /// Schema version recorded in `gaiaoffline_meta`, 0 if none is
+    pub fn schema_version(&self) -> Result<u32, ParseError> {
+        match self.meta_value("schema_version")? {
+            Some(version) => version.parse().map_err(|_| {
+                ParseError::new(
+                    ErrorCode::SettingsMismatch,
+                    format!("{}: invalid schema_version '{}'", self.path, version),
+                )
+            }),
+            None => Ok(0),
+        }
+    }
+
+    /// Apply every migration the database has not had yet
+    ///
+    /// Each migration commits with its new `schema_version`, so an
+    /// interrupted run resumes where it stopped. A database written by a
+    /// newer version of this library is refused.
+    pub fn migrate(&self) -> Result<(), ParseError> {
+        self.migrate_with(MIGRATIONS)
+    }
+
+    fn migrate_with(&self, migrations: &[Migration]) -> Result<(), ParseError> {
+        self.create_meta()?;
+
+        let current = self.schema_version()?;
+        let latest = migrations.last().map_or(0, |m| m.version);
+        if current > latest {
+            return Err(ParseError::new(
+                ErrorCode::SettingsMismatch,
+                format!(
+                    "{}: schema version {} is newer than the {} this version of gaiaoffline supports",
+                    self.path, current, latest
+                ),
+            ));
+        }
+
+        for migration in migrations.iter().filter(|m| m.version > current) {
+            let tx = self.conn.unchecked_transaction().map_err(|e| self.error(e))?;
+            (migration.apply)(self).map_err(|e| {
+                ParseError::new(
+                    e.code,
+                    format!("migration {} ({}) failed: {}", migration.version, migration.description, e.message),
+                )
+            })?;
+            self.set_meta_value("schema_version", &migration.version.to_string())?;
+            tx.commit().map_err(|e| self.error(e))?;
+        }
+        Ok(())
+    }
+
+    /// Migrate the database, then record `meta` if the database has none or
+    /// refuse it if it differs from what the database was populated with
+    ///
+    /// A database populated before `gaiaoffline_meta` existed is checked
+    /// against the columns of its `gaiadr3` table.
+    pub fn init_meta(&self, meta: &DatasetMeta) -> Result<(), ParseError> {
+        self.migrate()?;
+        self.check_meta(meta)?;
+
+        let tx = self.conn.unchecked_transaction().map_err(|e| self.error(e))?;
+        self.set_meta_value("stored_columns", &json!(meta.stored_columns).to_string())?;
+        self.set_meta_value("magnitude_limit", &json!(meta.magnitude_limit).to_string())?;
+        self.set_meta_value("zeropoints", &json!(meta.zeropoints).to_string())?;
+        self.set_meta_value("data_release", &meta.data_release)?;
+        self.set_meta_value("parser_version", PARSER_VERSION)?;
+        tx.commit().map_err(|e| self.error(e))
+    }
+
+    fn check_meta(&self, meta: &DatasetMeta) -> Result<(), ParseError> {
+        self.check_stored_columns(&meta.stored_columns)?;
+
+        // Numbers are compared by value, since TypeScript records 16 where
+        // this library records 16.0
+        let numbers = |value: &Value| -> Option<Vec<f64>> {
+            match value {
+                Value::Array(items) => items.iter().map(Value::as_f64).collect(),
+                other => other.as_f64().map(|number| vec![number]),
+            }
+        };
+        let recorded = self.read_meta()?;
+        let mismatch = |setting: &str, recorded: &Value, requested: Value| {
+            let same = match numbers(&requested) {
+                Some(requested) => numbers(recorded) == Some(requested),
+                None => *recorded == requested,
+            };
+            if recorded.is_null() || same {
+                return Ok(());
+            }
+            Err(ParseError::new(
+                ErrorCode::SettingsMismatch,
+                format!(
+                    "{}: populated with {} {} but {} was requested",
+                    self.path, setting, recorded, requested
+                ),
+            ))
+        };
+        mismatch("magnitude_limit", &recorded["magnitude_limit"], json!(meta.magnitude_limit))?;
+        mismatch("zeropoints", &recorded["zeropoints"], json!(meta.zeropoints))?;
+        mismatch("data_release", &recorded["data_release"], json!(meta.data_release))
+    }
+
+    /// Refuse `columns` unless they are the `gaiadr3` columns recorded in
+    /// `gaiaoffline_meta`, or those of an existing `gaiadr3` table if none
+    /// are recorded
+    ///
+    /// Order does not matter since rows are inserted by column name.
+    pub fn check_stored_columns(&self, columns: &[String]) -> Result<(), ParseError> {
+        let existing = match self.meta_value("stored_columns")? {
+            Some(recorded) => serde_json::from_str(&recorded).map_err(|e| {
+                ParseError::new(
+                    ErrorCode::SettingsMismatch,
+                    format!("{}: invalid stored_columns: {}", self.path, e),
+                )
+            })?,
+            None => self.table_columns("gaiadr3")?,
+        };
+        if existing.is_empty() {
+            return Ok(());
+        }
+
+        let mut requested = columns.to_vec();
+        let mut existing = existing;
+        requested.sort();
+        existing.sort();
+        if requested == existing {
+            return Ok(());
+        }
+
+        Err(ParseError::new(
+            ErrorCode::SettingsMismatch,
+            format!(
+                "{}: gaiadr3 stores columns {} but {} were requested",
+                self.path,
+                existing.join(","),
+                columns.join(",")
+            ),
+        ))
+    }
+
+    /// Everything recorded in `gaiaoffline_meta` as a JSON object, with
+    /// `null` for settings that are not recorded
+    pub fn read_meta(&self) -> Result<Value, ParseError> {
+        let parsed = |key: &str| -> Result<Value, ParseError> {
+            Ok(self
+                .meta_value(key)?
+                .and_then(|value| serde_json::from_str(&value).ok())
+                .unwrap_or(Value::Null))
+        };
+
+        Ok(json!({
+            "schema_version": self.schema_version()?,
+            "parser_version": self.meta_value("parser_version")?,
+            "stored_columns": parsed("stored_columns")?,
+            "magnitude_limit": parsed("magnitude_limit")?,
+            "zeropoints": parsed("zeropoints")?,
+            "data_release": self.meta_value("data_release")?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture_path;
+
+    fn fresh_db(name: &str) -> Database {
+        let path = fixture_path(name);
+        let _ = std::fs::remove_file(&path);
+        Database::open(path.to_str().unwrap()).unwrap()
+    }
+
+    fn sample_meta() -> DatasetMeta {
+        DatasetMeta::from_json(
+            r#"{"stored_columns": ["source_id", "ra"], "magnitude_limit": 16,
+                "zeropoints": [25.6873668671, 25.3385422158, 24.7478955012], "data_release": "DR3"}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_init_meta() {
+        let db = fresh_db("meta_init.db");
+        db.init_meta(&sample_meta()).unwrap();
+
+        let meta = db.read_meta().unwrap();
+        assert_eq!(meta["schema_version"], SCHEMA_VERSION);
+        assert_eq!(meta["parser_version"], PARSER_VERSION);
+        assert_eq!(meta["stored_columns"], json!(["source_id", "ra"]));
+        assert_eq!(meta["magnitude_limit"], 16.0);
+        assert_eq!(meta["data_release"], "DR3");
+
+        // The same settings in another order can append
+        let mut same = sample_meta();
+        same.stored_columns.reverse();
+        db.init_meta(&same).unwrap();
+
+        let mut columns = sample_meta();
+        columns.stored_columns.push("dec".to_string());
+        let err = db.init_meta(&columns).unwrap_err();
+        assert_eq!(err.code, ErrorCode::SettingsMismatch);
+        assert!(err.message.contains("source_id,ra"), "{}", err.message);
+
+        let mut limit = sample_meta();
+        limit.magnitude_limit = 17.0;
+        let err = db.init_meta(&limit).unwrap_err();
+        assert_eq!(err.code, ErrorCode::SettingsMismatch);
+        assert!(err.message.contains("magnitude_limit 16.0 but 17.0"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_meta_recorded_by_typescript() {
+        let db = fresh_db("meta_typescript.db");
+        db.create_meta().unwrap();
+        db.set_meta_value("magnitude_limit", "16").unwrap();
+        db.set_meta_value("zeropoints", "[25.6873668671,25.3385422158,24.7478955012]").unwrap();
+        db.set_meta_value("data_release", "DR3").unwrap();
+
+        db.init_meta(&sample_meta()).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+
+        let mut limit = sample_meta();
+        limit.magnitude_limit = 16.5;
+        assert_eq!(db.init_meta(&limit).unwrap_err().code, ErrorCode::SettingsMismatch);
+    }
+
+    #[test]
+    fn test_legacy_database() {
+        let db = fresh_db("meta_legacy.db");
+        db.create_gaiadr3(&["source_id".to_string(), "ra".to_string(), "dec".to_string()]).unwrap();
+        assert_eq!(db.read_meta().unwrap()["schema_version"], 0);
+
+        let err = db.init_meta(&sample_meta()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::SettingsMismatch);
+
+        let mut meta = sample_meta();
+        meta.stored_columns.push("dec".to_string());
+        db.init_meta(&meta).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        fn add_healpix(db: &Database) -> Result<(), ParseError> {
+            db.add_missing_columns("gaiadr3", &[("healpix", "INTEGER")])
+        }
+
+        let db = fresh_db("meta_migrate.db");
+        db.create_gaiadr3(&["source_id".to_string()]).unwrap();
+        db.conn
+            .execute_batch("CREATE TABLE file_tracking_tmass (url TEXT PRIMARY KEY, status TEXT DEFAULT 'pending')")
+            .unwrap();
+
+        db.migrate().unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(
+            db.table_columns("file_tracking_tmass").unwrap(),
+            ["url", "status", "row_count", "inserted_count"]
+        );
+
+        let healpix = Migration {
+            version: SCHEMA_VERSION + 1,
+            description: "add healpix to gaiadr3",
+            apply: add_healpix,
+        };
+        let migrations: Vec<Migration> = MIGRATIONS.iter().copied().chain([healpix]).collect();
+        db.migrate_with(&migrations).unwrap();
+        db.migrate_with(&migrations).unwrap();
+        assert_eq!(db.table_columns("gaiadr3").unwrap(), ["source_id", "healpix"]);
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION + 1);
+
+        // This version cannot read past its own migrations
+        let err = db.migrate().unwrap_err();
+        assert_eq!(err.code, ErrorCode::SettingsMismatch);
+    }
+}
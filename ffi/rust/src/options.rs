@@ -0,0 +1,201 @@
+use std::sync::Arc;
+use serde::Deserialize;
+use crate::columnar::ColumnType;
+use crate::ecsv::{EcsvColumn, EcsvHeader};
+use crate::error::{ErrorCode, ParseError};
+use crate::filter::MagnitudeCut;
+use crate::schema::{is_identifier, FileFormat, GaiaType};
+use crate::tables::TableProfile;
+
+/// Options for reading a Gaia CSV, passed over FFI as a JSON object
+///
+/// ```json
+/// {
+///   "format": "gaia_csv",
+///   "table": "astrophysical_parameters",
+///   "columns": ["source_id", "ra", "dec"],
+///   "ids_as_strings": false,
+///   "strict_columns": true,
+///   "strict_schema": true,
+///   "magnitude_cut": { "limit": 16, "band": "G", "zeropoint": 25.6873668671 },
+///   "filter": "parallax > 5 AND ruwe < 1.4",
+///   "gzip_threads": 4
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReaderOptions {
+    /// Layout and schema of the input file
+    pub format: FileFormat,
+    /// Gaia table profile supplying the schema of a `gaia_csv` file, and its
+    /// default columns when `columns` is empty
+    pub table: Option<TableProfile>,
+    /// Columns to keep, in output order
+    pub columns: Vec<String>,
+    /// Emit 64-bit identifier columns (`source_id`, `solution_id`,
+    /// `random_index`) as decimal strings instead of exact integers, for
+    /// consumers such as JavaScript that cannot hold them in a number
+    pub ids_as_strings: bool,
+    /// Fail if any requested column is missing from the file header; when
+    /// off, missing columns are skipped
+    pub strict_columns: bool,
+    /// Fail if the file's ECSV header declares a type for a column that
+    /// differs from the built-in schema, rather than reading it as declared
+    pub strict_schema: bool,
+    /// Drop rows fainter than a magnitude limit before any conversion
+    pub magnitude_cut: Option<MagnitudeCut>,
+    /// Keep only rows matching this expression (see `expr.rs` for the syntax)
+    pub filter: Option<String>,
+    /// Inflate a gzip file on this many threads (see `pgzip.rs`); 0 or 1
+    /// reads it on the calling thread
+    pub gzip_threads: usize,
+    /// Column metadata from the ECSV header of the file being read
+    #[serde(skip)]
+    pub(crate) ecsv: Option<Arc<EcsvHeader>>,
+}
+
+impl ReaderOptions {
+    /// Options selecting `columns` with all other settings at their defaults
+    pub fn with_columns(columns: &[String]) -> Self {
+        Self {
+            columns: columns.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Parse options from their JSON representation
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        let invalid = |message: String| {
+            ParseError::new(
+                ErrorCode::InvalidOptionsJson,
+                format!("invalid reader options: {}", message),
+            )
+        };
+
+        let mut options: Self = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+
+        if let Some(table) = options.table {
+            if options.format != FileFormat::GaiaCsv {
+                return Err(invalid(format!("table {} needs the gaia_csv format", table.name())));
+            }
+            if options.columns.is_empty() {
+                options.columns = table.default_columns().iter().map(|c| c.to_string()).collect();
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Attach the ECSV header read from a file, so its declared datatypes
+    /// are used for typing
+    ///
+    /// The header must describe the columns of the header row; with
+    /// `strict_schema` set it must also agree with the built-in schema.
+    pub(crate) fn with_ecsv<'a>(
+        &self,
+        ecsv: Option<EcsvHeader>,
+        headers: impl IntoIterator<Item = &'a str>,
+        source: &str,
+    ) -> Result<Self, ParseError> {
+        let Some(ecsv) = ecsv else {
+            return Ok(self.clone());
+        };
+
+        ecsv.check_headers(headers, source)?;
+
+        if self.strict_schema {
+            let mismatches = ecsv.schema_mismatches(|name| self.schema_type(name));
+            if !mismatches.is_empty() {
+                return Err(ParseError::new(
+                    ErrorCode::SchemaMismatch,
+                    format!(
+                        "{}: ECSV header disagrees with the built-in schema: {}",
+                        source,
+                        mismatches.join("; ")
+                    ),
+                ));
+            }
+        }
+
+        Ok(Self {
+            ecsv: Some(Arc::new(ecsv)),
+            ..self.clone()
+        })
+    }
+
+    /// ECSV metadata of `header`, if the file declared any
+    pub fn ecsv_column(&self, header: &str) -> Option<&EcsvColumn> {
+        self.ecsv.as_ref()?.column(header)
+    }
+
+    /// Declared type of `header`, from the file's ECSV header if present and
+    /// otherwise the built-in schema
+    pub fn declared_type(&self, header: &str) -> Option<GaiaType> {
+        self.ecsv
+            .as_ref()
+            .and_then(|ecsv| ecsv.declared_type(header))
+            .or_else(|| self.schema_type(header))
+    }
+
+    /// Type of `header` in the table profile's schema, or the input format's
+    /// if no profile is set
+    fn schema_type(&self, header: &str) -> Option<GaiaType> {
+        match self.table {
+            Some(table) => table.declared_type(header),
+            None => self.format.declared_type(header),
+        }
+    }
+
+    /// Column type used for `header` in columnar output
+    ///
+    /// Columns without a declared type are read as floats.
+    pub fn column_type(&self, header: &str) -> ColumnType {
+        if self.ids_as_strings && is_identifier(header) {
+            ColumnType::Utf8
+        } else {
+            self.declared_type(header).map_or(ColumnType::Float64, |t| t.column_type())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Band;
+
+    #[test]
+    fn test_from_json() {
+        let options = ReaderOptions::from_json(r#"{"columns": ["source_id"], "ids_as_strings": true}"#).unwrap();
+        assert_eq!(options.columns, vec!["source_id"]);
+        assert_eq!(options.column_type("source_id"), ColumnType::Utf8);
+        assert_eq!(options.column_type("ra"), ColumnType::Float64);
+
+        let defaults = ReaderOptions::from_json("{}").unwrap();
+        assert_eq!(defaults.column_type("source_id"), ColumnType::Int64);
+        assert_eq!(defaults.column_type("has_rvs"), ColumnType::Bool);
+        assert_eq!(defaults.format, FileFormat::GaiaCsv);
+        assert!(defaults.magnitude_cut.is_none());
+
+        let cut = ReaderOptions::from_json(r#"{"magnitude_cut": {"limit": 16, "zeropoint": 25.6873668671}}"#).unwrap();
+        assert_eq!(cut.magnitude_cut.unwrap().band, Band::G);
+
+        let typo = ReaderOptions::from_json(r#"{"column": ["ra"]}"#).unwrap_err();
+        assert_eq!(typo.code, ErrorCode::InvalidOptionsJson);
+    }
+
+    #[test]
+    fn test_table_profile() {
+        let vari = ReaderOptions::from_json(r#"{"table": "vari_summary"}"#).unwrap();
+        assert_eq!(vari.columns[0], "source_id");
+        assert_eq!(vari.column_type("in_vari_classification_result"), ColumnType::Bool);
+        // Columns of other tables are no longer typed by the gaia_source schema
+        assert_eq!(vari.declared_type("phot_variable_flag"), None);
+
+        let qso = ReaderOptions::from_json(r#"{"table": "qso_candidates", "columns": ["source_id", "classlabel_dsc"]}"#).unwrap();
+        assert_eq!(qso.columns.len(), 2);
+        assert_eq!(qso.column_type("classlabel_dsc"), ColumnType::Utf8);
+
+        let psc = ReaderOptions::from_json(r#"{"format": "tmass_psc", "table": "gaia_source"}"#).unwrap_err();
+        assert_eq!(psc.code, ErrorCode::InvalidOptionsJson);
+    }
+}
@@ -0,0 +1,479 @@
+//! Decompression of a single gzip member on several threads
+//!
+//! The compressed data is split into fixed-size chunks. A worker finds the
+//! first plausible block boundary in its chunk and decodes from there
+//! without the preceding window (see `inflate.rs`), stopping at the first
+//! boundary in the next chunk. The reader then takes the chunks in order,
+//! filling in window bytes from the output before each one. A chunk whose
+//! guessed start does not line up with where the previous chunk ended is
+//! decoded again from there, so a wrong guess costs time but never output.
+//!
+//! Workers run at most `2 * threads` chunks ahead of the reader. A decoded
+//! chunk is held as 16-bit symbols until the reader takes it, twice the size
+//! of its output, so besides the mapped file the reader holds at most about
+//! `4 * threads * CHUNK_SIZE` times the compression ratio, for example
+//! 640 MiB with 8 threads on data that deflates 5:1.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+use std::ops::Deref;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use flate2::bufread::MultiGzDecoder;
+use flate2::Crc;
+use memmap2::Mmap;
+use crate::gzip::header_len;
+use crate::inflate::{find_block, inflate_blocks, InflateError, Inflated, Window, WINDOW_SIZE};
+
+/// Compressed bytes per chunk
+pub(crate) const CHUNK_SIZE: usize = 4 << 20;
+
+/// Guessed block boundaries a worker tries in one chunk before leaving it to
+/// the reader, which decodes it from the end of the previous chunk
+///
+/// Each failed guess can decode most of the chunk before failing, so trying
+/// every candidate would take time quadratic in the chunk size.
+const MAX_BOUNDARY_ATTEMPTS: usize = 8;
+
+/// Open a gzip file for reading, decompressing it on `threads` threads if
+/// it is large enough to be worth it
+///
+/// A file of at least two chunks is memory-mapped rather than read, so its
+/// pages are loaded as the workers reach them. Anything smaller is streamed
+/// through `MultiGzDecoder` without being held in memory.
+pub(crate) fn open_parallel(file: BufReader<File>, threads: usize) -> io::Result<Box<dyn BufRead>> {
+    open_chunked(file, threads, CHUNK_SIZE)
+}
+
+fn open_chunked(file: BufReader<File>, threads: usize, chunk_size: usize) -> io::Result<Box<dyn BufRead>> {
+    let sequential = |file| Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))) as Box<dyn BufRead>);
+    if file.get_ref().metadata()?.len() < (chunk_size * 2) as u64 {
+        return sequential(file);
+    }
+
+    // SAFETY: the mapping is only read, and the files read here are complete
+    // downloads that nothing writes to any more
+    let map = unsafe { Mmap::map(file.get_ref())? };
+    match header_len(&map)? {
+        Some(header) if map.len() - header >= chunk_size * 2 => Ok(Box::new(ParallelGzipReader::new(
+            GzipData::from(map),
+            header,
+            threads,
+            chunk_size,
+        ))),
+        _ => sequential(file),
+    }
+}
+
+/// Compressed data shared by the reader and its workers, either a mapped
+/// file or bytes already in memory
+#[derive(Clone)]
+pub(crate) struct GzipData(Arc<Source>);
+
+enum Source {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl From<Mmap> for GzipData {
+    fn from(map: Mmap) -> Self {
+        Self(Arc::new(Source::Mapped(map)))
+    }
+}
+
+impl From<Vec<u8>> for GzipData {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Arc::new(Source::Owned(bytes)))
+    }
+}
+
+impl Deref for GzipData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &*self.0 {
+            Source::Mapped(map) => map,
+            Source::Owned(bytes) => bytes,
+        }
+    }
+}
+
+impl AsRef<[u8]> for GzipData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Chunk results shared between the reader and its workers
+#[derive(Default)]
+struct Queue {
+    // Next chunk to hand to a worker
+    next: usize,
+    // Chunks taken by the reader; workers stay within a few chunks of it
+    taken: usize,
+    results: BTreeMap<usize, Result<Inflated, InflateError>>,
+    shutdown: bool,
+}
+
+struct Shared {
+    data: GzipData,
+    // Bit offset of the first deflate block
+    start: u64,
+    chunk_bits: u64,
+    chunks: usize,
+    lookahead: usize,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+impl Shared {
+    /// Bit offset where chunk `index` starts; the last chunk runs to the end
+    fn chunk_start(&self, index: usize) -> u64 {
+        if index >= self.chunks {
+            u64::MAX
+        } else {
+            self.start + index as u64 * self.chunk_bits
+        }
+    }
+
+    fn inflate_chunk(&self, index: usize) -> Result<Inflated, InflateError> {
+        let (mut from, stop) = (self.chunk_start(index), self.chunk_start(index + 1));
+        if index == 0 {
+            return inflate_blocks(&self.data, from, Window::Known(&[]), stop);
+        }
+
+        for _ in 0..MAX_BOUNDARY_ATTEMPTS {
+            let candidate = find_block(&self.data, from, stop).ok_or(InflateError::Invalid("no block boundary found"))?;
+            match inflate_blocks(&self.data, candidate, Window::Unknown, stop) {
+                Ok(inflated) => return Ok(inflated),
+                Err(_) => from = candidate + 1,
+            }
+        }
+        Err(InflateError::Invalid("too many false block boundaries"))
+    }
+
+    fn work(&self) {
+        loop {
+            let index = {
+                let mut queue = self.lock();
+                while !queue.shutdown && queue.next < self.chunks && queue.next >= queue.taken + self.lookahead {
+                    queue = self.wait(queue);
+                }
+                if queue.shutdown || queue.next >= self.chunks {
+                    return;
+                }
+                queue.next += 1;
+                queue.next - 1
+            };
+
+            // Every chunk handed out must get a result, or `take` would wait
+            // for it forever; a panic is stored as a failed chunk, which the
+            // reader decodes again itself
+            let result = catch_unwind(AssertUnwindSafe(|| self.inflate_chunk(index)))
+                .unwrap_or(Err(InflateError::Invalid("worker panicked")));
+            self.lock().results.insert(index, result);
+            self.changed.notify_all();
+        }
+    }
+
+    /// Wait for chunk `index` and let the workers move on
+    fn take(&self, index: usize) -> Result<Inflated, InflateError> {
+        let mut queue = self.lock();
+        let result = loop {
+            match queue.results.remove(&index) {
+                Some(result) => break result,
+                None => queue = self.wait(queue),
+            }
+        };
+        queue.taken = index + 1;
+        self.changed.notify_all();
+        result
+    }
+
+    fn shut_down(&self) {
+        self.lock().shutdown = true;
+        self.changed.notify_all();
+    }
+
+    // The queue is consistent whenever the lock is released, so a panic
+    // while holding it leaves nothing to recover from
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, queue: MutexGuard<'a, Queue>) -> MutexGuard<'a, Queue> {
+        self.changed.wait(queue).unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Reader over gzip data in memory or mapped from a file whose first member
+/// is inflated on worker threads
+///
+/// Any further members are read sequentially. Like `GzipInflater`, errors
+/// are `InvalidData` for corruption and `UnexpectedEof` for truncation.
+pub(crate) struct ParallelGzipReader {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    next_chunk: usize,
+    // Bit offset of the next block to decode
+    position: u64,
+    // The last WINDOW_SIZE bytes of output
+    window: Vec<u8>,
+    crc: Crc,
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    member_done: bool,
+    // Members after the first
+    rest: Option<Box<dyn BufRead>>,
+}
+
+impl ParallelGzipReader {
+    /// Start decompressing `data`, whose first member header is `header`
+    /// bytes long
+    pub fn new(data: GzipData, header: usize, threads: usize, chunk_size: usize) -> Self {
+        let threads = threads.max(1);
+        let start = header as u64 * 8;
+        let chunk_bits = chunk_size as u64 * 8;
+        let shared = Arc::new(Shared {
+            start,
+            chunk_bits,
+            chunks: ((data.len() as u64 * 8 - start).div_ceil(chunk_bits) as usize).max(1),
+            lookahead: threads * 2,
+            data,
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+        });
+
+        let workers = (0..threads)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                std::thread::spawn(move || shared.work())
+            })
+            .collect();
+
+        Self {
+            shared,
+            workers,
+            next_chunk: 0,
+            position: start,
+            window: Vec::with_capacity(WINDOW_SIZE * 2),
+            crc: Crc::new(),
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            member_done: false,
+            rest: None,
+        }
+    }
+
+    /// Fill the buffer with the next chunk's output, which may be empty
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let index = self.next_chunk;
+        if index >= self.shared.chunks {
+            // Only reached after an earlier error in the last chunk
+            return Err(inflate_error(InflateError::Truncated));
+        }
+        self.next_chunk += 1;
+        let stop = self.shared.chunk_start(index + 1);
+
+        let inflated = match self.shared.take(index) {
+            Ok(inflated) if inflated.start == self.position => inflated,
+            // The worker started from a false boundary or found none
+            _ => inflate_blocks(&self.shared.data, self.position, Window::Known(&self.window), stop)
+                .map_err(inflate_error)?,
+        };
+
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        inflated.resolve(&self.window, &mut self.buffer).map_err(inflate_error)?;
+        self.crc.update(&self.buffer);
+
+        let keep = WINDOW_SIZE.saturating_sub(self.buffer.len()).min(self.window.len());
+        self.window.drain(..self.window.len() - keep);
+        self.window.extend_from_slice(&self.buffer[self.buffer.len().saturating_sub(WINDOW_SIZE)..]);
+
+        self.position = inflated.end;
+        if inflated.last_block {
+            self.end_member()?;
+        }
+        Ok(())
+    }
+
+    /// Check the first member's trailer and set up reading any others
+    fn end_member(&mut self) -> io::Result<()> {
+        self.member_done = true;
+        self.shared.shut_down();
+
+        let data = &self.shared.data;
+        let trailer_start = self.position.div_ceil(8) as usize;
+        let trailer = data.get(trailer_start..trailer_start + 8).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "gzip stream truncated in the trailer of member 1")
+        })?;
+
+        let word = |i: usize| u32::from_le_bytes(trailer[i..i + 4].try_into().unwrap());
+        if word(0) != self.crc.sum() || word(4) != self.crc.amount() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupt gzip stream in member 1: CRC32 or length does not match the trailer",
+            ));
+        }
+
+        if trailer_start + 8 < data.len() {
+            let mut rest = Cursor::new(data.clone());
+            rest.set_position(trailer_start as u64 + 8);
+            self.rest = Some(Box::new(BufReader::new(MultiGzDecoder::new(rest))));
+        }
+        Ok(())
+    }
+}
+
+fn inflate_error(err: InflateError) -> io::Error {
+    match err {
+        InflateError::Truncated => io::Error::new(io::ErrorKind::UnexpectedEof, "gzip stream truncated"),
+        InflateError::Invalid(reason) => {
+            io::Error::new(io::ErrorKind::InvalidData, format!("corrupt gzip stream: {}", reason))
+        }
+    }
+}
+
+impl Read for ParallelGzipReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for ParallelGzipReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.buffer_pos == self.buffer.len() && !self.member_done {
+            self.next_chunk()?;
+        }
+        if self.buffer_pos < self.buffer.len() {
+            return Ok(&self.buffer[self.buffer_pos..]);
+        }
+
+        match self.rest.as_mut() {
+            Some(rest) => rest.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if self.buffer_pos < self.buffer.len() {
+            self.buffer_pos += amount;
+        } else if let Some(rest) = self.rest.as_mut() {
+            rest.consume(amount);
+        }
+    }
+}
+
+impl Drop for ParallelGzipReader {
+    fn drop(&mut self) {
+        self.shared.shut_down();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{gzip_bytes, sample_rows, write_fixture};
+
+    fn read_parallel(compressed: Vec<u8>, threads: usize, chunk_size: usize) -> io::Result<Vec<u8>> {
+        let header = header_len(&compressed).unwrap().unwrap();
+        let mut reader = ParallelGzipReader::new(compressed.into(), header, threads, chunk_size);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let data = sample_rows(50_000);
+        let compressed = gzip_bytes(std::str::from_utf8(&data).unwrap());
+
+        // Chunks smaller than a block exercise the fallback to decoding from
+        // the previous chunk's end
+        for (threads, chunk_size) in [(1, 64 << 10), (4, 64 << 10), (3, 20 << 10), (8, 4 << 10)] {
+            let out = read_parallel(compressed.clone(), threads, chunk_size).unwrap();
+            assert!(out == data, "{} threads, {} byte chunks", threads, chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_open_file() {
+        let open = |path: &std::path::Path| {
+            let mut out = Vec::new();
+            let file = BufReader::new(File::open(path).unwrap());
+            open_chunked(file, 4, 16 << 10).unwrap().read_to_end(&mut out).unwrap();
+            out
+        };
+
+        // Too small to split, so streamed
+        let small = sample_rows(100);
+        let path = write_fixture("pgzip_small.csv.gz", &gzip_bytes(std::str::from_utf8(&small).unwrap()));
+        assert!(open(&path) == small);
+
+        // Mapped and split into chunks
+        let large = sample_rows(10_000);
+        let compressed = gzip_bytes(std::str::from_utf8(&large).unwrap());
+        assert!(compressed.len() > 32 << 10);
+        let path = write_fixture("pgzip_large.csv.gz", &compressed);
+        assert!(open(&path) == large);
+    }
+
+    #[test]
+    fn test_poisoned_queue() {
+        let data = sample_rows(10_000);
+        let compressed = gzip_bytes(std::str::from_utf8(&data).unwrap());
+        let header = header_len(&compressed).unwrap().unwrap();
+        let mut reader = ParallelGzipReader::new(compressed.into(), header, 2, 16 << 10);
+
+        let shared = Arc::clone(&reader.shared);
+        let _ = std::thread::spawn(move || {
+            let _queue = shared.queue.lock().unwrap();
+            panic!("poison the queue");
+        })
+        .join();
+        assert!(reader.shared.queue.is_poisoned());
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert!(out == data);
+    }
+
+    #[test]
+    fn test_further_members() {
+        let mut compressed = gzip_bytes(std::str::from_utf8(&sample_rows(10_000)).unwrap());
+        compressed.extend(gzip_bytes("4295806720,0,0,0\n"));
+
+        let out = read_parallel(compressed, 2, 16 << 10).unwrap();
+        assert!(out.ends_with(b"\n4295806720,0,0,0\n"));
+        assert_eq!(out.len(), sample_rows(10_000).len() + 17);
+    }
+
+    #[test]
+    fn test_truncated_and_corrupt() {
+        let compressed = gzip_bytes(std::str::from_utf8(&sample_rows(10_000)).unwrap());
+
+        let truncated = compressed[..compressed.len() * 2 / 3].to_vec();
+        let err = read_parallel(truncated, 4, 16 << 10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let no_trailer = compressed[..compressed.len() - 4].to_vec();
+        let err = read_parallel(no_trailer, 4, 16 << 10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut bad_crc = compressed.clone();
+        let crc_offset = bad_crc.len() - 8;
+        bad_crc[crc_offset] ^= 0xff;
+        let err = read_parallel(bad_crc, 4, 16 << 10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
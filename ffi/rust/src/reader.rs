@@ -0,0 +1,572 @@
+use std::io::BufRead;
+use csv::{ReaderBuilder, StringRecord};
+use serde_json::{json, Value};
+use crate::codec::open_decompressed;
+use crate::columnar::{array_elements, is_null_field, parse_float, parse_i64, BatchBuilder, ColumnBatch, ColumnType};
+use crate::ecsv::{read_preamble, EcsvHeader};
+use crate::error::{ErrorCode, ParseError};
+use crate::filter::RowFilter;
+use crate::options::ReaderOptions;
+use crate::records::RecordReader;
+use crate::schema::{is_identifier, FileFormat, GaiaType};
+
+pub(crate) type CsvFileReader = csv::Reader<Box<dyn BufRead>>;
+
+/// Stateful reader over a Gaia CSV file, plain or compressed
+///
+/// Rows are pulled in chunks so callers never hold more than one chunk
+/// of converted records in memory at a time. Every row is split into the
+/// same buffers, and only the fields that are kept or filtered on are
+/// checked for UTF-8 or parsed.
+pub struct GaiaCsvReader {
+    file_path: String,
+    records: RecordReader,
+    headers: StringRecord,
+    column_indices: Vec<usize>,
+    json_columns: Vec<JsonColumn>,
+    filter: RowFilter,
+    options: ReaderOptions,
+}
+
+impl GaiaCsvReader {
+    /// Open a CSV file and resolve the columns to keep
+    ///
+    /// The compression codec is detected from the file's magic bytes.
+    ///
+    /// Column types declared in the file's ECSV header take precedence over
+    /// the built-in schema.
+    pub fn open(file_path: &str, options: &ReaderOptions) -> Result<Self, ParseError> {
+        let (records, headers, ecsv) = open_records(file_path, options.format, options.gzip_threads)?;
+        let options = options.with_ecsv(ecsv, &headers, file_path)?;
+
+        let column_indices = resolve_columns(&headers, &options.columns, options.strict_columns, file_path)?;
+        let filter = RowFilter::compile(&headers, &options, file_path)?;
+        let json_columns = column_indices
+            .iter()
+            .map(|&idx| JsonColumn::new(&headers[idx], &options))
+            .collect();
+
+        Ok(Self {
+            file_path: file_path.to_string(),
+            records,
+            headers,
+            column_indices,
+            json_columns,
+            filter,
+            options,
+        })
+    }
+
+    /// Column names from the file header, in file order
+    pub fn headers(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter()
+    }
+
+    /// Options in effect for this file, including its ECSV header
+    pub fn options(&self) -> &ReaderOptions {
+        &self.options
+    }
+
+    /// Name and columnar type of each kept column, in output order
+    pub fn columns(&self) -> Vec<(&str, ColumnType)> {
+        self.column_indices
+            .iter()
+            .map(|&idx| (&self.headers[idx], self.options.column_type(&self.headers[idx])))
+            .collect()
+    }
+
+    /// Read up to `max_rows` records that pass the row filter (all remaining
+    /// rows if 0)
+    ///
+    /// Returns an empty vector once the file is exhausted.
+    pub fn next_chunk(
+        &mut self,
+        max_rows: usize,
+    ) -> Result<Vec<Value>, ParseError> {
+        let limit = if max_rows == 0 { usize::MAX } else { max_rows };
+        let mut records = Vec::new();
+
+        while records.len() < limit && self.records.read(&self.file_path)? {
+            if !self.filter.accepts(|idx| str_field(&self.records, idx)) {
+                continue;
+            }
+
+            let mut obj = serde_json::Map::new();
+
+            for (column, &idx) in self.json_columns.iter().zip(&self.column_indices) {
+                if let Some(value) = self.records.get(idx) {
+                    let value = std::str::from_utf8(value).map_err(|e| {
+                        self.row_error(ParseError::new(
+                            ErrorCode::CsvSyntax,
+                            format!("{} is not valid UTF-8: {}", column.name, e),
+                        ))
+                    })?;
+                    obj.insert(column.name.clone(), column.convert(value));
+                }
+            }
+
+            records.push(Value::Object(obj));
+        }
+
+        Ok(records)
+    }
+
+    /// Read up to `max_rows` records that pass the row filter into typed
+    /// columns (all remaining rows if 0)
+    ///
+    /// Returns an empty batch once the file is exhausted.
+    pub fn next_columns(&mut self, max_rows: usize) -> Result<ColumnBatch, ParseError> {
+        let limit = if max_rows == 0 { usize::MAX } else { max_rows };
+        let headers = &self.headers;
+        let mut builder = BatchBuilder::new(|idx| &headers[idx], &self.column_indices, &self.options)?;
+
+        while builder.len() < limit && self.records.read(&self.file_path)? {
+            if self.filter.accepts(|idx| str_field(&self.records, idx)) {
+                builder
+                    .push_row(|idx| self.records.get(idx))
+                    .map_err(|err| self.row_error(err))?;
+            }
+        }
+
+        Ok(builder.take())
+    }
+
+    /// An error in the row just read, with its location
+    fn row_error(&self, err: ParseError) -> ParseError {
+        ParseError::new(
+            err.code,
+            format!("{}: row {}: {}", self.file_path, self.records.line(), err.message),
+        )
+    }
+}
+
+/// A field of the current record for the row filter, which compares text,
+/// or None if it is not valid UTF-8
+///
+/// Filters only look at a few columns, so the check is cheap.
+fn str_field(records: &RecordReader, idx: usize) -> Option<&str> {
+    records.get(idx).and_then(|field| std::str::from_utf8(field).ok())
+}
+
+/// Open a file of the given format, decompressing it as needed, and read
+/// its ECSV header, if any, and header row
+///
+/// Files in a headerless format use the format's built-in column names.
+pub(crate) fn open_csv(
+    file_path: &str,
+    format: FileFormat,
+    gzip_threads: usize,
+) -> Result<(CsvFileReader, StringRecord, Option<EcsvHeader>), ParseError> {
+    let (buf_reader, ecsv) = open_body(file_path, gzip_threads)?;
+    let delimiter = ecsv.as_ref().map_or(format.delimiter(), |ecsv| ecsv.delimiter);
+
+    let fixed_headers = format.fixed_headers();
+    let mut csv_reader = ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .delimiter(delimiter)
+        .has_headers(fixed_headers.is_none())
+        .from_reader(buf_reader);
+
+    let headers = match fixed_headers {
+        Some(names) => StringRecord::from(names),
+        None => csv_reader
+            .headers()
+            .map_err(|e| ParseError::from_csv(e, file_path, None))?
+            .clone(),
+    };
+
+    Ok((csv_reader, headers, ecsv))
+}
+
+/// `open_csv` for the faster `RecordReader`
+fn open_records(
+    file_path: &str,
+    format: FileFormat,
+    gzip_threads: usize,
+) -> Result<(RecordReader, StringRecord, Option<EcsvHeader>), ParseError> {
+    let (buf_reader, ecsv) = open_body(file_path, gzip_threads)?;
+    let delimiter = ecsv.as_ref().map_or(format.delimiter(), |ecsv| ecsv.delimiter);
+    let mut records = RecordReader::new(buf_reader, delimiter);
+
+    let headers = match format.fixed_headers() {
+        Some(names) => StringRecord::from(names),
+        None if records.read(file_path)? => {
+            let names = (0..records.len())
+                .map(|idx| std::str::from_utf8(records.get(idx).unwrap_or_default()))
+                .collect::<Result<Vec<&str>, _>>()
+                .map_err(|e| {
+                    ParseError::new(ErrorCode::CsvSyntax, format!("{}: header is not valid UTF-8: {}", file_path, e))
+                })?;
+            StringRecord::from(names)
+        }
+        None => StringRecord::new(),
+    };
+
+    Ok((records, headers, ecsv))
+}
+
+/// Open a file, decompressing it as needed, and read its ECSV header, if
+/// any, leaving the reader at the header row
+fn open_body(
+    file_path: &str,
+    gzip_threads: usize,
+) -> Result<(Box<dyn BufRead>, Option<EcsvHeader>), ParseError> {
+    let mut buf_reader = open_decompressed(file_path, gzip_threads)?;
+
+    let preamble = read_preamble(&mut buf_reader).map_err(|e| ParseError::from_io(e, file_path))?;
+    let ecsv = EcsvHeader::parse(&preamble, file_path)?;
+
+    Ok((buf_reader, ecsv))
+}
+
+/// Read the next record into the reusable buffer, returning false at EOF
+pub(crate) fn read_record<R: std::io::Read>(
+    csv_reader: &mut csv::Reader<R>,
+    record: &mut StringRecord,
+    file_path: &str,
+) -> Result<bool, ParseError> {
+    csv_reader.read_record(record).map_err(|e| {
+        let row = csv_reader.position().line();
+        ParseError::from_csv(e, file_path, Some(row))
+    })
+}
+
+/// Find the header index of each column to keep
+///
+/// Columns missing from the header are skipped unless `strict` is set, in
+/// which case they are all named in the error. `source` names the file or
+/// stream in error messages.
+pub(crate) fn resolve_columns<'a>(
+    headers: impl IntoIterator<Item = &'a str> + Clone,
+    columns_to_keep: &[String],
+    strict: bool,
+    source: &str,
+) -> Result<Vec<usize>, ParseError> {
+    let positions: Vec<Option<usize>> = columns_to_keep
+        .iter()
+        .map(|col| headers.clone().into_iter().position(|h| h == col))
+        .collect();
+
+    if strict {
+        let missing: Vec<&str> = columns_to_keep
+            .iter()
+            .zip(&positions)
+            .filter(|(_, position)| position.is_none())
+            .map(|(col, _)| col.as_str())
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(ParseError::new(
+                ErrorCode::MissingColumn,
+                format!("{}: missing columns: {}", source, missing.join(", ")),
+            ));
+        }
+    }
+
+    Ok(positions.into_iter().flatten().collect())
+}
+
+/// How one kept column is converted to JSON, resolved once per file
+///
+/// Columns with a declared type, from the file's ECSV header or the
+/// format's schema, are converted to it, with null fields becoming JSON
+/// nulls. 64-bit identifiers are written as exact integers, or as strings
+/// if `ids_as_strings` is set for consumers that parse JSON numbers as
+/// doubles.
+struct JsonColumn {
+    name: String,
+    declared: Option<GaiaType>,
+    id_as_string: bool,
+}
+
+impl JsonColumn {
+    fn new(header: &str, options: &ReaderOptions) -> Self {
+        let declared = options.declared_type(header);
+        Self {
+            name: header.to_string(),
+            declared,
+            id_as_string: declared == Some(GaiaType::Int64) && options.ids_as_strings && is_identifier(header),
+        }
+    }
+
+    /// Convert a raw CSV field to the column's JSON type
+    fn convert(&self, value: &str) -> Value {
+        let Some(gaia_type) = self.declared else {
+            return guess_value(value);
+        };
+
+        if is_null_field(value) {
+            return Value::Null;
+        }
+
+        match gaia_type {
+            GaiaType::Int64 if self.id_as_string => Value::String(value.to_string()),
+            GaiaType::Int64 => parse_i64(value.as_bytes()).map_or(Value::Null, |num| json!(num)),
+            GaiaType::Float => parse_float::<f64>(value.as_bytes()).map_or(Value::Null, |num| json!(num)),
+            GaiaType::Bool if value.eq_ignore_ascii_case("true") => Value::Bool(true),
+            GaiaType::Bool if value.eq_ignore_ascii_case("false") => Value::Bool(false),
+            GaiaType::Bool => Value::Null,
+            GaiaType::String => Value::String(value.to_string()),
+            // Null elements become JSON nulls, since JSON has no NaN
+            GaiaType::Float32Array | GaiaType::Float64Array => match array_elements(value) {
+                Some(elements) => elements
+                    .map(|element| parse_float::<f64>(element.as_bytes()).map_or(Value::Null, Value::from))
+                    .collect(),
+                None => Value::Null,
+            },
+        }
+    }
+}
+
+/// Guess the type of a field from a column outside the known schema
+fn guess_value(value: &str) -> Value {
+    if value.is_empty() {
+        return Value::String(value.to_string());
+    }
+
+    // Try to parse as number
+    match parse_float::<f64>(value.as_bytes()) {
+        Some(num) => json!(num),
+        None if is_null_field(value) => Value::Null,
+        None if value.eq_ignore_ascii_case("true") => Value::Bool(true),
+        None if value.eq_ignore_ascii_case("false") => Value::Bool(false),
+        None => Value::String(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columnar::ColumnType;
+    use crate::filter::{Band, MagnitudeCut};
+    use crate::schema::FileFormat;
+    use crate::test_support::{write_fixture, write_gz_fixture, SAMPLE_CSV, SAMPLE_ECSV, SAMPLE_TMASS_PSC};
+
+    #[test]
+    fn test_next_chunk_respects_max_rows() {
+        let path = write_gz_fixture("reader_chunks.csv.gz", SAMPLE_CSV);
+        let columns = vec!["source_id".to_string(), "ra".to_string()];
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+
+        assert_eq!(reader.next_chunk(2).unwrap().len(), 2);
+        assert_eq!(reader.next_chunk(2).unwrap().len(), 1);
+        assert!(reader.next_chunk(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_columns() {
+        let path = write_gz_fixture("reader_columns.csv.gz", SAMPLE_CSV);
+        let columns = vec![
+            "source_id".to_string(),
+            "phot_g_mean_flux".to_string(),
+        ];
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+
+        let batch = reader.next_columns(0).unwrap();
+        assert_eq!(batch.len, 3);
+        assert_eq!(batch.columns[0].column_type(), ColumnType::Int64);
+        assert_eq!(batch.columns[1].null_count, 1);
+
+        assert_eq!(reader.next_columns(0).unwrap().len, 0);
+    }
+
+    #[test]
+    fn test_magnitude_cut_drops_rows() {
+        let path = write_gz_fixture("reader_magnitude.csv.gz", SAMPLE_CSV);
+        let options = ReaderOptions {
+            columns: vec!["source_id".to_string()],
+            magnitude_cut: Some(MagnitudeCut {
+                limit: 16.0,
+                band: Band::G,
+                zeropoint: 25.6873668671,
+            }),
+            ..ReaderOptions::default()
+        };
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &options).unwrap();
+
+        // Row 2 has a null flux; the other two are brighter than G = 16
+        let records = reader.next_chunk(0).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["source_id"], json!(38655544960i64));
+    }
+
+    #[test]
+    fn test_tmass_psc_format() {
+        let path = write_gz_fixture("reader_psc.gz", SAMPLE_TMASS_PSC);
+        let options = ReaderOptions {
+            format: FileFormat::TmassPsc,
+            columns: vec!["designation".to_string(), "j_m".to_string(), "ph_qual".to_string()],
+            strict_columns: true,
+            ..ReaderOptions::default()
+        };
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &options).unwrap();
+
+        let batch = reader.next_columns(0).unwrap();
+        assert_eq!(batch.len, 3);
+        assert_eq!(batch.columns[0].column_type(), ColumnType::Utf8);
+        assert_eq!(batch.columns[1].column_type(), ColumnType::Float64);
+        assert_eq!(batch.columns[1].null_count, 1);
+        assert_eq!(batch.columns[2].null_count, 0);
+    }
+
+    #[test]
+    fn test_ecsv_header_types_columns() {
+        let path = write_gz_fixture("reader_ecsv.csv.gz", SAMPLE_ECSV);
+        let columns = vec!["source_id".to_string(), "spectraltype_esphs".to_string()];
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+
+        assert_eq!(reader.options().ecsv_column("ra").unwrap().ucd.as_deref(), Some("pos.eq.ra;meta.main"));
+        let records = reader.next_chunk(0).unwrap();
+        assert_eq!(records[0]["spectraltype_esphs"], json!("G"));
+
+        // A DR where ra was stored as an integer would be caught up front
+        let drifted = SAMPLE_ECSV.replace("name: ra, unit: deg, datatype: float64", "name: ra, unit: deg, datatype: int32");
+        let path = write_gz_fixture("reader_ecsv_drift.csv.gz", &drifted);
+        let strict = ReaderOptions {
+            strict_schema: true,
+            ..ReaderOptions::with_columns(&columns)
+        };
+        let err = GaiaCsvReader::open(path.to_str().unwrap(), &strict).err().unwrap();
+        assert_eq!(err.code, ErrorCode::SchemaMismatch);
+        assert!(err.message.ends_with("ra is int32, expected float64"));
+
+        let mislabelled = SAMPLE_ECSV.replace("spectraltype_esphs,has_rvs", "spectraltype,has_rvs");
+        let path = write_gz_fixture("reader_ecsv_names.csv.gz", &mislabelled);
+        let err = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).err().unwrap();
+        assert_eq!(err.code, ErrorCode::CsvSyntax);
+    }
+
+    #[test]
+    fn test_array_columns() {
+        let contents = r#"# %ECSV 1.0
+# ---
+# datatype:
+# - {name: source_id, datatype: int64}
+# - {name: bp_coefficients, datatype: string, subtype: 'float64[3]'}
+# - {name: flux, datatype: string, subtype: 'float32[null]', unit: W.m**-2.nm**-1}
+source_id,bp_coefficients,flux
+4295806720,"[1.25,-3.5e2,0.0]","[1.0,null]"
+34361129088,,"[]"
+"#;
+        let path = write_gz_fixture("reader_arrays.csv.gz", contents);
+        let columns = vec!["bp_coefficients".to_string(), "flux".to_string()];
+
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+        let batch = reader.next_columns(0).unwrap();
+        assert_eq!(batch.columns[0].column_type(), ColumnType::Float64List);
+        assert_eq!(batch.columns[0].null_count, 1);
+        assert_eq!(batch.columns[1].column_type(), ColumnType::Float32List);
+        assert_eq!(batch.columns[1].offsets(), Some(&[0, 2, 2][..]));
+
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+        let records = reader.next_chunk(0).unwrap();
+        assert_eq!(records[0]["bp_coefficients"], json!([1.25, -350.0, 0.0]));
+        assert_eq!(records[0]["flux"], json!([1.0, null]));
+        assert_eq!(records[1]["bp_coefficients"], Value::Null);
+        assert_eq!(records[1]["flux"], json!([]));
+    }
+
+    #[test]
+    fn test_open_errors_are_classified() {
+        let columns = vec!["source_id".to_string()];
+
+        let missing = GaiaCsvReader::open("/nonexistent/file.csv.gz", &ReaderOptions::with_columns(&columns));
+        assert_eq!(missing.err().unwrap().code, ErrorCode::Io);
+
+        let path = write_gz_fixture("reader_wrong_columns.csv.gz", SAMPLE_CSV);
+        let mut options = ReaderOptions::with_columns(&["sourceid".to_string()]);
+        let wrong = GaiaCsvReader::open(path.to_str().unwrap(), &options);
+        assert!(wrong.is_ok());
+
+        options.strict_columns = true;
+        let wrong = GaiaCsvReader::open(path.to_str().unwrap(), &options);
+        assert_eq!(wrong.err().unwrap().code, ErrorCode::MissingColumn);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let contents = b"source_id,phot_variable_flag,ra\n1,VARIABLE,\xff\n2,\xffVARIABLE,1.5\n";
+        let path = write_fixture("reader_utf8.csv", contents);
+        let columns = vec!["source_id".to_string(), "phot_variable_flag".to_string(), "ra".to_string()];
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+
+        // Bad bytes in a float column are a null, but in a text column an error
+        let err = reader.next_columns(0).unwrap_err();
+        assert_eq!(err.code, ErrorCode::CsvSyntax);
+        assert!(err.message.contains("row 3: phot_variable_flag is not valid UTF-8"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_strict_columns_names_missing() {
+        let headers = ["source_id", "ra", "dec"];
+        let columns = vec!["source_id".to_string(), "paralax".to_string(), "ra".to_string(), "pmra".to_string()];
+
+        assert_eq!(resolve_columns(headers, &columns, false, "a.csv.gz").unwrap(), vec![0, 1]);
+
+        let err = resolve_columns(headers, &columns, true, "a.csv.gz").unwrap_err();
+        assert_eq!(err.code, ErrorCode::MissingColumn);
+        assert_eq!(err.message, "a.csv.gz: missing columns: paralax, pmra");
+    }
+
+    #[test]
+    fn test_corrupt_gzip_reports_row() {
+        let path = write_gz_fixture("reader_corrupt.csv.gz", SAMPLE_CSV);
+        let mut bytes = std::fs::read(&path).unwrap();
+        // Break the CRC32 in the gzip trailer
+        let crc_offset = bytes.len() - 8;
+        bytes[crc_offset] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        let columns = vec!["source_id".to_string()];
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+        let err = reader.next_chunk(0).unwrap_err();
+
+        assert_eq!(err.code, ErrorCode::CorruptGzip);
+        assert!(err.message.contains("reader_corrupt.csv.gz: row "));
+    }
+
+    #[test]
+    fn test_truncated_gzip() {
+        let path = write_gz_fixture("reader_truncated.csv.gz", SAMPLE_CSV);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+        let columns = vec!["source_id".to_string()];
+        let mut reader = GaiaCsvReader::open(path.to_str().unwrap(), &ReaderOptions::with_columns(&columns)).unwrap();
+        let err = reader.next_chunk(0).unwrap_err();
+
+        assert_eq!(err.code, ErrorCode::Truncated);
+    }
+
+    fn convert_value(header: &str, value: &str, options: &ReaderOptions) -> Value {
+        JsonColumn::new(header, options).convert(value)
+    }
+
+    #[test]
+    fn test_convert_value() {
+        let gaia = ReaderOptions::default();
+        let ids_as_strings = ReaderOptions {
+            ids_as_strings: true,
+            ..ReaderOptions::default()
+        };
+
+        assert_eq!(convert_value("source_id", "6917529027641081856", &gaia), json!(6917529027641081856i64));
+        assert_eq!(convert_value("source_id", "4295806720", &ids_as_strings), json!("4295806720"));
+        assert_eq!(convert_value("random_index", "1297811957", &ids_as_strings), json!("1297811957"));
+        assert_eq!(convert_value("ra", "44.99", &gaia), json!(44.99));
+        assert_eq!(convert_value("ra", "null", &gaia), Value::Null);
+        assert_eq!(convert_value("ra", "", &gaia), Value::Null);
+        assert_eq!(convert_value("has_rvs", "False", &gaia), json!(false));
+        assert_eq!(convert_value("phot_variable_flag", "VARIABLE", &gaia), json!("VARIABLE"));
+        assert_eq!(convert_value("libname_gspphot", "MARCS", &gaia), json!("MARCS"));
+        assert_eq!(convert_value("phot_g_n_obs", "345", &gaia), json!(345));
+        assert_eq!(convert_value("other_table_column", "1e3", &gaia), json!(1000.0));
+
+        let tmass = ReaderOptions {
+            format: FileFormat::TmassPsc,
+            ..ReaderOptions::default()
+        };
+        assert_eq!(convert_value("j_m", "\\N", &tmass), Value::Null);
+        assert_eq!(convert_value("ph_qual", "AAA", &tmass), json!("AAA"));
+    }
+}
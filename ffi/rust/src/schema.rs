@@ -0,0 +1,340 @@
+use serde::Deserialize;
+use crate::columnar::ColumnType;
+
+/// Logical type of a catalogue column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GaiaType {
+    /// Integer columns, from flags stored as bytes up to 64-bit identifiers
+    Int64,
+    /// Single and double precision floats
+    Float,
+    Bool,
+    String,
+    /// Arrays of single precision floats, such as XP spectra coefficients
+    Float32Array,
+    /// Arrays of double precision floats
+    Float64Array,
+}
+
+impl GaiaType {
+    /// Physical column type used for columnar and Arrow output
+    pub fn column_type(self) -> ColumnType {
+        match self {
+            GaiaType::Int64 => ColumnType::Int64,
+            GaiaType::Float => ColumnType::Float64,
+            GaiaType::Bool => ColumnType::Bool,
+            GaiaType::String => ColumnType::Utf8,
+            GaiaType::Float32Array => ColumnType::Float32List,
+            GaiaType::Float64Array => ColumnType::Float64List,
+        }
+    }
+}
+
+/// Columns of the Gaia DR3 `gaia_source` table, in file order
+///
+/// Mirrors `gaiaColumns` in `src/types.ts`, with types taken from the DR3
+/// data model: `byte`/`short`/`int`/`long` map to `Int64`, `float`/`double`
+/// to `Float`, `boolean` to `Bool` and `char` to `String`.
+pub const GAIA_SOURCE: &[(&str, GaiaType)] = &[
+    ("solution_id", GaiaType::Int64),
+    ("designation", GaiaType::String),
+    ("source_id", GaiaType::Int64),
+    ("random_index", GaiaType::Int64),
+    ("ref_epoch", GaiaType::Float),
+    ("ra", GaiaType::Float),
+    ("ra_error", GaiaType::Float),
+    ("dec", GaiaType::Float),
+    ("dec_error", GaiaType::Float),
+    ("parallax", GaiaType::Float),
+    ("parallax_error", GaiaType::Float),
+    ("parallax_over_error", GaiaType::Float),
+    ("pm", GaiaType::Float),
+    ("pmra", GaiaType::Float),
+    ("pmra_error", GaiaType::Float),
+    ("pmdec", GaiaType::Float),
+    ("pmdec_error", GaiaType::Float),
+    ("ra_dec_corr", GaiaType::Float),
+    ("ra_parallax_corr", GaiaType::Float),
+    ("ra_pmra_corr", GaiaType::Float),
+    ("ra_pmdec_corr", GaiaType::Float),
+    ("dec_parallax_corr", GaiaType::Float),
+    ("dec_pmra_corr", GaiaType::Float),
+    ("dec_pmdec_corr", GaiaType::Float),
+    ("parallax_pmra_corr", GaiaType::Float),
+    ("parallax_pmdec_corr", GaiaType::Float),
+    ("pmra_pmdec_corr", GaiaType::Float),
+    ("astrometric_n_obs_al", GaiaType::Int64),
+    ("astrometric_n_obs_ac", GaiaType::Int64),
+    ("astrometric_n_good_obs_al", GaiaType::Int64),
+    ("astrometric_n_bad_obs_al", GaiaType::Int64),
+    ("astrometric_gof_al", GaiaType::Float),
+    ("astrometric_chi2_al", GaiaType::Float),
+    ("astrometric_excess_noise", GaiaType::Float),
+    ("astrometric_excess_noise_sig", GaiaType::Float),
+    ("astrometric_params_solved", GaiaType::Int64),
+    ("astrometric_primary_flag", GaiaType::Bool),
+    ("nu_eff_used_in_astrometry", GaiaType::Float),
+    ("pseudocolour", GaiaType::Float),
+    ("pseudocolour_error", GaiaType::Float),
+    ("ra_pseudocolour_corr", GaiaType::Float),
+    ("dec_pseudocolour_corr", GaiaType::Float),
+    ("parallax_pseudocolour_corr", GaiaType::Float),
+    ("pmra_pseudocolour_corr", GaiaType::Float),
+    ("pmdec_pseudocolour_corr", GaiaType::Float),
+    ("astrometric_matched_transits", GaiaType::Int64),
+    ("visibility_periods_used", GaiaType::Int64),
+    ("astrometric_sigma5d_max", GaiaType::Float),
+    ("matched_transits", GaiaType::Int64),
+    ("new_matched_transits", GaiaType::Int64),
+    ("matched_transits_removed", GaiaType::Int64),
+    ("ipd_gof_harmonic_amplitude", GaiaType::Float),
+    ("ipd_gof_harmonic_phase", GaiaType::Float),
+    ("ipd_frac_multi_peak", GaiaType::Int64),
+    ("ipd_frac_odd_win", GaiaType::Int64),
+    ("ruwe", GaiaType::Float),
+    ("scan_direction_strength_k1", GaiaType::Float),
+    ("scan_direction_strength_k2", GaiaType::Float),
+    ("scan_direction_strength_k3", GaiaType::Float),
+    ("scan_direction_strength_k4", GaiaType::Float),
+    ("scan_direction_mean_k1", GaiaType::Float),
+    ("scan_direction_mean_k2", GaiaType::Float),
+    ("scan_direction_mean_k3", GaiaType::Float),
+    ("scan_direction_mean_k4", GaiaType::Float),
+    ("duplicated_source", GaiaType::Bool),
+    ("phot_g_n_obs", GaiaType::Int64),
+    ("phot_g_mean_flux", GaiaType::Float),
+    ("phot_g_mean_flux_error", GaiaType::Float),
+    ("phot_g_mean_flux_over_error", GaiaType::Float),
+    ("phot_g_mean_mag", GaiaType::Float),
+    ("phot_bp_n_obs", GaiaType::Int64),
+    ("phot_bp_mean_flux", GaiaType::Float),
+    ("phot_bp_mean_flux_error", GaiaType::Float),
+    ("phot_bp_mean_flux_over_error", GaiaType::Float),
+    ("phot_bp_mean_mag", GaiaType::Float),
+    ("phot_rp_n_obs", GaiaType::Int64),
+    ("phot_rp_mean_flux", GaiaType::Float),
+    ("phot_rp_mean_flux_error", GaiaType::Float),
+    ("phot_rp_mean_flux_over_error", GaiaType::Float),
+    ("phot_rp_mean_mag", GaiaType::Float),
+    ("phot_bp_rp_excess_factor", GaiaType::Float),
+    ("phot_bp_n_contaminated_transits", GaiaType::Int64),
+    ("phot_bp_n_blended_transits", GaiaType::Int64),
+    ("phot_rp_n_contaminated_transits", GaiaType::Int64),
+    ("phot_rp_n_blended_transits", GaiaType::Int64),
+    ("phot_proc_mode", GaiaType::Int64),
+    ("bp_rp", GaiaType::Float),
+    ("bp_g", GaiaType::Float),
+    ("g_rp", GaiaType::Float),
+    ("radial_velocity", GaiaType::Float),
+    ("radial_velocity_error", GaiaType::Float),
+    ("rv_method_used", GaiaType::Int64),
+    ("rv_nb_transits", GaiaType::Int64),
+    ("rv_nb_deblended_transits", GaiaType::Int64),
+    ("rv_visibility_periods_used", GaiaType::Int64),
+    ("rv_expected_sig_to_noise", GaiaType::Float),
+    ("rv_renormalised_gof", GaiaType::Float),
+    ("rv_chisq_pvalue", GaiaType::Float),
+    ("rv_time_duration", GaiaType::Float),
+    ("rv_amplitude_robust", GaiaType::Float),
+    ("rv_template_teff", GaiaType::Float),
+    ("rv_template_logg", GaiaType::Float),
+    ("rv_template_fe_h", GaiaType::Float),
+    ("rv_atm_param_origin", GaiaType::Int64),
+    ("vbroad", GaiaType::Float),
+    ("vbroad_error", GaiaType::Float),
+    ("vbroad_nb_transits", GaiaType::Int64),
+    ("grvs_mag", GaiaType::Float),
+    ("grvs_mag_error", GaiaType::Float),
+    ("grvs_mag_nb_transits", GaiaType::Int64),
+    ("rvs_spec_sig_to_noise", GaiaType::Float),
+    ("phot_variable_flag", GaiaType::String),
+    ("l", GaiaType::Float),
+    ("b", GaiaType::Float),
+    ("ecl_lon", GaiaType::Float),
+    ("ecl_lat", GaiaType::Float),
+    ("in_qso_candidates", GaiaType::Bool),
+    ("in_galaxy_candidates", GaiaType::Bool),
+    ("non_single_star", GaiaType::Int64),
+    ("has_xp_continuous", GaiaType::Bool),
+    ("has_xp_sampled", GaiaType::Bool),
+    ("has_rvs", GaiaType::Bool),
+    ("has_epoch_photometry", GaiaType::Bool),
+    ("has_epoch_rv", GaiaType::Bool),
+    ("has_mcmc_gspphot", GaiaType::Bool),
+    ("has_mcmc_msc", GaiaType::Bool),
+    ("in_andromeda_survey", GaiaType::Bool),
+    ("classprob_dsc_combmod_quasar", GaiaType::Float),
+    ("classprob_dsc_combmod_galaxy", GaiaType::Float),
+    ("classprob_dsc_combmod_star", GaiaType::Float),
+    ("teff_gspphot", GaiaType::Float),
+    ("teff_gspphot_lower", GaiaType::Float),
+    ("teff_gspphot_upper", GaiaType::Float),
+    ("logg_gspphot", GaiaType::Float),
+    ("logg_gspphot_lower", GaiaType::Float),
+    ("logg_gspphot_upper", GaiaType::Float),
+    ("mh_gspphot", GaiaType::Float),
+    ("mh_gspphot_lower", GaiaType::Float),
+    ("mh_gspphot_upper", GaiaType::Float),
+    ("distance_gspphot", GaiaType::Float),
+    ("distance_gspphot_lower", GaiaType::Float),
+    ("distance_gspphot_upper", GaiaType::Float),
+    ("azero_gspphot", GaiaType::Float),
+    ("azero_gspphot_lower", GaiaType::Float),
+    ("azero_gspphot_upper", GaiaType::Float),
+    ("ag_gspphot", GaiaType::Float),
+    ("ag_gspphot_lower", GaiaType::Float),
+    ("ag_gspphot_upper", GaiaType::Float),
+    ("ebpminrp_gspphot", GaiaType::Float),
+    ("ebpminrp_gspphot_lower", GaiaType::Float),
+    ("ebpminrp_gspphot_upper", GaiaType::Float),
+    ("libname_gspphot", GaiaType::String),
+];
+
+/// Columns of the 2MASS All-Sky Point Source Catalog (`psc_*.gz`), in file order
+///
+/// The IRSA bulk files are pipe-separated with no header row, so these names
+/// are assigned by position. Types follow the PSC column descriptions.
+pub const TMASS_PSC: &[(&str, GaiaType)] = &[
+    ("ra", GaiaType::Float),
+    ("dec", GaiaType::Float),
+    ("err_maj", GaiaType::Float),
+    ("err_min", GaiaType::Float),
+    ("err_ang", GaiaType::Int64),
+    ("designation", GaiaType::String),
+    ("j_m", GaiaType::Float),
+    ("j_cmsig", GaiaType::Float),
+    ("j_msigcom", GaiaType::Float),
+    ("j_snr", GaiaType::Float),
+    ("h_m", GaiaType::Float),
+    ("h_cmsig", GaiaType::Float),
+    ("h_msigcom", GaiaType::Float),
+    ("h_snr", GaiaType::Float),
+    ("k_m", GaiaType::Float),
+    ("k_cmsig", GaiaType::Float),
+    ("k_msigcom", GaiaType::Float),
+    ("k_snr", GaiaType::Float),
+    ("ph_qual", GaiaType::String),
+    ("rd_flg", GaiaType::String),
+    ("bl_flg", GaiaType::String),
+    ("cc_flg", GaiaType::String),
+    ("ndet", GaiaType::String),
+    ("prox", GaiaType::Float),
+    ("pxpa", GaiaType::Int64),
+    ("pxcntr", GaiaType::Int64),
+    ("gal_contam", GaiaType::Int64),
+    ("mp_flg", GaiaType::Int64),
+    ("pts_key", GaiaType::Int64),
+    ("hemis", GaiaType::String),
+    ("date", GaiaType::String),
+    ("scan", GaiaType::Int64),
+    ("glon", GaiaType::Float),
+    ("glat", GaiaType::Float),
+    ("x_scan", GaiaType::Float),
+    ("jdate", GaiaType::Float),
+    ("j_psfchi", GaiaType::Float),
+    ("h_psfchi", GaiaType::Float),
+    ("k_psfchi", GaiaType::Float),
+    ("j_m_stdap", GaiaType::Float),
+    ("j_msig_stdap", GaiaType::Float),
+    ("h_m_stdap", GaiaType::Float),
+    ("h_msig_stdap", GaiaType::Float),
+    ("k_m_stdap", GaiaType::Float),
+    ("k_msig_stdap", GaiaType::Float),
+    ("dist_edge_ns", GaiaType::Int64),
+    ("dist_edge_ew", GaiaType::Int64),
+    ("dist_edge_flg", GaiaType::String),
+    ("dup_src", GaiaType::Int64),
+    ("use_src", GaiaType::Int64),
+    ("a", GaiaType::String),
+    ("dist_opt", GaiaType::Float),
+    ("phi_opt", GaiaType::Int64),
+    ("b_m_opt", GaiaType::Float),
+    ("vr_m_opt", GaiaType::Float),
+    ("nopt_mchs", GaiaType::Int64),
+    ("ext_key", GaiaType::Int64),
+    ("scan_key", GaiaType::Int64),
+    ("coadd_key", GaiaType::Int64),
+    ("coadd", GaiaType::Int64),
+];
+
+/// Layout of an input file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    /// Comma-separated Gaia archive CSV with `#` comment lines and a header row
+    #[default]
+    GaiaCsv,
+    /// Headerless pipe-separated 2MASS PSC file using `\N` for nulls
+    TmassPsc,
+}
+
+impl FileFormat {
+    pub fn delimiter(self) -> u8 {
+        match self {
+            FileFormat::GaiaCsv => b',',
+            FileFormat::TmassPsc => b'|',
+        }
+    }
+
+    /// Built-in column names for formats whose files have no header row
+    pub fn fixed_headers(self) -> Option<Vec<String>> {
+        match self {
+            FileFormat::GaiaCsv => None,
+            FileFormat::TmassPsc => {
+                Some(TMASS_PSC.iter().map(|(name, _)| name.to_string()).collect())
+            }
+        }
+    }
+
+    /// Look up the declared type of a column in this format's schema
+    pub fn declared_type(self, name: &str) -> Option<GaiaType> {
+        let schema = match self {
+            FileFormat::GaiaCsv => GAIA_SOURCE,
+            FileFormat::TmassPsc => TMASS_PSC,
+        };
+        lookup(schema, name)
+    }
+}
+
+/// 64-bit identifier columns that cannot be represented exactly as doubles
+pub const IDENTIFIER_COLUMNS: &[&str] = &["source_id", "solution_id", "random_index"];
+
+/// Whether a column holds a 64-bit identifier
+pub fn is_identifier(name: &str) -> bool {
+    IDENTIFIER_COLUMNS.contains(&name)
+}
+
+pub(crate) fn lookup(schema: &[(&str, GaiaType)], name: &str) -> Option<GaiaType> {
+    schema
+        .iter()
+        .find(|(column, _)| *column == name)
+        .map(|&(_, gaia_type)| gaia_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaia_source_types() {
+        assert_eq!(GAIA_SOURCE.len(), 152);
+        assert_eq!(FileFormat::GaiaCsv.declared_type("source_id"), Some(GaiaType::Int64));
+        assert_eq!(FileFormat::GaiaCsv.declared_type("phot_g_mean_flux"), Some(GaiaType::Float));
+        assert_eq!(FileFormat::GaiaCsv.declared_type("phot_variable_flag"), Some(GaiaType::String));
+        assert_eq!(FileFormat::GaiaCsv.declared_type("has_xp_continuous"), Some(GaiaType::Bool));
+        assert_eq!(FileFormat::GaiaCsv.declared_type("not_a_column"), None);
+    }
+
+    #[test]
+    fn test_tmass_psc_positions() {
+        // processTmassFile in src/utils.ts reads these by position
+        let headers = FileFormat::TmassPsc.fixed_headers().unwrap();
+        assert_eq!(headers.len(), 60);
+        assert_eq!(headers[5], "designation");
+        assert_eq!(headers[6], "j_m");
+        assert_eq!(headers[10], "h_m");
+        assert_eq!(headers[14], "k_m");
+        assert_eq!(FileFormat::TmassPsc.declared_type("ph_qual"), Some(GaiaType::String));
+        assert_eq!(FileFormat::GaiaCsv.declared_type("j_m"), None);
+    }
+}
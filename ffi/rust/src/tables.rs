@@ -0,0 +1,371 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::schema::{lookup, GaiaType, GAIA_SOURCE};
+
+/// Gaia DR3 tables the reader knows how to ingest
+///
+/// Each profile has a schema, the columns kept when none are requested, and
+/// the key column that joins its rows to `gaia_source`. Profiles other than
+/// `gaia_source` type the commonly used columns of their table, with types
+/// from the DR3 data model; any other column is typed by the file's ECSV
+/// header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableProfile {
+    GaiaSource,
+    AstrophysicalParameters,
+    VariSummary,
+    NssTwoBodyOrbit,
+    QsoCandidates,
+}
+
+impl TableProfile {
+    pub const ALL: [TableProfile; 5] = [
+        TableProfile::GaiaSource,
+        TableProfile::AstrophysicalParameters,
+        TableProfile::VariSummary,
+        TableProfile::NssTwoBodyOrbit,
+        TableProfile::QsoCandidates,
+    ];
+
+    /// Archive table name
+    pub fn name(self) -> &'static str {
+        match self {
+            TableProfile::GaiaSource => "gaia_source",
+            TableProfile::AstrophysicalParameters => "astrophysical_parameters",
+            TableProfile::VariSummary => "vari_summary",
+            TableProfile::NssTwoBodyOrbit => "nss_two_body_orbit",
+            TableProfile::QsoCandidates => "qso_candidates",
+        }
+    }
+
+    /// Local table the profile's files are ingested into: `gaiadr3` for
+    /// `gaia_source`, and the archive name for the others
+    pub fn local_table(self) -> &'static str {
+        match self {
+            TableProfile::GaiaSource => "gaiadr3",
+            other => other.name(),
+        }
+    }
+
+    /// Table recording which files of the profile have been ingested
+    pub fn tracking_table(self) -> &'static str {
+        match self {
+            TableProfile::GaiaSource => "file_tracking_gaiadr3",
+            TableProfile::AstrophysicalParameters => "file_tracking_astrophysical_parameters",
+            TableProfile::VariSummary => "file_tracking_vari_summary",
+            TableProfile::NssTwoBodyOrbit => "file_tracking_nss_two_body_orbit",
+            TableProfile::QsoCandidates => "file_tracking_qso_candidates",
+        }
+    }
+
+    pub fn schema(self) -> &'static [(&'static str, GaiaType)] {
+        match self {
+            TableProfile::GaiaSource => GAIA_SOURCE,
+            TableProfile::AstrophysicalParameters => ASTROPHYSICAL_PARAMETERS,
+            TableProfile::VariSummary => VARI_SUMMARY,
+            TableProfile::NssTwoBodyOrbit => NSS_TWO_BODY_ORBIT,
+            TableProfile::QsoCandidates => QSO_CANDIDATES,
+        }
+    }
+
+    /// Columns kept when a reader requests none
+    pub fn default_columns(self) -> &'static [&'static str] {
+        match self {
+            // Mirrors the default `storedColumns` in `src/config.ts`
+            TableProfile::GaiaSource => &[
+                "source_id",
+                "ra",
+                "dec",
+                "parallax",
+                "pmra",
+                "pmdec",
+                "radial_velocity",
+                "phot_g_mean_flux",
+                "phot_bp_mean_flux",
+                "phot_rp_mean_flux",
+                "teff_gspphot",
+                "logg_gspphot",
+                "mh_gspphot",
+            ],
+            TableProfile::AstrophysicalParameters => &[
+                "source_id",
+                "classprob_dsc_combmod_star",
+                "teff_gspphot",
+                "logg_gspphot",
+                "mh_gspphot",
+                "distance_gspphot",
+                "ag_gspphot",
+                "ebpminrp_gspphot",
+                "radius_flame",
+                "mass_flame",
+                "age_flame",
+            ],
+            TableProfile::VariSummary => &[
+                "source_id",
+                "num_selected_g_fov",
+                "mean_mag_g_fov",
+                "std_dev_mag_g_fov",
+                "range_mag_g_fov",
+                "in_vari_classification_result",
+            ],
+            TableProfile::NssTwoBodyOrbit => &[
+                "source_id",
+                "nss_solution_type",
+                "period",
+                "period_error",
+                "t_periastron",
+                "eccentricity",
+                "eccentricity_error",
+                "semi_amplitude_primary",
+                "mass_ratio",
+                "inclination",
+                "goodness_of_fit",
+                "significance",
+            ],
+            TableProfile::QsoCandidates => &[
+                "source_id",
+                "gaia_crf_source",
+                "classlabel_dsc",
+                "classprob_dsc_combmod_quasar",
+                "redshift_qsoc",
+                "redshift_qsoc_lower",
+                "redshift_qsoc_upper",
+                "flags_qsoc",
+                "host_galaxy_detected",
+            ],
+        }
+    }
+
+    /// Column joining rows of this table to `gaia_source`
+    pub fn key_column(self) -> &'static str {
+        "source_id"
+    }
+
+    /// Look up the declared type of a column in this table's schema
+    pub fn declared_type(self, name: &str) -> Option<GaiaType> {
+        lookup(self.schema(), name)
+    }
+
+    /// JSON description of the profile for FFI callers
+    pub fn describe(self) -> Value {
+        let columns: Vec<Value> = self
+            .schema()
+            .iter()
+            .map(|(name, gaia_type)| json!({ "name": name, "type": gaia_type.column_type().name() }))
+            .collect();
+
+        json!({
+            "name": self.name(),
+            "local_table": self.local_table(),
+            "key": self.key_column(),
+            "default_columns": self.default_columns(),
+            "columns": columns,
+        })
+    }
+}
+
+/// Commonly used columns of `astrophysical_parameters`
+const ASTROPHYSICAL_PARAMETERS: &[(&str, GaiaType)] = &[
+    ("solution_id", GaiaType::Int64),
+    ("source_id", GaiaType::Int64),
+    ("classprob_dsc_combmod_quasar", GaiaType::Float),
+    ("classprob_dsc_combmod_galaxy", GaiaType::Float),
+    ("classprob_dsc_combmod_star", GaiaType::Float),
+    ("classprob_dsc_combmod_whitedwarf", GaiaType::Float),
+    ("classprob_dsc_combmod_binarystar", GaiaType::Float),
+    ("teff_gspphot", GaiaType::Float),
+    ("teff_gspphot_lower", GaiaType::Float),
+    ("teff_gspphot_upper", GaiaType::Float),
+    ("logg_gspphot", GaiaType::Float),
+    ("logg_gspphot_lower", GaiaType::Float),
+    ("logg_gspphot_upper", GaiaType::Float),
+    ("mh_gspphot", GaiaType::Float),
+    ("mh_gspphot_lower", GaiaType::Float),
+    ("mh_gspphot_upper", GaiaType::Float),
+    ("distance_gspphot", GaiaType::Float),
+    ("distance_gspphot_lower", GaiaType::Float),
+    ("distance_gspphot_upper", GaiaType::Float),
+    ("azero_gspphot", GaiaType::Float),
+    ("ag_gspphot", GaiaType::Float),
+    ("abp_gspphot", GaiaType::Float),
+    ("arp_gspphot", GaiaType::Float),
+    ("ebpminrp_gspphot", GaiaType::Float),
+    ("mg_gspphot", GaiaType::Float),
+    ("radius_gspphot", GaiaType::Float),
+    ("logposterior_gspphot", GaiaType::Float),
+    ("mcmcaccept_gspphot", GaiaType::Float),
+    ("libname_gspphot", GaiaType::String),
+    ("teff_gspspec", GaiaType::Float),
+    ("logg_gspspec", GaiaType::Float),
+    ("mh_gspspec", GaiaType::Float),
+    ("alphafe_gspspec", GaiaType::Float),
+    ("fem_gspspec", GaiaType::Float),
+    ("flags_gspspec", GaiaType::String),
+    ("radius_flame", GaiaType::Float),
+    ("radius_flame_lower", GaiaType::Float),
+    ("radius_flame_upper", GaiaType::Float),
+    ("lum_flame", GaiaType::Float),
+    ("mass_flame", GaiaType::Float),
+    ("mass_flame_lower", GaiaType::Float),
+    ("mass_flame_upper", GaiaType::Float),
+    ("age_flame", GaiaType::Float),
+    ("age_flame_lower", GaiaType::Float),
+    ("age_flame_upper", GaiaType::Float),
+    ("flags_flame", GaiaType::String),
+    ("evolstage_flame", GaiaType::Int64),
+    ("teff_esphs", GaiaType::Float),
+    ("logg_esphs", GaiaType::Float),
+    ("vsini_esphs", GaiaType::Float),
+    ("spectraltype_esphs", GaiaType::String),
+];
+
+/// Commonly used columns of `vari_summary`
+const VARI_SUMMARY: &[(&str, GaiaType)] = &[
+    ("solution_id", GaiaType::Int64),
+    ("source_id", GaiaType::Int64),
+    ("num_selected_g_fov", GaiaType::Int64),
+    ("num_selected_bp", GaiaType::Int64),
+    ("num_selected_rp", GaiaType::Int64),
+    ("mean_obs_time_g_fov", GaiaType::Float),
+    ("time_duration_g_fov", GaiaType::Float),
+    ("min_mag_g_fov", GaiaType::Float),
+    ("max_mag_g_fov", GaiaType::Float),
+    ("mean_mag_g_fov", GaiaType::Float),
+    ("median_mag_g_fov", GaiaType::Float),
+    ("range_mag_g_fov", GaiaType::Float),
+    ("trimmed_range_mag_g_fov", GaiaType::Float),
+    ("std_dev_mag_g_fov", GaiaType::Float),
+    ("skewness_mag_g_fov", GaiaType::Float),
+    ("kurtosis_mag_g_fov", GaiaType::Float),
+    ("mad_mag_g_fov", GaiaType::Float),
+    ("abbe_mag_g_fov", GaiaType::Float),
+    ("iqr_mag_g_fov", GaiaType::Float),
+    ("stetson_mag_g_fov", GaiaType::Float),
+    ("std_dev_over_rms_err_mag_g_fov", GaiaType::Float),
+    ("outlier_median_g_fov", GaiaType::Float),
+    ("mean_mag_bp", GaiaType::Float),
+    ("std_dev_mag_bp", GaiaType::Float),
+    ("mean_mag_rp", GaiaType::Float),
+    ("std_dev_mag_rp", GaiaType::Float),
+    ("in_vari_classification_result", GaiaType::Bool),
+    ("in_vari_rrlyrae", GaiaType::Bool),
+    ("in_vari_cepheid", GaiaType::Bool),
+    ("in_vari_planetary_transit", GaiaType::Bool),
+    ("in_vari_short_timescale", GaiaType::Bool),
+    ("in_vari_long_period_variable", GaiaType::Bool),
+    ("in_vari_eclipsing_binary", GaiaType::Bool),
+    ("in_vari_rotation_modulation", GaiaType::Bool),
+    ("in_vari_ms_oscillator", GaiaType::Bool),
+    ("in_vari_agn", GaiaType::Bool),
+    ("in_vari_microlensing", GaiaType::Bool),
+    ("in_vari_compact_companion", GaiaType::Bool),
+];
+
+/// Commonly used columns of `nss_two_body_orbit`
+const NSS_TWO_BODY_ORBIT: &[(&str, GaiaType)] = &[
+    ("solution_id", GaiaType::Int64),
+    ("source_id", GaiaType::Int64),
+    ("nss_solution_type", GaiaType::String),
+    ("ra", GaiaType::Float),
+    ("dec", GaiaType::Float),
+    ("parallax", GaiaType::Float),
+    ("parallax_error", GaiaType::Float),
+    ("pmra", GaiaType::Float),
+    ("pmdec", GaiaType::Float),
+    ("a_thiele_innes", GaiaType::Float),
+    ("b_thiele_innes", GaiaType::Float),
+    ("f_thiele_innes", GaiaType::Float),
+    ("g_thiele_innes", GaiaType::Float),
+    ("c_thiele_innes", GaiaType::Float),
+    ("h_thiele_innes", GaiaType::Float),
+    ("period", GaiaType::Float),
+    ("period_error", GaiaType::Float),
+    ("t_periastron", GaiaType::Float),
+    ("t_periastron_error", GaiaType::Float),
+    ("eccentricity", GaiaType::Float),
+    ("eccentricity_error", GaiaType::Float),
+    ("center_of_mass_velocity", GaiaType::Float),
+    ("center_of_mass_velocity_error", GaiaType::Float),
+    ("semi_amplitude_primary", GaiaType::Float),
+    ("semi_amplitude_primary_error", GaiaType::Float),
+    ("semi_amplitude_secondary", GaiaType::Float),
+    ("semi_amplitude_secondary_error", GaiaType::Float),
+    ("mass_ratio", GaiaType::Float),
+    ("mass_ratio_error", GaiaType::Float),
+    ("inclination", GaiaType::Float),
+    ("inclination_error", GaiaType::Float),
+    ("arg_periastron", GaiaType::Float),
+    ("arg_periastron_error", GaiaType::Float),
+    ("bit_index", GaiaType::Int64),
+    ("corr_vec", GaiaType::Float32Array),
+    ("obj_func", GaiaType::Float),
+    ("goodness_of_fit", GaiaType::Float),
+    ("efficiency", GaiaType::Float),
+    ("significance", GaiaType::Float),
+    ("flags", GaiaType::Int64),
+];
+
+/// Commonly used columns of `qso_candidates`
+const QSO_CANDIDATES: &[(&str, GaiaType)] = &[
+    ("solution_id", GaiaType::Int64),
+    ("source_id", GaiaType::Int64),
+    ("astrometric_selection_flag", GaiaType::Bool),
+    ("gaia_crf_source", GaiaType::Bool),
+    ("vari_best_class_name", GaiaType::String),
+    ("vari_best_class_score", GaiaType::Float),
+    ("fractional_variability_g", GaiaType::Float),
+    ("structure_function_index", GaiaType::Float),
+    ("qso_variability", GaiaType::Float),
+    ("non_qso_variability", GaiaType::Float),
+    ("vari_agn_membership_score", GaiaType::Float),
+    ("classlabel_dsc", GaiaType::String),
+    ("classlabel_dsc_joint", GaiaType::String),
+    ("classlabel_oa", GaiaType::String),
+    ("redshift_qsoc", GaiaType::Float),
+    ("redshift_qsoc_lower", GaiaType::Float),
+    ("redshift_qsoc_upper", GaiaType::Float),
+    ("ccfratio_qsoc", GaiaType::Float),
+    ("zscore_qsoc", GaiaType::Float),
+    ("flags_qsoc", GaiaType::Int64),
+    ("n_transits", GaiaType::Int64),
+    ("intensity_quasar_oa", GaiaType::Float),
+    ("intensity_galaxy_oa", GaiaType::Float),
+    ("classprob_dsc_combmod_quasar", GaiaType::Float),
+    ("classprob_dsc_combmod_galaxy", GaiaType::Float),
+    ("host_galaxy_detected", GaiaType::Bool),
+    ("host_galaxy_flag", GaiaType::Int64),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_are_consistent() {
+        for profile in TableProfile::ALL {
+            let schema = profile.schema();
+            assert!(profile.declared_type(profile.key_column()).is_some(), "{}", profile.name());
+            assert_eq!(profile.default_columns()[0], profile.key_column());
+
+            for column in profile.default_columns() {
+                assert!(profile.declared_type(column).is_some(), "{}.{}", profile.name(), column);
+            }
+            for (idx, (name, _)) in schema.iter().enumerate() {
+                assert!(!schema[..idx].iter().any(|(other, _)| other == name), "{}.{}", profile.name(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        let vari = TableProfile::VariSummary.describe();
+        assert_eq!(vari["name"], "vari_summary");
+        assert_eq!(vari["local_table"], "vari_summary");
+        assert_eq!(TableProfile::GaiaSource.describe()["local_table"], "gaiadr3");
+        assert_eq!(vari["key"], "source_id");
+        assert_eq!(vari["columns"][1], json!({ "name": "source_id", "type": "int64" }));
+        assert_eq!(TableProfile::VariSummary.declared_type("in_vari_rrlyrae"), Some(GaiaType::Bool));
+        assert_eq!(TableProfile::NssTwoBodyOrbit.declared_type("corr_vec"), Some(GaiaType::Float32Array));
+    }
+}
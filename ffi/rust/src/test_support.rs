@@ -0,0 +1,95 @@
+use std::io::Write;
+use std::path::PathBuf;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// A small Gaia DR3 style CSV with a comment header and three rows
+pub const SAMPLE_CSV: &str = "\
+# %ECSV 1.0
+# ---
+solution_id,source_id,ra,dec,phot_g_mean_flux,phot_variable_flag,has_rvs
+1636148068921376768,4295806720,44.99615537864534,0.005615226341865997,12345.6789,NOT_AVAILABLE,False
+1636148068921376768,34361129088,45.00432028915398,0.021047763781174733,null,VARIABLE,True
+1636148068921376768,38655544960,45.004978371745516,0.019879675701858644,1583118.2,NOT_AVAILABLE,False
+";
+
+/// An astrophysical_parameters style CSV with a full ECSV header, including
+/// a column outside the `gaia_source` schema
+pub const SAMPLE_ECSV: &str = "\
+# %ECSV 1.0
+# ---
+# delimiter: ','
+# datatype:
+# - {name: source_id, datatype: int64, description: Unique source identifier, meta: {ucd: meta.id}}
+# - {name: ra, unit: deg, datatype: float64, description: 'Right ascension, ICRS', meta: {ucd: pos.eq.ra;meta.main}}
+# - {name: classprob_dsc_combmod_star, datatype: float32, description: Probability from DSC-Combmod of being a single star}
+# - {name: spectraltype_esphs, datatype: string, description: Spectral type from ESP-HS}
+# - {name: has_rvs, datatype: bool}
+# meta: {name: astrophysical_parameters}
+source_id,ra,classprob_dsc_combmod_star,spectraltype_esphs,has_rvs
+4295806720,44.99615537864534,0.99,G,False
+34361129088,45.00432028915398,null,A,True
+";
+
+/// A `tmasspscxsc_best_neighbour` style CSV; the last row has no 2MASS
+/// designation
+pub const SAMPLE_BEST_NEIGHBOUR: &str = "\
+# %ECSV 1.0
+source_id,clean_tmass_psc_xsc_oid,original_ext_source_id,angular_distance,xm_flag,number_of_mates,number_of_neighbours
+6917529027641081856,1,00000021-0510150,0.0771,1,0,1
+4295806720,2,00000049-1733487,0.1234,1,1,2
+34361129088,3,,0.5,1,0,1
+";
+
+/// Three rows of a 2MASS PSC bulk file; the last has no J or K detection
+pub const SAMPLE_TMASS_PSC: &str = r"0.000883|-5.170853|0.08|0.08|90|00000021-0510150|15.767|0.073|0.074|18.2|15.168|0.084|0.085|13.1|14.952|0.121|0.121|9.0|ABB|222|111|000|060605|8.9|323|1279805432|0|0|2193543|s|1998-10-17|51|86.456894|-65.080364|-145.6|2451103.7193|1.05|0.79|0.83|15.728|0.061|15.103|0.103|15.007|0.161|198|58|sw|0|1|U|\N|\N|\N|\N|0|\N|51|1224|266
+0.002071|-17.563566|0.06|0.06|45|00000049-1733487|13.245|0.027|0.029|186.6|12.831|0.026|0.027|127.0|12.779|0.030|0.031|81.4|AAA|222|111|000|666666|16.0|112|1280283457|0|0|2208742|s|1998-10-11|39|69.232766|-75.506003|99.9|2451097.6452|0.88|1.28|1.02|13.264|0.024|12.840|0.030|12.773|0.046|72|202|ne|0|1|U|\N|\N|\N|\N|0|\N|39|1264|317
+0.005|-5.2|0.1|0.1|0|00000120-0512000|\N|\N|\N|\N|16.1|0.2|0.2|5.0|\N|\N|\N|\N|UCU|020|010|000|000100|3.0|10|1|0|0|2193544|s|1998-10-17|51|86.4|-65.1|0.0|2451103.7|\N|1.0|\N|\N|\N|\N|\N|\N|\N|10|10|sw|0|1|0|\N|\N|\N|\N|0|\N|51|1224|266
+";
+
+/// `rows` lines of pseudo-random CSV that compress to many deflate blocks
+pub fn sample_rows(rows: usize) -> Vec<u8> {
+    let mut csv = Vec::new();
+    let mut state = 0x2545f4914f6cdd1du64;
+    for row in 0..rows {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        writeln!(
+            csv,
+            "{},{:.6},{:.6},{}",
+            4295806720 + row,
+            (state % 36000) as f64 / 100.0,
+            state as f64 / 1e19,
+            state % 7
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// A uniquely named path in the temp directory
+pub fn fixture_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gaia-csv-parser-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Write `contents` as-is to a uniquely named file in the temp directory
+pub fn write_fixture(name: &str, contents: &[u8]) -> PathBuf {
+    let path = fixture_path(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Compress `contents` as a single gzip member
+pub fn gzip_bytes(contents: &str) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(contents.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Write `contents` gzipped to a uniquely named file in the temp directory
+pub fn write_gz_fixture(name: &str, contents: &str) -> PathBuf {
+    write_fixture(name, &gzip_bytes(contents))
+}
@@ -0,0 +1,180 @@
+use std::cmp::Ordering;
+use serde::Deserialize;
+use crate::db::Database;
+use crate::error::{ErrorCode, ParseError};
+
+/// Options for writing 2MASS photometry, passed over FFI as JSON
+///
+/// ```json
+/// { "with_errors": true, "with_quality_flags": true }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TmassOptions {
+    /// Also store `j_msigcom`, `h_msigcom` and `k_msigcom`, the total
+    /// photometric uncertainty of each magnitude
+    pub with_errors: bool,
+    /// Also store the `ph_qual`, `rd_flg`, `bl_flg` and `cc_flg` quality
+    /// flags
+    pub with_quality_flags: bool,
+}
+
+impl TmassOptions {
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        serde_json::from_str(json).map_err(|e| {
+            ParseError::new(
+                ErrorCode::InvalidOptionsJson,
+                format!("invalid 2MASS options: {}", e),
+            )
+        })
+    }
+
+    /// PSC columns stored in `tmass` after the designation, which is
+    /// stored as `tmass_source_id`
+    pub fn columns(&self) -> Vec<&'static str> {
+        let mut columns = vec!["j_m", "h_m", "k_m"];
+        if self.with_errors {
+            columns.extend(["j_msigcom", "h_msigcom", "k_msigcom"]);
+        }
+        if self.with_quality_flags {
+            columns.extend(["ph_qual", "rd_flg", "bl_flg", "cc_flg"]);
+        }
+        columns
+    }
+}
+
+/// The `tmass_xmatch` table as a map from 2MASS designation to the Gaia
+/// sources matched to it
+///
+/// PSC designations (`hhmmssss±ddmmsss`) are packed into a `u64` and kept
+/// with their Gaia `source_id` in one sorted array, 16 bytes per pair.
+/// Anything else is kept as a string in a second, usually empty, array.
+#[derive(Debug, Default)]
+pub struct TmassXmatchIndex {
+    packed: Vec<(u64, u64)>,
+    other: Vec<(Box<[u8]>, u64)>,
+}
+
+impl TmassXmatchIndex {
+    /// Read every pair from `tmass_xmatch`
+    ///
+    /// Pairs whose Gaia `source_id` is not a 64-bit integer are skipped.
+    pub fn load(db: &Database) -> Result<Self, ParseError> {
+        let mut statement = db
+            .conn
+            .prepare("SELECT gaiadr3_source_id, tmass_source_id FROM tmass_xmatch")
+            .map_err(|e| db.error(e))?;
+        let mut rows = statement.query([]).map_err(|e| db.error(e))?;
+
+        let mut index = Self::default();
+        while let Some(row) = rows.next().map_err(|e| db.error(e))? {
+            let source_id: String = row.get(0).map_err(|e| db.error(e))?;
+            let designation: String = row.get(1).map_err(|e| db.error(e))?;
+            if let Ok(source_id) = source_id.parse() {
+                index.push(designation.as_bytes(), source_id);
+            }
+        }
+
+        index.finish();
+        Ok(index)
+    }
+
+    fn push(&mut self, designation: &[u8], source_id: u64) {
+        match pack_designation(designation) {
+            Some(key) => self.packed.push((key, source_id)),
+            None => self.other.push((designation.into(), source_id)),
+        }
+    }
+
+    fn finish(&mut self) {
+        self.packed.sort_unstable();
+        self.packed.shrink_to_fit();
+        self.other.sort_unstable();
+        self.other.shrink_to_fit();
+    }
+
+    /// Gaia `source_id`s matched to a designation
+    pub fn source_ids<'a>(&'a self, designation: &[u8]) -> impl Iterator<Item = u64> + 'a {
+        let key = pack_designation(designation);
+        let packed = match key {
+            Some(key) => equal_range(&self.packed, |&(k, _)| k.cmp(&key)),
+            None => &[],
+        };
+        let other = match key {
+            Some(_) => &[],
+            None => equal_range(&self.other, |(d, _)| (**d).cmp(designation)),
+        };
+        packed.iter().map(|&(_, id)| id).chain(other.iter().map(|&(_, id)| id))
+    }
+
+    /// Number of pairs in the index
+    pub fn len(&self) -> usize {
+        self.packed.len() + self.other.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The run of a sorted slice that `cmp` finds equal
+fn equal_range<T>(items: &[T], cmp: impl Fn(&T) -> Ordering) -> &[T] {
+    let start = items.partition_point(|item| cmp(item) == Ordering::Less);
+    let len = items[start..].partition_point(|item| cmp(item) == Ordering::Equal);
+    &items[start..start + len]
+}
+
+/// Pack a `hhmmssss±ddmmsss` designation into its 15 digits and sign
+fn pack_designation(designation: &[u8]) -> Option<u64> {
+    if designation.len() != 16 {
+        return None;
+    }
+
+    let negative = match designation[8] {
+        b'+' => 0,
+        b'-' => 1,
+        _ => return None,
+    };
+
+    let mut key = 0u64;
+    for &byte in designation[..8].iter().chain(&designation[9..]) {
+        if !byte.is_ascii_digit() {
+            return None;
+        }
+        key = key * 10 + u64::from(byte - b'0');
+    }
+    Some(key * 2 + negative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_designation() {
+        let north = pack_designation(b"00000049+1733487").unwrap();
+        let south = pack_designation(b"00000049-1733487").unwrap();
+        assert_ne!(north, south);
+        assert_eq!(south, 491733487 * 2 + 1);
+
+        assert_eq!(pack_designation(b"00000049-173348"), None);
+        assert_eq!(pack_designation(b"0000004X-1733487"), None);
+        assert_eq!(pack_designation(b"00000049 1733487"), None);
+    }
+
+    #[test]
+    fn test_source_ids() {
+        let mut index = TmassXmatchIndex::default();
+        index.push(b"00000049-1733487", 2);
+        index.push(b"00000021-0510150", 1);
+        index.push(b"00000049-1733487", 3);
+        index.push(b"J0000", 4);
+        index.finish();
+
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.source_ids(b"00000049-1733487").collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(index.source_ids(b"00000021-0510150").collect::<Vec<_>>(), [1]);
+        assert_eq!(index.source_ids(b"J0000").collect::<Vec<_>>(), [4]);
+        assert_eq!(index.source_ids(b"00000049+1733487").count(), 0);
+    }
+}
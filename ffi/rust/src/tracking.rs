@@ -0,0 +1,272 @@
+use rusqlite::{params, OptionalExtension, Transaction};
+use crate::db::{quote_identifier, Database};
+use crate::error::{ErrorCode, ParseError};
+use crate::ingest::IngestCounts;
+use crate::tables::TableProfile;
+
+/// One of the tables recording which downloaded files have been ingested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingTable {
+    Gaiadr3,
+    TmassXmatch,
+    Tmass,
+    /// Files of a table profile other than `gaia_source`
+    Profile(TableProfile),
+}
+
+impl TrackingTable {
+    /// Tracking table for the files of `profile`
+    pub fn for_profile(profile: TableProfile) -> Self {
+        match profile {
+            TableProfile::GaiaSource => Self::Gaiadr3,
+            other => Self::Profile(other),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gaiadr3 => "file_tracking_gaiadr3",
+            Self::TmassXmatch => "file_tracking_tmass_xmatch",
+            Self::Tmass => "file_tracking_tmass",
+            Self::Profile(profile) => profile.tracking_table(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, ParseError> {
+        match name {
+            "file_tracking_gaiadr3" => Ok(Self::Gaiadr3),
+            "file_tracking_tmass_xmatch" => Ok(Self::TmassXmatch),
+            "file_tracking_tmass" => Ok(Self::Tmass),
+            _ => TableProfile::ALL
+                .into_iter()
+                .find(|profile| profile.tracking_table() == name)
+                .map(Self::for_profile)
+                .ok_or_else(|| {
+                    ParseError::new(ErrorCode::InvalidArgument, format!("unknown tracking table '{}'", name))
+                }),
+        }
+    }
+}
+
+/// Processing status of a tracked file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl FileStatus {
+    fn from_sql(status: &str) -> Self {
+        match status {
+            "completed" => Self::Completed,
+            "failed" => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+// Columns added to the `(url, status)` tables `GaiaDatabase` creates
+const COUNT_COLUMNS: [(&str, &str); 2] = [("row_count", "INTEGER"), ("inserted_count", "INTEGER")];
+
+impl Database {
+    /// Create a tracking table if it does not exist, or add the row count
+    /// columns to one created by `GaiaDatabase.initialize()`
+    pub fn create_tracking(&self, table: TrackingTable) -> Result<(), ParseError> {
+        let name = quote_identifier(table.name());
+        self.conn
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (url TEXT PRIMARY KEY, status TEXT DEFAULT 'pending', \
+                 row_count INTEGER, inserted_count INTEGER);",
+                name
+            ))
+            .map_err(|e| self.error(e))?;
+
+        self.add_missing_columns(table.name(), &COUNT_COLUMNS)
+    }
+
+    /// Add the row count columns to every existing tracking table
+    pub(crate) fn add_tracking_counts(&self) -> Result<(), ParseError> {
+        for table in [TrackingTable::Gaiadr3, TrackingTable::TmassXmatch, TrackingTable::Tmass] {
+            if !self.table_columns(table.name())?.is_empty() {
+                self.add_missing_columns(table.name(), &COUNT_COLUMNS)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `urls` as pending files, resetting any already tracked if
+    /// `overwrite` is set
+    pub fn initialize_tracking(
+        &mut self,
+        table: TrackingTable,
+        urls: &[String],
+        overwrite: bool,
+    ) -> Result<(), ParseError> {
+        self.create_tracking(table)?;
+
+        let verb = if overwrite { "INSERT OR REPLACE" } else { "INSERT OR IGNORE" };
+        let sql = format!("{} INTO {} (url, status) VALUES (?, 'pending')", verb, quote_identifier(table.name()));
+
+        let tx = self.conn.transaction().map_err(|e| ParseError::from_sqlite(e, &self.path))?;
+        let result = tx.prepare(&sql).and_then(|mut statement| {
+            for url in urls {
+                statement.execute([url])?;
+            }
+            Ok(())
+        });
+        result
+            .and_then(|()| tx.commit())
+            .map_err(|e| ParseError::from_sqlite(e, &self.path))
+    }
+
+    /// Status of a file, or None if it is not tracked
+    pub fn file_status(&self, table: TrackingTable, url: &str) -> Result<Option<FileStatus>, ParseError> {
+        let sql = format!("SELECT status FROM {} WHERE url = ?", quote_identifier(table.name()));
+        self.conn
+            .query_row(&sql, [url], |row| row.get::<_, Option<String>>(0))
+            .optional()
+            .map(|status| status.map(|s| FileStatus::from_sql(s.as_deref().unwrap_or("pending"))))
+            .map_err(|e| self.error(e))
+    }
+
+    /// Mark a file as failed
+    pub fn mark_file_failed(&self, table: TrackingTable, url: &str) -> Result<(), ParseError> {
+        let sql = format!("UPDATE {} SET status = 'failed' WHERE url = ?", quote_identifier(table.name()));
+        self.conn.execute(&sql, [url]).map(|_| ()).map_err(|e| self.error(e))
+    }
+
+    /// Write a file's rows with `write` and, when `url` is given, mark it
+    /// completed with their counts in the same transaction
+    ///
+    /// Nothing from a file that fails is kept, and the file is marked
+    /// failed instead.
+    pub(crate) fn ingest_file(
+        &mut self,
+        table: TrackingTable,
+        url: Option<&str>,
+        write: impl FnOnce(&Transaction) -> Result<IngestCounts, ParseError>,
+    ) -> Result<IngestCounts, ParseError> {
+        if url.is_some() {
+            self.create_tracking(table)?;
+        }
+
+        let path = self.path.clone();
+        let result = self
+            .conn
+            .transaction()
+            .map_err(|e| ParseError::from_sqlite(e, &path))
+            .and_then(|tx| {
+                let counts = write(&tx)?;
+                if let Some(url) = url {
+                    mark_completed(&tx, table, url, counts).map_err(|e| ParseError::from_sqlite(e, &path))?;
+                }
+                tx.commit().map_err(|e| ParseError::from_sqlite(e, &path))?;
+                Ok(counts)
+            });
+
+        if let (Err(_), Some(url)) = (&result, url) {
+            // The failure itself is the error worth reporting
+            let _ = self.mark_file_failed(table, url);
+        }
+        result
+    }
+}
+
+/// Mark a file completed with its row counts, adding it if it is not
+/// tracked yet
+fn mark_completed(tx: &Transaction, table: TrackingTable, url: &str, counts: IngestCounts) -> rusqlite::Result<()> {
+    let sql = format!(
+        "INSERT INTO {} (url, status, row_count, inserted_count) VALUES (?1, 'completed', ?2, ?3) \
+         ON CONFLICT(url) DO UPDATE SET status = 'completed', row_count = ?2, inserted_count = ?3",
+        quote_identifier(table.name())
+    );
+    tx.execute(&sql, params![url, counts.rows as i64, counts.inserted as i64]).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture_path;
+
+    fn fresh_db(name: &str) -> Database {
+        let path = fixture_path(name);
+        let _ = std::fs::remove_file(&path);
+        Database::open(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_upgrades_typescript_table() {
+        let db = fresh_db("tracking_upgrade.db");
+        db.conn
+            .execute_batch(
+                "CREATE TABLE file_tracking_tmass (url TEXT PRIMARY KEY, status TEXT DEFAULT 'pending');
+                 INSERT INTO file_tracking_tmass (url, status) VALUES ('a', 'completed');",
+            )
+            .unwrap();
+
+        db.create_tracking(TrackingTable::Tmass).unwrap();
+        db.create_tracking(TrackingTable::Tmass).unwrap();
+
+        assert_eq!(
+            db.table_columns("file_tracking_tmass").unwrap(),
+            ["url", "status", "row_count", "inserted_count"]
+        );
+        assert_eq!(db.file_status(TrackingTable::Tmass, "a").unwrap(), Some(FileStatus::Completed));
+    }
+
+    #[test]
+    fn test_table_names() {
+        for name in ["file_tracking_gaiadr3", "file_tracking_tmass", "file_tracking_vari_summary"] {
+            assert_eq!(TrackingTable::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(
+            TrackingTable::from_name("file_tracking_gaiadr3").unwrap(),
+            TrackingTable::for_profile(TableProfile::GaiaSource)
+        );
+        assert_eq!(TrackingTable::from_name("gaiadr3").unwrap_err().code, ErrorCode::InvalidArgument);
+    }
+
+    #[test]
+    fn test_ingest_file_is_atomic() {
+        let mut db = fresh_db("tracking_atomic.db");
+        let urls = vec!["ok".to_string(), "bad".to_string()];
+        db.initialize_tracking(TrackingTable::Gaiadr3, &urls, false).unwrap();
+        db.conn.execute_batch("CREATE TABLE t (id INTEGER)").unwrap();
+
+        let counts = db
+            .ingest_file(TrackingTable::Gaiadr3, Some("ok"), |tx| {
+                tx.execute_batch("INSERT INTO t VALUES (1)").unwrap();
+                Ok(IngestCounts { rows: 2, inserted: 1 })
+            })
+            .unwrap();
+        assert_eq!(counts, IngestCounts { rows: 2, inserted: 1 });
+
+        let err = db
+            .ingest_file(TrackingTable::Gaiadr3, Some("bad"), |tx| {
+                tx.execute_batch("INSERT INTO t VALUES (2)").unwrap();
+                Err(ParseError::new(ErrorCode::CsvSyntax, "bad row"))
+            })
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::CsvSyntax);
+
+        // Only the successful file's row and status were kept
+        let ids: i64 = db.conn.query_row("SELECT SUM(id) FROM t", [], |row| row.get(0)).unwrap();
+        assert_eq!(ids, 1);
+        let counts: (String, i64, i64) = db
+            .conn
+            .query_row(
+                "SELECT status, row_count, inserted_count FROM file_tracking_gaiadr3 WHERE url = 'ok'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(counts, ("completed".to_string(), 2, 1));
+        assert_eq!(db.file_status(TrackingTable::Gaiadr3, "bad").unwrap(), Some(FileStatus::Failed));
+        assert_eq!(db.file_status(TrackingTable::Gaiadr3, "other").unwrap(), None);
+
+        // Overwriting resets the status
+        db.initialize_tracking(TrackingTable::Gaiadr3, &urls, true).unwrap();
+        assert_eq!(db.file_status(TrackingTable::Gaiadr3, "ok").unwrap(), Some(FileStatus::Pending));
+    }
+}
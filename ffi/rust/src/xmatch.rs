@@ -0,0 +1,217 @@
+use csv::StringRecord;
+use serde::Deserialize;
+use crate::columnar::{fits_offsets, is_null_field, offset_overflow};
+use crate::error::{ErrorCode, ParseError};
+use crate::filter::RowFilter;
+use crate::options::ReaderOptions;
+use crate::reader::{open_csv, read_record, resolve_columns, CsvFileReader};
+use crate::schema::FileFormat;
+
+/// Options for reading a `*_best_neighbour` table, passed over FFI as JSON
+///
+/// ```json
+/// { "with_match_quality": true, "filter": "number_of_mates = 0" }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XmatchOptions {
+    /// Also return `angular_distance`, `number_of_neighbours` and
+    /// `number_of_mates` for each pair
+    pub with_match_quality: bool,
+    /// Keep only rows matching this expression (see `expr.rs`), e.g.
+    /// `number_of_neighbours = 1 AND number_of_mates = 0`
+    pub filter: Option<String>,
+}
+
+impl XmatchOptions {
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        serde_json::from_str(json).map_err(|e| {
+            ParseError::new(
+                ErrorCode::InvalidOptionsJson,
+                format!("invalid crossmatch options: {}", e),
+            )
+        })
+    }
+}
+
+/// Columns read from a best-neighbour table, in the order of `XmatchReader::indices`
+const XMATCH_COLUMNS: [&str; 5] = [
+    "source_id",
+    "original_ext_source_id",
+    "angular_distance",
+    "number_of_neighbours",
+    "number_of_mates",
+];
+
+/// Crossmatch pairs stored as packed arrays
+///
+/// `designations` holds the 2MASS designations back to back, delimited by
+/// `len + 1` offsets. The match quality arrays are empty unless requested;
+/// a null `angular_distance` is NaN and a null count is -1.
+#[derive(Debug)]
+pub struct XmatchBatch {
+    pub source_ids: Vec<u64>,
+    pub designation_offsets: Vec<i32>,
+    pub designations: Vec<u8>,
+    pub angular_distance: Vec<f64>,
+    pub number_of_neighbours: Vec<i32>,
+    pub number_of_mates: Vec<i32>,
+}
+
+impl XmatchBatch {
+    fn new() -> Self {
+        Self {
+            source_ids: Vec::new(),
+            designation_offsets: vec![0],
+            designations: Vec::new(),
+            angular_distance: Vec::new(),
+            number_of_neighbours: Vec::new(),
+            number_of_mates: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.source_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source_ids.is_empty()
+    }
+
+    /// Designation of the pair at `index`
+    pub fn designation(&self, index: usize) -> &str {
+        let start = self.designation_offsets[index] as usize;
+        let end = self.designation_offsets[index + 1] as usize;
+        // Designations are copied from UTF-8 records
+        std::str::from_utf8(&self.designations[start..end]).unwrap_or_default()
+    }
+}
+
+/// Reader for Gaia `*_best_neighbour` crossmatch tables
+///
+/// Yields (Gaia `source_id`, `original_ext_source_id`) pairs without
+/// building per-row objects. Rows missing either ID are skipped.
+pub struct XmatchReader {
+    file_path: String,
+    csv_reader: CsvFileReader,
+    // Header indices of XMATCH_COLUMNS; quality columns only if requested
+    indices: Vec<usize>,
+    filter: RowFilter,
+    record: StringRecord,
+}
+
+impl XmatchReader {
+    pub fn open(file_path: &str, options: &XmatchOptions) -> Result<Self, ParseError> {
+        let (csv_reader, headers, _) = open_csv(file_path, FileFormat::GaiaCsv, 0)?;
+
+        let wanted = if options.with_match_quality { 5 } else { 2 };
+        let columns: Vec<String> = XMATCH_COLUMNS[..wanted].iter().map(|c| c.to_string()).collect();
+        let indices = resolve_columns(&headers, &columns, true, file_path)?;
+
+        let filter_options = ReaderOptions {
+            filter: options.filter.clone(),
+            ..ReaderOptions::default()
+        };
+        let filter = RowFilter::compile(&headers, &filter_options, file_path)?;
+
+        Ok(Self {
+            file_path: file_path.to_string(),
+            csv_reader,
+            indices,
+            filter,
+            record: StringRecord::new(),
+        })
+    }
+
+    /// Read up to `max_pairs` pairs (all remaining pairs if 0)
+    ///
+    /// Returns an empty batch once the file is exhausted.
+    pub fn next_pairs(&mut self, max_pairs: usize) -> Result<XmatchBatch, ParseError> {
+        let limit = if max_pairs == 0 { usize::MAX } else { max_pairs };
+        let mut batch = XmatchBatch::new();
+
+        while batch.len() < limit && read_record(&mut self.csv_reader, &mut self.record, &self.file_path)? {
+            if !self.filter.accepts(|idx| self.record.get(idx)) {
+                continue;
+            }
+
+            let field = |column: usize| self.record.get(self.indices[column]).unwrap_or("");
+            let (source_id, designation) = (field(0), field(1));
+            if is_null_field(source_id) || is_null_field(designation) {
+                continue;
+            }
+
+            let source_id = source_id.parse::<u64>().map_err(|_| {
+                ParseError::new(
+                    ErrorCode::CsvSyntax,
+                    format!(
+                        "{}: row {}: invalid source_id {:?}",
+                        self.file_path,
+                        self.csv_reader.position().line(),
+                        source_id
+                    ),
+                )
+            })?;
+
+            if !fits_offsets(batch.designations.len(), designation.len()) {
+                return Err(offset_overflow("original_ext_source_id"));
+            }
+
+            batch.source_ids.push(source_id);
+            batch.designations.extend_from_slice(designation.as_bytes());
+            batch.designation_offsets.push(batch.designations.len() as i32);
+
+            if self.indices.len() > 2 {
+                batch.angular_distance.push(field(2).parse().unwrap_or(f64::NAN));
+                batch.number_of_neighbours.push(field(3).parse().unwrap_or(-1));
+                batch.number_of_mates.push(field(4).parse().unwrap_or(-1));
+            }
+        }
+
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{write_gz_fixture, SAMPLE_BEST_NEIGHBOUR};
+
+    #[test]
+    fn test_next_pairs() {
+        let path = write_gz_fixture("xmatch_pairs.csv.gz", SAMPLE_BEST_NEIGHBOUR);
+        let mut reader = XmatchReader::open(path.to_str().unwrap(), &XmatchOptions::default()).unwrap();
+
+        let batch = reader.next_pairs(0).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.source_ids[0], 6917529027641081856);
+        assert_eq!(batch.designation(1), "00000049-1733487");
+        assert!(batch.angular_distance.is_empty());
+
+        assert!(reader.next_pairs(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_match_quality_and_filter() {
+        let path = write_gz_fixture("xmatch_quality.csv.gz", SAMPLE_BEST_NEIGHBOUR);
+        let options = XmatchOptions::from_json(
+            r#"{"with_match_quality": true, "filter": "number_of_neighbours = 1 AND number_of_mates = 0"}"#,
+        )
+        .unwrap();
+        let mut reader = XmatchReader::open(path.to_str().unwrap(), &options).unwrap();
+
+        let batch = reader.next_pairs(0).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch.angular_distance, vec![0.0771]);
+        assert_eq!(batch.number_of_neighbours, vec![1]);
+        assert_eq!(batch.number_of_mates, vec![0]);
+    }
+
+    #[test]
+    fn test_requires_best_neighbour_columns() {
+        let path = write_gz_fixture("xmatch_wrong.csv.gz", "source_id,ra\n1,2\n");
+        let err = XmatchReader::open(path.to_str().unwrap(), &XmatchOptions::default()).err().unwrap();
+        assert_eq!(err.code, ErrorCode::MissingColumn);
+        assert!(err.message.contains("original_ext_source_id"));
+    }
+}
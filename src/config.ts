@@ -44,6 +44,11 @@ export interface CLIConfig {
    * @default 16
    */
   magnitudeLimit: number;
+  /**
+   * Row filter expression applied by the Rust parser, e.g.
+   * `parallax > 5 AND ruwe < 1.4` (requires useRustParser)
+   */
+  filter?: string;
   /**
    * The log level
    * @default "INFO"
@@ -62,6 +67,31 @@ export interface CLIConfig {
    * @default false
    */
   useRustParser: boolean;
+  /**
+   * Number of Rust threads that parse downloaded files in parallel in file
+   * mode; 0 uses one per core (requires useRustParser)
+   * @default 0
+   */
+  rustThreads: number;
+  /**
+   * Whether Rust writes downloaded Gaia, 2MASS crossmatch and 2MASS
+   * photometry files straight into SQLite instead of returning records to
+   * TypeScript (requires useRustParser, file mode)
+   * @default false
+   */
+  useRustIngest: boolean;
+  /**
+   * Whether to also store the 2MASS magnitude uncertainties `j_msigcom`,
+   * `h_msigcom` and `k_msigcom` (requires useRustIngest)
+   * @default false
+   */
+  tmassErrors: boolean;
+  /**
+   * Whether to also store the 2MASS quality flags `ph_qual`, `rd_flg`,
+   * `bl_flg` and `cc_flg` (requires useRustIngest)
+   * @default false
+   */
+  tmassQualityFlags: boolean;
 
   /**
    * Whether to use C FFI for CSV parsing (requires c-csv library)
@@ -97,6 +127,10 @@ export const DEFAULT_CONFIG: CLIConfig = {
   logLevel: "INFO",
   useStreaming: false,
   useRustParser: false,
+  rustThreads: 0,
+  useRustIngest: false,
+  tmassErrors: false,
+  tmassQualityFlags: false,
   useCParser: false,
 };
 
@@ -110,11 +144,16 @@ export function parseConfig(args: string[]): CLIConfig {
       "mag-limit",
       "download-dir",
       "csv-chunks",
+      "filter",
+      "rust-threads",
     ],
     boolean: [
       "clean",
       "stream",
       "rust-ffi",
+      "rust-ingest",
+      "tmass-errors",
+      "tmass-flags",
       "c-ffi",
     ],
     negatable: [
@@ -130,6 +169,9 @@ export function parseConfig(args: string[]): CLIConfig {
       "csv-chunks": DEFAULT_CONFIG.csvChunkSize,
       "stream": DEFAULT_CONFIG.useStreaming,
       "rust-ffi": DEFAULT_CONFIG.useRustParser,
+      "rust-ingest": DEFAULT_CONFIG.useRustIngest,
+      "tmass-errors": DEFAULT_CONFIG.tmassErrors,
+      "tmass-flags": DEFAULT_CONFIG.tmassQualityFlags,
       "c-ffi": DEFAULT_CONFIG.useCParser,
     },
     alias: {
@@ -152,11 +194,13 @@ export function parseConfig(args: string[]): CLIConfig {
     `${parsed["csv-chunks"]}`,
     DEFAULT_CONFIG.csvChunkSize,
   );
+  const useRustParser = parsed["rust-ffi"];
   const { valid, invalid } =
     (parsed.columns?.split(",") ?? DEFAULT_CONFIG.storedColumns).reduce(
       (acc: { valid: GaiaColumn[]; invalid: string[] }, column) => {
-        if (isGaiaColumn(column)) {
-          acc.valid.push(column);
+        // The Rust parser validates columns against the real file header
+        if (useRustParser || isGaiaColumn(column)) {
+          acc.valid.push(column as GaiaColumn);
         } else {
           acc.invalid.push(column);
         }
@@ -169,6 +213,31 @@ export function parseConfig(args: string[]): CLIConfig {
     throw new Error(`Invalid columns: ${invalid.join(", ")}`);
   }
 
+  if (parsed.filter !== undefined && !useRustParser) {
+    throw new Error("--filter requires --rust-ffi");
+  }
+
+  if (parsed["rust-threads"] !== undefined && !useRustParser) {
+    throw new Error("--rust-threads requires --rust-ffi");
+  }
+  const rustThreads = Math.max(
+    0,
+    getNumber(parsed["rust-threads"], DEFAULT_CONFIG.rustThreads),
+  );
+
+  const useRustIngest = parsed["rust-ingest"];
+  if (useRustIngest && !useRustParser) {
+    throw new Error("--rust-ingest requires --rust-ffi");
+  }
+  if (useRustIngest && parsed["stream"]) {
+    throw new Error("--rust-ingest cannot be combined with --stream");
+  }
+  for (const flag of ["tmass-errors", "tmass-flags"] as const) {
+    if (parsed[flag] && !useRustIngest) {
+      throw new Error(`--${flag} requires --rust-ingest`);
+    }
+  }
+
   const logLevel = parsed["log-level"].toUpperCase();
   if (!isLogLevel(logLevel)) {
     throw new Error(`Invalid log level: ${logLevel}`);
@@ -201,12 +270,17 @@ export function parseConfig(args: string[]): CLIConfig {
     downloadDir: parsed["download-dir"],
     cleanUpDownloadedFiles: parsed["clean"],
     magnitudeLimit,
+    filter: parsed.filter,
     csvChunkSize,
     logLevel,
     storedColumns: valid,
     zeropoints: DEFAULT_CONFIG.zeropoints,
     useStreaming,
-    useRustParser: parsed["rust-ffi"],
+    useRustParser,
+    rustThreads,
+    useRustIngest,
+    tmassErrors: parsed["tmass-errors"],
+    tmassQualityFlags: parsed["tmass-flags"],
     useCParser: parsed["c-ffi"],
   };
 
@@ -231,11 +305,16 @@ Options:
   --csv-chunks      The amount of rows to process at a time from the CSV file. (default: 100000)
   --db-path         Path to SQLite database (default: ./gaiaoffline.db)
   --file-limit      Limit number of files to download (for testing)
+  --filter          Row filter for the Rust parser, e.g. "parallax > 5 AND ruwe < 1.4" (requires --rust-ffi)
   -l, --log-level   Log level: DEBUG, INFO, WARN, ERROR (default: INFO)
   --no-clean        Don't clean up downloaded files after processing
   -m, --mag-limit   Magnitude limit for filtering (default: 16)
   -p, --parallel    Number of parallel downloads (default: 10, max: 50)
   --rust            Use Rust FFI for CSV parsing (2-4x faster, requires --allow-ffi)
+  --rust-threads    Rust threads that parse downloaded files in parallel (default: 0, one per core; requires --rust-ffi)
+  --rust-ingest     Write downloaded Gaia, crossmatch and 2MASS files into SQLite from Rust, skipping TypeScript records (requires --rust-ffi, not with --stream)
+  --tmass-errors    Also store 2MASS magnitude uncertainties j_msigcom, h_msigcom, k_msigcom (requires --rust-ingest)
+  --tmass-flags     Also store 2MASS quality flags ph_qual, rd_flg, bl_flg, cc_flg (requires --rust-ingest)
   --c               Use C FFI for CSV parsing (4-5x faster, fastest option, requires --allow-ffi)
   --stream          Process files while downloading (faster but uses more RAM)
 
@@ -252,6 +331,9 @@ Examples:
   # Populate 2MASS photometry (run after populating crossmatch)
   gaiaoffline populate:tmass
 
+  # Only keep nearby, well-behaved sources in the southern sky
+  gaiaoffline populate --rust-ffi --filter "parallax > 5 AND ruwe < 1.4 AND dec < -30"
+
   # Test with only 2 files using C FFI parser
   gaiaoffline populate --file-limit 2 --c
   `);
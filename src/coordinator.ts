@@ -4,6 +4,7 @@ import {
   createLogger,
   formatBytes,
   formatDuration,
+  isCorruptGzipError,
   processTmassFile,
   processTmassXmatchFile,
   streamAndFilterCSV,
@@ -12,6 +13,9 @@ import type { CLIConfig } from "./config.ts";
 import { Logger } from "./types.ts";
 import type { DownloadProgress } from "./downloader.ts";
 
+// Data release of the gaia_source files, recorded in gaiaoffline_meta
+const GAIA_DATA_RELEASE = "DR3";
+
 export interface PopulateStats {
   totalFiles: number;
   completedFiles: number;
@@ -36,6 +40,7 @@ export class PopulateCoordinator {
   };
   private logger: Logger;
   private interval: number = 0;
+  private columnsValidated = false;
 
   constructor(db: GaiaDatabase, config: CLIConfig) {
     this.db = db;
@@ -57,7 +62,21 @@ export class PopulateCoordinator {
     const startTime = Date.now();
 
     await this.downloader.initialize();
-    this.db.initialize();
+    // Refuses to append with other settings before downloading anything
+    this.db.initialize({
+      storedColumns: this.config.storedColumns,
+      magnitudeLimit: this.config.magnitudeLimit,
+      zeropoints: this.config.zeropoints,
+      dataRelease: GAIA_DATA_RELEASE,
+    });
+
+    if (this.config.useRustParser) {
+      // Rust also migrates the database and records its parser version
+      const { initDatabaseMetaFromConfigRust } = await import(
+        "./utils-rust.ts"
+      );
+      initDatabaseMetaFromConfigRust(this.config, GAIA_DATA_RELEASE);
+    }
 
     this.logger.info("📋 Fetching list of Gaia DR3 files…");
     const allUrls = await getCSVUrls(
@@ -69,7 +88,13 @@ export class PopulateCoordinator {
 
     this.logger.debug(`Found ${totalFiles} files to process…`);
 
-    this.db.initializeTracking("file_tracking_gaiadr3", allUrls);
+    if (this.config.useRustIngest) {
+      // Rust owns the tracking table, adding its row count columns
+      const { initTrackingRust } = await import("./utils-rust.ts");
+      initTrackingRust("file_tracking_gaiadr3", allUrls, this.config);
+    } else {
+      this.db.initializeTracking("file_tracking_gaiadr3", allUrls);
+    }
 
     // Filter out already processed files
     let pendingUrls = allUrls.filter(
@@ -151,6 +176,11 @@ export class PopulateCoordinator {
         url: string;
         records: GaiaRecord[] | null;
         error: string | null;
+        /**
+         * Rows already written while the file was parsed (--rust-ingest, or
+         * --stream --rust-ffi)
+         */
+        inserted?: number;
       }>;
 
       if (this.config.useStreaming) {
@@ -182,6 +212,33 @@ export class PopulateCoordinator {
               return { url: streamResult.url, records: [], error: null };
             }
 
+            if (this.config.useRustParser) {
+              // Dynamically import Rust FFI only when needed
+              const { streamAndFilterCSVRust } = await import(
+                "./utils-rust.ts"
+              );
+
+              // Insert each chunk as it is parsed so a file is never held
+              // in memory whole
+              let inserted = 0;
+              for await (
+                const chunk of streamAndFilterCSVRust(
+                  { stream: streamResult.stream, url: streamResult.url },
+                  this.config,
+                )
+              ) {
+                inserted += this.db.insertGaiaRecords(chunk);
+              }
+              this.db.markFileCompleted(trackingTable, streamResult.url);
+
+              return {
+                url: streamResult.url,
+                records: null,
+                error: null,
+                inserted,
+              };
+            }
+
             const records = await streamAndFilterCSV(
               streamResult.stream,
               this.config,
@@ -218,6 +275,45 @@ export class PopulateCoordinator {
 
         clearInterval(this.interval);
 
+        // Fail fast on a column typo instead of failing every file
+        const firstDownload = downloadResults.find((result) => result.success);
+        if (
+          this.config.useRustParser && !this.columnsValidated && firstDownload
+        ) {
+          const { validateColumnsRust } = await import("./utils-rust.ts");
+          validateColumnsRust(
+            firstDownload.filePath,
+            this.config.storedColumns,
+          );
+          this.columnsValidated = true;
+        }
+
+        // Parse every pending file in one call on the Rust thread pool,
+        // rather than one blocking FFI call per file
+        let rustResults: Map<string, GaiaRecord[] | Error> | null = null;
+        if (
+          this.config.useRustParser && !this.config.useCParser &&
+          !this.config.useRustIngest
+        ) {
+          const pendingPaths = downloadResults
+            .filter((result) =>
+              result.success &&
+              !this.db.isFileProcessed(trackingTable, result.url)
+            )
+            .map((result) => result.filePath);
+
+          if (pendingPaths.length > 0) {
+            const { parseFilesRust } = await import("./utils-rust.ts");
+            const parseStartTime = Date.now();
+            rustResults = await parseFilesRust(pendingPaths, this.config);
+            this.logger.info(
+              `Parsed ${pendingPaths.length} files in Rust in ${
+                Date.now() - parseStartTime
+              }ms`,
+            );
+          }
+        }
+
         // Process files in parallel (read from disk)
         const processPromises = downloadResults.map(async (result) => {
           if (!result.success) {
@@ -242,15 +338,29 @@ export class PopulateCoordinator {
               // Dynamically import C FFI only when needed (fastest option)
               const { streamAndFilterCSVC } = await import("./utils-c.ts");
               records = await streamAndFilterCSVC(result.filePath, this.config);
-            } else if (this.config.useRustParser) {
-              // Dynamically import Rust FFI only when needed
-              const { streamAndFilterCSVRust } = await import(
-                "./utils-rust.ts"
-              );
-              records = await streamAndFilterCSVRust(
+            } else if (this.config.useRustIngest) {
+              // Rust writes the rows and marks the file completed itself,
+              // so no records come back
+              const { ingestGaiaFileRust } = await import("./utils-rust.ts");
+              const inserted = ingestGaiaFileRust(
                 result.filePath,
+                result.url,
                 this.config,
               );
+
+              this.logger.info(
+                `${result.url} ingested in ${Date.now() - csvStartTime}ms`,
+              );
+              await this.cleanUpDownload(result.filePath);
+
+              return { url: result.url, records: null, error: null, inserted };
+            } else if (this.config.useRustParser) {
+              // Already parsed in the batch above
+              const parsed = rustResults?.get(result.filePath);
+              if (parsed instanceof Error) {
+                throw parsed;
+              }
+              records = parsed ?? [];
             } else {
               records = await streamAndFilterCSV(result.filePath, this.config);
             }
@@ -259,14 +369,7 @@ export class PopulateCoordinator {
               `${result.url} processed in ${Date.now() - csvStartTime}ms`,
             );
 
-            // Clean up file if configured
-            if (this.config.cleanUpDownloadedFiles) {
-              try {
-                await Deno.remove(result.filePath);
-              } catch {
-                // Ignore cleanup errors
-              }
-            }
+            await this.cleanUpDownload(result.filePath);
 
             return { url: result.url, records, error: null };
           } catch (error) {
@@ -274,8 +377,8 @@ export class PopulateCoordinator {
               ? error.message
               : String(error);
 
-            // If gzip is corrupt, delete the file
-            if (errorMessage.includes("corrupt gzip stream")) {
+            // If gzip is corrupt or truncated, delete the file
+            if (isCorruptGzipError(error)) {
               try {
                 await Deno.remove(result.filePath);
               } catch {
@@ -302,7 +405,11 @@ export class PopulateCoordinator {
       const networkErrors: string[] = [];
 
       for (const result of processResults) {
-        if (result.records) {
+        if (result.inserted !== undefined) {
+          // Already inserted and marked completed while it was parsed
+          this.stats.completedFiles++;
+          this.stats.totalRecords += result.inserted;
+        } else if (result.records) {
           allRecords.push(...result.records);
         } else if (result.error) {
           this.stats.failedFiles++;
@@ -363,6 +470,19 @@ export class PopulateCoordinator {
     }
   }
 
+  /**
+   * Delete a processed download if configured
+   */
+  private async cleanUpDownload(filePath: string): Promise<void> {
+    if (this.config.cleanUpDownloadedFiles) {
+      try {
+        await Deno.remove(filePath);
+      } catch {
+        // Ignore cleanup errors
+      }
+    }
+  }
+
   /**
    * Print final summary
    */
@@ -418,7 +538,12 @@ export class PopulateCoordinator {
 
     this.logger.debug(`Found ${totalFiles} files to process…`);
 
-    this.db.initializeTracking("file_tracking_tmass_xmatch", allUrls);
+    if (this.config.useRustIngest) {
+      const { initTrackingRust } = await import("./utils-rust.ts");
+      initTrackingRust("file_tracking_tmass_xmatch", allUrls, this.config);
+    } else {
+      this.db.initializeTracking("file_tracking_tmass_xmatch", allUrls);
+    }
 
     // Filter out already processed files
     let pendingUrls = allUrls.filter(
@@ -487,7 +612,12 @@ export class PopulateCoordinator {
 
     this.logger.debug(`Found ${totalFiles} files to process…`);
 
-    this.db.initializeTracking("file_tracking_tmass", filteredUrls);
+    if (this.config.useRustIngest) {
+      const { initTrackingRust } = await import("./utils-rust.ts");
+      initTrackingRust("file_tracking_tmass", filteredUrls, this.config);
+    } else {
+      this.db.initializeTracking("file_tracking_tmass", filteredUrls);
+    }
 
     // Filter out already processed files
     let pendingUrls = filteredUrls.filter(
@@ -522,6 +652,10 @@ export class PopulateCoordinator {
     urls: string[],
     trackingTable: string,
   ): Promise<void> {
+    if (this.config.useRustIngest) {
+      return this.ingestTmassCrossmatchRust(urls, trackingTable);
+    }
+
     const batchSize = this.config.maxParallelDownloads;
     const totalBatches = Math.ceil(urls.length / batchSize);
 
@@ -546,12 +680,27 @@ export class PopulateCoordinator {
         }
 
         try {
+          // Parse with Rust FFI when enabled, otherwise TypeScript
+          let potentialRecords;
+          if (
+            this.config.useRustParser &&
+            !this.db.isFileProcessed(trackingTable, result.url)
+          ) {
+            const { parseTmassXmatchRust } = await import("./utils-rust.ts");
+            potentialRecords = await parseTmassXmatchRust(
+              result.filePath,
+              undefined,
+              this.config.csvChunkSize,
+            );
+          }
+
           const processResult = await processTmassXmatchFile(
             result.filePath,
             result.url,
             this.db,
             this.config,
             trackingTable,
+            potentialRecords,
           );
 
           if (processResult.success) {
@@ -588,6 +737,136 @@ export class PopulateCoordinator {
     }
   }
 
+  /**
+   * Ingest 2MASS crossmatch files in batches from Rust, filtering every
+   * file against the Gaia source IDs loaded once up front
+   */
+  private async ingestTmassCrossmatchRust(
+    urls: string[],
+    trackingTable: string,
+  ): Promise<void> {
+    const { freeGaiaIdsRust, ingestTmassXmatchFileRust, loadGaiaIdsRust } =
+      await import("./utils-rust.ts");
+
+    const loadStartTime = Date.now();
+    const ids = loadGaiaIdsRust(this.config);
+    this.logger.info(
+      `Loaded ${ids.size.toLocaleString()} Gaia source IDs in ${
+        formatDuration(Date.now() - loadStartTime)
+      }`,
+    );
+
+    try {
+      await this.ingestDownloadsRust(
+        urls,
+        trackingTable,
+        (filePath, url) =>
+          ingestTmassXmatchFileRust(filePath, url, ids, this.config),
+      );
+    } finally {
+      freeGaiaIdsRust(ids);
+    }
+  }
+
+  /**
+   * Ingest 2MASS photometry files in batches from Rust, joining every file
+   * against the crossmatch loaded once up front
+   */
+  private async ingestTmassRust(
+    urls: string[],
+    trackingTable: string,
+  ): Promise<void> {
+    const { freeTmassIndexRust, ingestTmassFileRust, loadTmassIndexRust } =
+      await import("./utils-rust.ts");
+
+    const loadStartTime = Date.now();
+    const index = loadTmassIndexRust(this.config);
+    this.logger.info(
+      `Loaded ${index.size.toLocaleString()} 2MASS crossmatch pairs in ${
+        formatDuration(Date.now() - loadStartTime)
+      }`,
+    );
+
+    try {
+      await this.ingestDownloadsRust(
+        urls,
+        trackingTable,
+        (filePath, url) =>
+          ingestTmassFileRust(filePath, url, index, this.config),
+      );
+    } finally {
+      freeTmassIndexRust(index);
+    }
+  }
+
+  /**
+   * Download files in batches and hand each one to `ingestFile`, which
+   * writes it and its "completed" status in Rust and returns the number of
+   * new rows
+   */
+  private async ingestDownloadsRust(
+    urls: string[],
+    trackingTable: string,
+    ingestFile: (filePath: string, url: string) => number,
+  ): Promise<void> {
+    const batchSize = this.config.maxParallelDownloads;
+    const totalBatches = Math.ceil(urls.length / batchSize);
+
+    for (let i = 0; i < urls.length; i += batchSize) {
+      const batchUrls = urls.slice(i, i + batchSize);
+      const batchNum = Math.floor(i / batchSize) + 1;
+
+      this.logger.info(
+        `📦 Downloading batch ${batchNum}/${totalBatches} (${batchUrls.length} files)`,
+      );
+
+      const downloadResults = await this.downloader.downloadBatch(batchUrls);
+
+      for (const result of downloadResults) {
+        if (!result.success) {
+          this.stats.failedFiles++;
+          this.logger.error(
+            `❌ Failed to download ${result.url}: ${result.error}`,
+          );
+          continue;
+        }
+
+        try {
+          if (this.db.isFileProcessed(trackingTable, result.url)) {
+            this.logger.debug(`Skipping already processed: ${result.url}`);
+            continue;
+          }
+
+          const inserted = ingestFile(result.filePath, result.url);
+
+          this.stats.completedFiles++;
+          this.stats.totalRecords += inserted;
+          this.logger.info(`✅ Processed ${result.url}: ${inserted} records`);
+        } catch (error) {
+          this.stats.failedFiles++;
+          this.logger.error(
+            `❌ Failed to process ${result.url}: ${
+              error instanceof Error ? error.message : String(error)
+            }`,
+          );
+        } finally {
+          await this.cleanUpDownload(result.filePath);
+        }
+      }
+
+      const progress = this.db.getTrackingProgress(trackingTable);
+      const percentage = progress.total > 0
+        ? (progress.completed / progress.total) * 100
+        : 0;
+
+      this.logger.info(
+        `Progress: ${progress.completed}/${progress.total} (${
+          percentage.toFixed(1)
+        }%) | Records: ${this.stats.totalRecords.toLocaleString()}\n`,
+      );
+    }
+  }
+
   /**
    * Process 2MASS photometry files in batches
    */
@@ -595,6 +874,10 @@ export class PopulateCoordinator {
     urls: string[],
     trackingTable: string,
   ): Promise<void> {
+    if (this.config.useRustIngest) {
+      return this.ingestTmassRust(urls, trackingTable);
+    }
+
     const batchSize = this.config.maxParallelDownloads;
     const totalBatches = Math.ceil(urls.length / batchSize);
 
@@ -619,11 +902,25 @@ export class PopulateCoordinator {
         }
 
         try {
+          // Parse with Rust FFI when enabled, otherwise TypeScript
+          let potentialRecords;
+          if (
+            this.config.useRustParser &&
+            !this.db.isFileProcessed(trackingTable, result.url)
+          ) {
+            const { parseTmassPscRust } = await import("./utils-rust.ts");
+            potentialRecords = await parseTmassPscRust(
+              result.filePath,
+              this.config.csvChunkSize,
+            );
+          }
+
           const processResult = await processTmassFile(
             result.filePath,
             result.url,
             this.db,
             trackingTable,
+            potentialRecords,
           );
 
           if (processResult.success) {
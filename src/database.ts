@@ -27,6 +27,31 @@ export interface TmassRecord {
   h_m: number | null;
   k_m: number | null;
 }
+/**
+ * Settings recorded in `gaiaoffline_meta` when the database is populated;
+ * `schema_version` and `parser_version` are only written by the Rust
+ * library under `--rust-ffi`
+ */
+export interface DatabaseMeta {
+  schema_version?: string;
+  parser_version?: string;
+  stored_columns?: string;
+  magnitude_limit?: string;
+  zeropoints?: string;
+  data_release?: string;
+}
+
+/**
+ * Settings that determine what a database holds, checked and recorded by
+ * `GaiaDatabase.initialize()`
+ */
+export interface DatasetSettings {
+  storedColumns: string[];
+  magnitudeLimit: number;
+  zeropoints: number[];
+  dataRelease: string;
+}
+
 export interface TrackingProgress {
   total: number;
   completed: number;
@@ -51,9 +76,10 @@ export class GaiaDatabase {
   }
 
   /**
-   * Initialize database schema
+   * Initialize database schema, and record `settings` in
+   * `gaiaoffline_meta` if given (see `recordSettings`)
    */
-  initialize(): void {
+  initialize(settings?: DatasetSettings): void {
     // Create main Gaia table
     const columnDefs = this.config.storedColumns
       .map((col) => {
@@ -93,6 +119,83 @@ export class GaiaDatabase {
     this.createTrackingTable("file_tracking_gaiadr3");
     this.createTrackingTable("file_tracking_tmass_xmatch");
     this.createTrackingTable("file_tracking_tmass");
+
+    if (settings) {
+      this.recordSettings(settings);
+    }
+  }
+
+  /**
+   * Record the settings the database is populated with in
+   * `gaiaoffline_meta`, in the format the Rust library uses, throwing if it
+   * was populated with different ones. A database populated before the
+   * table existed is checked against its `gaiadr3` columns.
+   */
+  private recordSettings(settings: DatasetSettings): void {
+    this.db.exec(`
+      CREATE TABLE IF NOT EXISTS gaiaoffline_meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+      );
+    `);
+
+    const meta: DatabaseMeta = this.getMeta() ?? {};
+    const recordedColumns = meta.stored_columns
+      ? JSON.parse(meta.stored_columns) as string[]
+      : this.getTableColumns("gaiadr3");
+    if (
+      recordedColumns.length > 0 &&
+      !sameColumns(recordedColumns, settings.storedColumns)
+    ) {
+      throw new Error(
+        `${this.config.databasePath}: gaiadr3 stores columns ${
+          recordedColumns.join(",")
+        } but ${settings.storedColumns.join(",")} were requested`,
+      );
+    }
+
+    const values: Record<string, string> = {
+      stored_columns: JSON.stringify(settings.storedColumns),
+      magnitude_limit: JSON.stringify(settings.magnitudeLimit),
+      zeropoints: JSON.stringify(settings.zeropoints),
+      data_release: settings.dataRelease,
+    };
+
+    // Numbers are compared by value, as the Rust library records 16 as 16.0
+    const normalize = (key: string, value: string) =>
+      key === "data_release" ? value : JSON.stringify(JSON.parse(value));
+    for (
+      const key of ["magnitude_limit", "zeropoints", "data_release"] as const
+    ) {
+      const recorded = meta[key];
+      if (recorded !== undefined && normalize(key, recorded) !== values[key]) {
+        throw new Error(
+          `${this.config.databasePath}: populated with ${key} ${recorded} but ${
+            values[key]
+          } was requested`,
+        );
+      }
+    }
+
+    const insert = this.db.prepare(
+      `INSERT OR REPLACE INTO gaiaoffline_meta (key, value) VALUES (?, ?)`,
+    );
+    this.db.transaction(() => {
+      for (const [key, value] of Object.entries(values)) {
+        insert.run(key, value);
+      }
+    })();
+    insert.finalize();
+  }
+
+  /**
+   * Names of the columns of a table, empty if it does not exist
+   */
+  private getTableColumns(tableName: string): string[] {
+    const rows = this.db.prepare(`PRAGMA table_info(${tableName})`).all() as {
+      name: string;
+    }[];
+    return rows.map((row) => row.name);
   }
 
   /**
@@ -288,6 +391,26 @@ export class GaiaDatabase {
     return result !== undefined;
   }
 
+  /**
+   * Read `gaiaoffline_meta` as raw key/value strings, or null if the
+   * database has none
+   */
+  getMeta(): DatabaseMeta | null {
+    const table = this.db.prepare(
+      `SELECT name FROM sqlite_master WHERE type='table' AND name='gaiaoffline_meta'`,
+    ).get() as { name: string } | undefined;
+
+    if (table === undefined) {
+      return null;
+    }
+
+    const rows = this.db.prepare(
+      `SELECT key, value FROM gaiaoffline_meta`,
+    ).all() as { key: string; value: string }[];
+
+    return Object.fromEntries(rows.map((row) => [row.key, row.value]));
+  }
+
   /**
    * Get database handle for direct queries (used by utils)
    */
@@ -439,3 +562,11 @@ export class GaiaDatabase {
     this.db.close();
   }
 }
+
+/**
+ * Whether two column lists hold the same columns in any order
+ */
+export function sameColumns(a: string[], b: string[]): boolean {
+  const set = new Set(a);
+  return a.length === b.length && b.every((column) => set.has(column));
+}
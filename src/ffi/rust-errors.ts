@@ -0,0 +1,38 @@
+/**
+ * Errors reported by the Rust parser, kept apart from the FFI bindings so
+ * they can be checked without loading those
+ */
+
+/**
+ * Error codes reported by the Rust parser (see `ErrorCode` in error.rs)
+ */
+export const RustErrorCode = {
+  InvalidArgument: 1,
+  InvalidOptionsJson: 2,
+  Io: 3,
+  CorruptGzip: 4,
+  CsvSyntax: 5,
+  MissingColumn: 6,
+  InvalidFilter: 7,
+  SchemaMismatch: 8,
+  UnsupportedCompression: 9,
+  Truncated: 10,
+  Database: 11,
+  OutputEncoding: 12,
+  SettingsMismatch: 13,
+} as const;
+
+export type RustErrorCode = (typeof RustErrorCode)[keyof typeof RustErrorCode];
+
+/**
+ * An error raised by the Rust parser, carrying its error code
+ */
+export class RustParseError extends Error {
+  readonly code: RustErrorCode;
+
+  constructor(code: RustErrorCode, message: string) {
+    super(message);
+    this.name = "RustParseError";
+    this.code = code;
+  }
+}
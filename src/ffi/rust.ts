@@ -4,6 +4,9 @@
  */
 
 import { join } from "@std/path";
+import { RustErrorCode, RustParseError } from "./rust-errors.ts";
+
+export { RustErrorCode, RustParseError };
 
 const libPath = Deno.build.os === "darwin"
   ? "./ffi/rust/target/release/libgaia_csv_parser.dylib"
@@ -11,18 +14,257 @@ const libPath = Deno.build.os === "darwin"
   ? "./ffi/rust/target/release/gaia_csv_parser.dll"
   : "./ffi/rust/target/release/libgaia_csv_parser.so";
 
-let lib:
-  | Deno.DynamicLibrary<{
-    parse_gzipped_csv: {
-      parameters: ["pointer", "pointer", "usize"];
-      result: "pointer";
-    };
-    free_string: {
-      parameters: ["pointer"];
-      result: "void";
-    };
-  }>
-  | null = null;
+const symbols = {
+  parse_gzipped_csv: {
+    parameters: ["pointer", "pointer", "usize"],
+    result: "pointer",
+  },
+  open_gzipped_csv: {
+    parameters: ["pointer", "pointer"],
+    result: "pointer",
+  },
+  open_csv_reader: {
+    parameters: ["pointer", "pointer"],
+    result: "pointer",
+  },
+  read_csv_header: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  read_csv_chunk: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  read_csv_columns: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  column_batch_len: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  column_batch_num_columns: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  column_batch_column_name: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  column_batch_column_type: {
+    parameters: ["pointer", "usize"],
+    result: "i32",
+  },
+  column_batch_values: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  column_batch_values_len: {
+    parameters: ["pointer", "usize"],
+    result: "usize",
+  },
+  column_batch_offsets: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  column_batch_validity: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  free_column_batch: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  close_csv_reader: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  parse_csv_files: {
+    parameters: ["pointer", "pointer", "usize"],
+    result: "pointer",
+    nonblocking: true,
+  },
+  file_batch_len: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  file_batch_error_code: {
+    parameters: ["pointer", "usize"],
+    result: "i32",
+  },
+  file_batch_error_message: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  file_batch_columns: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  free_file_batch: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  ingest_gaia_csv: {
+    parameters: [
+      "pointer",
+      "pointer",
+      "pointer",
+      "pointer",
+      "usize",
+      "buffer",
+      "buffer",
+    ],
+    result: "i32",
+  },
+  load_gaia_source_ids: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  source_id_set_len: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  free_source_id_set: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  ingest_tmass_xmatch: {
+    parameters: [
+      "pointer",
+      "pointer",
+      "pointer",
+      "pointer",
+      "pointer",
+      "usize",
+      "buffer",
+      "buffer",
+    ],
+    result: "i32",
+  },
+  load_tmass_xmatch_index: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  tmass_xmatch_index_len: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  free_tmass_xmatch_index: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  ingest_tmass_psc: {
+    parameters: [
+      "pointer",
+      "pointer",
+      "pointer",
+      "pointer",
+      "pointer",
+      "usize",
+      "buffer",
+      "buffer",
+    ],
+    result: "i32",
+  },
+  init_database_meta: {
+    parameters: ["pointer", "pointer"],
+    result: "i32",
+  },
+  read_database_meta: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  init_file_tracking: {
+    parameters: ["pointer", "pointer", "pointer", "bool"],
+    result: "i32",
+  },
+  create_csv_decoder: {
+    parameters: ["pointer", "pointer", "usize"],
+    result: "pointer",
+  },
+  feed_csv_decoder: {
+    parameters: ["pointer", "buffer", "usize"],
+    result: "i32",
+  },
+  finish_csv_decoder: {
+    parameters: ["pointer"],
+    result: "i32",
+  },
+  next_decoder_batch: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  free_csv_decoder: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  open_xmatch_reader: {
+    parameters: ["pointer", "pointer"],
+    result: "pointer",
+  },
+  read_xmatch_pairs: {
+    parameters: ["pointer", "usize"],
+    result: "pointer",
+  },
+  xmatch_batch_len: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  xmatch_batch_source_ids: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  xmatch_batch_designation_offsets: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  xmatch_batch_designations: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  xmatch_batch_designations_len: {
+    parameters: ["pointer"],
+    result: "usize",
+  },
+  xmatch_batch_angular_distance: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  xmatch_batch_number_of_neighbours: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  xmatch_batch_number_of_mates: {
+    parameters: ["pointer"],
+    result: "pointer",
+  },
+  free_xmatch_batch: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  close_xmatch_reader: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+  list_table_profiles: {
+    parameters: [],
+    result: "pointer",
+  },
+  last_error_code: {
+    parameters: [],
+    result: "i32",
+  },
+  last_error_message: {
+    parameters: [],
+    result: "pointer",
+  },
+  free_string: {
+    parameters: ["pointer"],
+    result: "void",
+  },
+} as const;
+
+let lib: Deno.DynamicLibrary<typeof symbols> | null = null;
 
 /**
  * Lazy-load the Rust library (only loads once)
@@ -30,21 +272,111 @@ let lib:
 function getRustLib() {
   if (!lib) {
     const fullPath = join(Deno.cwd(), "../../", libPath);
-    lib = Deno.dlopen(fullPath, {
-      parse_gzipped_csv: {
-        parameters: ["pointer", "pointer", "usize"],
-        result: "pointer",
-      },
-      free_string: {
-        parameters: ["pointer"],
-        result: "void",
-      },
-    });
+    lib = Deno.dlopen(fullPath, symbols);
   }
   return lib;
 }
 
 const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+/**
+ * Encode a string as a null-terminated C string
+ */
+function toCString(value: string): Uint8Array {
+  return encoder.encode(value + "\0");
+}
+
+/**
+ * Options for the columnar readers (see `ReaderOptions` in options.rs)
+ */
+export interface RustReaderOptions {
+  /**
+   * Input layout: a Gaia archive CSV, or a headerless pipe-separated 2MASS
+   * PSC file whose columns are named by the built-in PSC schema
+   * @default "gaia_csv"
+   */
+  format?: "gaia_csv" | "tmass_psc";
+  /** Gaia table profile supplying the schema (see `listTableProfilesRust`) */
+  table?: RustTableName;
+  /** Columns to keep, in output order (the profile's defaults if empty) */
+  columns: string[];
+  /**
+   * Return `source_id`, `solution_id` and `random_index` as utf8 columns
+   * instead of exact int64 columns
+   */
+  idsAsStrings?: boolean;
+  /** Fail naming every requested column missing from the file header */
+  strictColumns?: boolean;
+  /**
+   * Fail if the file's ECSV header declares a column type that differs from
+   * the built-in schema, e.g. a file from another data release
+   */
+  strictSchema?: boolean;
+  /** Drop rows fainter than `limit` in `band` before they are converted */
+  magnitudeCut?: {
+    limit: number;
+    band?: "G" | "BP" | "RP";
+    zeropoint: number;
+  };
+  /** Keep only rows matching a filter expression such as `dec < -30` */
+  filter?: string;
+  /**
+   * Inflate a gzip file on this many threads, holding it in memory; pays
+   * off for single large files on machines with spare cores
+   * @default 0 (single-threaded)
+   */
+  gzipThreads?: number;
+}
+
+/**
+ * Encode reader options as the JSON object expected by Rust
+ */
+function toOptionsCString(options: RustReaderOptions): Uint8Array {
+  return toCString(JSON.stringify({
+    format: options.format ?? "gaia_csv",
+    table: options.table ?? null,
+    columns: options.columns,
+    ids_as_strings: options.idsAsStrings ?? false,
+    strict_columns: options.strictColumns ?? false,
+    strict_schema: options.strictSchema ?? false,
+    magnitude_cut: options.magnitudeCut ?? null,
+    filter: options.filter ?? null,
+    gzip_threads: options.gzipThreads ?? 0,
+  }));
+}
+
+/**
+ * Read a Rust-allocated JSON string and free it
+ */
+function takeJson<T>(ptr: Deno.PointerObject): T {
+  const rustLib = getRustLib();
+  try {
+    return JSON.parse(new Deno.UnsafePointerView(ptr).getCString());
+  } finally {
+    rustLib.symbols.free_string(ptr);
+  }
+}
+
+/**
+ * Build an error from the last failed Rust call on this thread
+ */
+function lastRustError(fallbackMessage: string): RustParseError {
+  const rustLib = getRustLib();
+  const code = rustLib.symbols.last_error_code() as RustErrorCode;
+  const messagePtr = rustLib.symbols.last_error_message();
+
+  if (messagePtr === null) {
+    return new RustParseError(code, fallbackMessage);
+  }
+
+  try {
+    const message = new Deno.UnsafePointerView(messagePtr).getCString();
+    return new RustParseError(code, `${fallbackMessage}: ${message}`);
+  } finally {
+    rustLib.symbols.free_string(messagePtr);
+  }
+}
 
 /**
  * Parse a gzipped CSV file using Rust
@@ -55,39 +387,943 @@ export function parseGzippedCsvRust(
   chunkSize = 100000,
 ): Promise<Array<Record<string, unknown>>> {
   // Convert strings to C strings (null-terminated)
-  const filePathBytes = encoder.encode(filePath + "\0");
-  const columnsJson = JSON.stringify(columnsToKeep);
-  const columnsJsonBytes = encoder.encode(columnsJson + "\0");
-
-  // Allocate memory for the input strings
-  const filePathPtr = Deno.UnsafePointer.of(filePathBytes);
-  const columnsJsonPtr = Deno.UnsafePointer.of(columnsJsonBytes);
+  const filePathBytes = toCString(filePath);
+  const columnsJsonBytes = toCString(JSON.stringify(columnsToKeep));
 
   // Get the library (loads on first call)
   const rustLib = getRustLib();
 
   // Call Rust function
   const resultPtr = rustLib.symbols.parse_gzipped_csv(
-    filePathPtr,
-    columnsJsonPtr,
+    Deno.UnsafePointer.of(filePathBytes),
+    Deno.UnsafePointer.of(columnsJsonBytes),
     chunkSize,
   );
 
   if (resultPtr === null) {
-    throw new Error("Failed to parse CSV file in Rust");
+    throw lastRustError("Failed to parse CSV file in Rust");
+  }
+
+  return Promise.resolve(takeJson(resultPtr));
+}
+
+/**
+ * Gaia DR3 tables with a built-in profile (see `TableProfile` in tables.rs)
+ */
+export type RustTableName =
+  | "gaia_source"
+  | "astrophysical_parameters"
+  | "vari_summary"
+  | "nss_two_body_orbit"
+  | "qso_candidates";
+
+/**
+ * A built-in table profile: its schema, default columns and the key that
+ * joins it to `gaia_source`
+ */
+export interface RustTableProfile {
+  name: RustTableName;
+  /** Table `ingestGaiaCsvRust` writes the profile's rows to */
+  local_table: string;
+  key: string;
+  default_columns: string[];
+  columns: { name: string; type: RustColumn["type"] }[];
+}
+
+/**
+ * List the Gaia table profiles built into the Rust parser
+ */
+export function listTableProfilesRust(): RustTableProfile[] {
+  const resultPtr = getRustLib().symbols.list_table_profiles();
+
+  if (resultPtr === null) {
+    throw lastRustError("Failed to list table profiles in Rust");
+  }
+
+  return takeJson(resultPtr);
+}
+
+/**
+ * A column from a file header with the type it is read as (`null` for
+ * undeclared columns) and the metadata from the file's ECSV header, if any
+ */
+export interface RustHeaderColumn {
+  name: string;
+  type: RustColumn["type"] | null;
+  /** ECSV datatype such as `float32` or `int64` */
+  datatype: string | null;
+  unit: string | null;
+  ucd: string | null;
+  description: string | null;
+}
+
+/**
+ * Read the header of a gzipped CSV file using Rust without parsing any rows
+ */
+export function readCsvHeaderRust(filePath: string): RustHeaderColumn[] {
+  const filePathBytes = toCString(filePath);
+
+  const rustLib = getRustLib();
+
+  const resultPtr = rustLib.symbols.read_csv_header(
+    Deno.UnsafePointer.of(filePathBytes),
+  );
+
+  if (resultPtr === null) {
+    throw lastRustError("Failed to read CSV header in Rust");
+  }
+
+  return takeJson(resultPtr);
+}
+
+/**
+ * Stream a gzipped CSV file using Rust, yielding chunks of at most
+ * `chunkSize` records so only one chunk is held in memory at a time
+ */
+export async function* streamGzippedCsvRust(
+  filePath: string,
+  columnsToKeep: string[],
+  chunkSize = 100000,
+): AsyncGenerator<Array<Record<string, unknown>>> {
+  const filePathBytes = toCString(filePath);
+  const columnsJsonBytes = toCString(JSON.stringify(columnsToKeep));
+
+  const rustLib = getRustLib();
+
+  const reader = rustLib.symbols.open_gzipped_csv(
+    Deno.UnsafePointer.of(filePathBytes),
+    Deno.UnsafePointer.of(columnsJsonBytes),
+  );
+
+  if (reader === null) {
+    throw lastRustError("Failed to open CSV file in Rust");
+  }
+
+  try {
+    while (true) {
+      const chunkPtr = rustLib.symbols.read_csv_chunk(reader, chunkSize);
+
+      if (chunkPtr === null) {
+        throw lastRustError("Failed to read CSV chunk in Rust");
+      }
+
+      const records = takeJson<Array<Record<string, unknown>>>(chunkPtr);
+      if (records.length === 0) {
+        return;
+      }
+
+      yield records;
+    }
+  } finally {
+    rustLib.symbols.close_csv_reader(reader);
+  }
+}
+
+/**
+ * A typed column borrowed from a Rust column batch. Bit `i` of `validity`
+ * (LSB first) is set when row `i` is not null.
+ *
+ * Array-valued columns (XP and RVS spectra) hold the elements of every row
+ * back to back in `values`; row `i` is `values[offsets[i]..offsets[i + 1]]`
+ * and null elements are NaN.
+ */
+export type RustColumn =
+  | { name: string; type: "float64"; values: Float64Array; validity: Uint8Array }
+  | { name: string; type: "int64"; values: BigInt64Array; validity: Uint8Array }
+  | { name: string; type: "utf8"; values: string[]; validity: Uint8Array }
+  | { name: string; type: "bool"; values: boolean[]; validity: Uint8Array }
+  | {
+    name: string;
+    type: "float32[]";
+    offsets: Int32Array;
+    values: Float32Array;
+    validity: Uint8Array;
+  }
+  | {
+    name: string;
+    type: "float64[]";
+    offsets: Int32Array;
+    values: Float64Array;
+    validity: Uint8Array;
+  };
+
+export interface RustColumnBatch {
+  length: number;
+  columns: RustColumn[];
+}
+
+/**
+ * Whether row `index` of a column is not null
+ */
+export function isColumnValid(column: RustColumn, index: number): boolean {
+  return (column.validity[index >> 3] & (1 << (index & 7))) !== 0;
+}
+
+/**
+ * Elements of row `index` of an array-valued column
+ */
+export function listElements(
+  column: Extract<RustColumn, { offsets: Int32Array }>,
+  index: number,
+): Float32Array | Float64Array {
+  return column.values.subarray(column.offsets[index], column.offsets[index + 1]);
+}
+
+/**
+ * Wrap `byteLength` bytes of Rust memory without copying
+ */
+function borrowBuffer(
+  ptr: Deno.PointerValue,
+  byteLength: number,
+): ArrayBuffer {
+  if (ptr === null || byteLength === 0) {
+    return new ArrayBuffer(0);
+  }
+  return Deno.UnsafePointerView.getArrayBuffer(ptr, byteLength);
+}
+
+/**
+ * Wrap the columns of a Rust column batch
+ */
+function readColumnBatch(batch: Deno.PointerObject): RustColumnBatch {
+  const rustLib = getRustLib();
+  const symbols = rustLib.symbols;
+  const length = Number(symbols.column_batch_len(batch));
+  const numColumns = Number(symbols.column_batch_num_columns(batch));
+  const columns: RustColumn[] = [];
+
+  for (let i = 0; i < numColumns; i++) {
+    const name = new Deno.UnsafePointerView(
+      symbols.column_batch_column_name(batch, i)!,
+    ).getCString();
+    const valuesPtr = symbols.column_batch_values(batch, i);
+    const valuesLen = Number(symbols.column_batch_values_len(batch, i));
+    const validity = new Uint8Array(
+      borrowBuffer(symbols.column_batch_validity(batch, i), Math.ceil(length / 8)),
+    );
+
+    switch (symbols.column_batch_column_type(batch, i)) {
+      case 0:
+        columns.push({
+          name,
+          type: "float64",
+          values: new Float64Array(borrowBuffer(valuesPtr, valuesLen)),
+          validity,
+        });
+        break;
+      case 1:
+        columns.push({
+          name,
+          type: "int64",
+          values: new BigInt64Array(borrowBuffer(valuesPtr, valuesLen)),
+          validity,
+        });
+        break;
+      case 2: {
+        const offsets = new Int32Array(
+          borrowBuffer(symbols.column_batch_offsets(batch, i), (length + 1) * 4),
+        );
+        const bytes = new Uint8Array(borrowBuffer(valuesPtr, valuesLen));
+        const values = new Array<string>(length);
+        for (let row = 0; row < length; row++) {
+          values[row] = decoder.decode(
+            bytes.subarray(offsets[row], offsets[row + 1]),
+          );
+        }
+        columns.push({ name, type: "utf8", values, validity });
+        break;
+      }
+      case 3: {
+        const bits = new Uint8Array(borrowBuffer(valuesPtr, valuesLen));
+        const values = new Array<boolean>(length);
+        for (let row = 0; row < length; row++) {
+          values[row] = (bits[row >> 3] & (1 << (row & 7))) !== 0;
+        }
+        columns.push({ name, type: "bool", values, validity });
+        break;
+      }
+      case 4:
+      case 5: {
+        const offsets = new Int32Array(
+          borrowBuffer(symbols.column_batch_offsets(batch, i), (length + 1) * 4),
+        );
+        const buffer = borrowBuffer(valuesPtr, valuesLen);
+        columns.push(
+          symbols.column_batch_column_type(batch, i) === 4
+            ? { name, type: "float32[]", offsets, values: new Float32Array(buffer), validity }
+            : { name, type: "float64[]", offsets, values: new Float64Array(buffer), validity },
+        );
+        break;
+      }
+      default:
+        throw new Error(`Unknown column type for column ${name}`);
+    }
+  }
+
+  return { length, columns };
+}
+
+/**
+ * Stream a gzipped CSV file using Rust, yielding typed columnar batches of
+ * at most `chunkSize` rows. 64-bit IDs are exact int64 columns unless
+ * `idsAsStrings` is set.
+ *
+ * Numeric columns are views over Rust memory and are only valid until the
+ * next batch is requested; copy them if they need to outlive the iteration.
+ */
+export async function* streamGzippedCsvColumnsRust(
+  filePath: string,
+  options: RustReaderOptions,
+  chunkSize = 100000,
+): AsyncGenerator<RustColumnBatch> {
+  const filePathBytes = toCString(filePath);
+  const optionsJsonBytes = toOptionsCString(options);
+
+  const rustLib = getRustLib();
+
+  const reader = rustLib.symbols.open_csv_reader(
+    Deno.UnsafePointer.of(filePathBytes),
+    Deno.UnsafePointer.of(optionsJsonBytes),
+  );
+
+  if (reader === null) {
+    throw lastRustError("Failed to open CSV file in Rust");
+  }
+
+  try {
+    while (true) {
+      const batch = rustLib.symbols.read_csv_columns(reader, chunkSize);
+
+      if (batch === null) {
+        throw lastRustError("Failed to read CSV columns in Rust");
+      }
+
+      try {
+        const columnBatch = readColumnBatch(batch);
+        if (columnBatch.length === 0) {
+          return;
+        }
+
+        yield columnBatch;
+      } finally {
+        rustLib.symbols.free_column_batch(batch);
+      }
+    }
+  } finally {
+    rustLib.symbols.close_csv_reader(reader);
+  }
+}
+
+/**
+ * The outcome of parsing one file with `parseCsvFilesRust`
+ */
+export interface RustFileResult {
+  filePath: string;
+  batch: RustColumnBatch | null;
+  error: RustParseError | null;
+}
+
+/**
+ * Parse several CSV files in parallel on a pool of `threads` Rust threads
+ * (one per core if 0), yielding each file's columns or error in the order
+ * given. The parse runs off the JavaScript thread.
+ *
+ * Every file is parsed before the first result is yielded. As with
+ * `streamGzippedCsvColumnsRust`, numeric columns are only valid until the
+ * next result is requested.
+ */
+export async function* parseCsvFilesRust(
+  filePaths: string[],
+  options: RustReaderOptions,
+  threads = 0,
+): AsyncGenerator<RustFileResult> {
+  const pathsJsonBytes = toCString(JSON.stringify(filePaths));
+  const optionsJsonBytes = toOptionsCString(options);
+
+  const rustLib = getRustLib();
+
+  const batch = await rustLib.symbols.parse_csv_files(
+    Deno.UnsafePointer.of(pathsJsonBytes),
+    Deno.UnsafePointer.of(optionsJsonBytes),
+    threads,
+  );
+
+  if (batch === null) {
+    // The error was recorded on the worker thread, so only the cause is known
+    throw new RustParseError(
+      RustErrorCode.InvalidArgument,
+      "Failed to parse CSV files in Rust: invalid file path list",
+    );
+  }
+
+  try {
+    for (let i = 0; i < filePaths.length; i++) {
+      const columns = rustLib.symbols.file_batch_columns(batch, i);
+
+      if (columns !== null) {
+        yield { filePath: filePaths[i], batch: readColumnBatch(columns), error: null };
+        continue;
+      }
+
+      const code = rustLib.symbols.file_batch_error_code(batch, i) as RustErrorCode;
+      const messagePtr = rustLib.symbols.file_batch_error_message(batch, i);
+      let message = "unknown error";
+      if (messagePtr !== null) {
+        try {
+          message = new Deno.UnsafePointerView(messagePtr).getCString();
+        } finally {
+          rustLib.symbols.free_string(messagePtr);
+        }
+      }
+
+      yield {
+        filePath: filePaths[i],
+        batch: null,
+        error: new RustParseError(code, `Failed to parse CSV file in Rust: ${message}`),
+      };
+    }
+  } finally {
+    rustLib.symbols.free_file_batch(batch);
+  }
+}
+
+/**
+ * Row counts from `ingestGaiaCsvRust`
+ */
+export interface RustIngestCounts {
+  /** Rows that passed the magnitude cut and row filter */
+  rows: number;
+  /** Rows that were new to the table; the rest were duplicates */
+  inserted: number;
+}
+
+/**
+ * Tables recording which downloaded files have been ingested
+ */
+export type RustTrackingTable =
+  | "file_tracking_gaiadr3"
+  | "file_tracking_tmass_xmatch"
+  | "file_tracking_tmass"
+  | "file_tracking_astrophysical_parameters"
+  | "file_tracking_vari_summary"
+  | "file_tracking_nss_two_body_orbit"
+  | "file_tracking_qso_candidates";
+
+/**
+ * Parse a Gaia file straight into the `gaiadr3` table of a SQLite
+ * database, creating the table with `options.columns` as
+ * `GaiaDatabase.initialize()` would. With another profile as
+ * `options.table`, the rows go into the profile's `local_table` instead,
+ * keyed on `source_id` so it joins to `gaiadr3`. Rows are parsed
+ * `batchSize` at a time (50,000 if 0) and inserted with `INSERT OR IGNORE`
+ * in one transaction, without passing through JavaScript.
+ *
+ * If `url` is given, the same transaction marks the file completed in
+ * the profile's tracking table (`file_tracking_gaiadr3` for
+ * `gaia_source`) with its row counts, and a failure marks it failed.
+ */
+export function ingestGaiaCsvRust(
+  dbPath: string,
+  filePath: string,
+  url: string | null,
+  options: RustReaderOptions,
+  batchSize = 0,
+): RustIngestCounts {
+  const dbPathBytes = toCString(dbPath);
+  const filePathBytes = toCString(filePath);
+  const urlBytes = url === null ? null : toCString(url);
+  const optionsJsonBytes = toOptionsCString(options);
+  const rows = new BigUint64Array(1);
+  const inserted = new BigUint64Array(1);
+
+  const rustLib = getRustLib();
+
+  const status = rustLib.symbols.ingest_gaia_csv(
+    Deno.UnsafePointer.of(dbPathBytes),
+    Deno.UnsafePointer.of(filePathBytes),
+    urlBytes && Deno.UnsafePointer.of(urlBytes),
+    Deno.UnsafePointer.of(optionsJsonBytes),
+    batchSize,
+    rows,
+    inserted,
+  );
+
+  if (status) {
+    throw lastRustError(`Failed to ingest ${filePath} in Rust`);
+  }
+
+  return { rows: Number(rows[0]), inserted: Number(inserted[0]) };
+}
+
+/**
+ * Add files to a tracking table as pending, keeping the status of files
+ * already tracked unless `overwrite` is set. Creates the table, or adds its
+ * row count columns, if needed.
+ */
+export function initFileTrackingRust(
+  dbPath: string,
+  table: RustTrackingTable,
+  urls: string[],
+  overwrite = false,
+): void {
+  const dbPathBytes = toCString(dbPath);
+  const tableBytes = toCString(table);
+  const urlsJsonBytes = toCString(JSON.stringify(urls));
+
+  const status = getRustLib().symbols.init_file_tracking(
+    Deno.UnsafePointer.of(dbPathBytes),
+    Deno.UnsafePointer.of(tableBytes),
+    Deno.UnsafePointer.of(urlsJsonBytes),
+    overwrite,
+  );
+
+  if (status) {
+    throw lastRustError(`Failed to initialize ${table} in Rust`);
+  }
+}
+
+/**
+ * Decode a gzipped CSV download stream using Rust, yielding typed columnar
+ * batches of at most `chunkSize` rows as the bytes arrive.
+ *
+ * `source` (e.g. the URL) is used in error messages. As with
+ * `streamGzippedCsvColumnsRust`, numeric columns are only valid until the
+ * next batch is requested.
+ */
+export async function* decodeCsvStreamRust(
+  stream: ReadableStream<Uint8Array>,
+  source: string,
+  options: RustReaderOptions,
+  chunkSize = 100000,
+): AsyncGenerator<RustColumnBatch> {
+  const sourceBytes = toCString(source);
+  const optionsJsonBytes = toOptionsCString(options);
+
+  const rustLib = getRustLib();
+
+  const decoderPtr = rustLib.symbols.create_csv_decoder(
+    Deno.UnsafePointer.of(sourceBytes),
+    Deno.UnsafePointer.of(optionsJsonBytes),
+    chunkSize,
+  );
+
+  if (decoderPtr === null) {
+    throw lastRustError("Failed to create CSV decoder in Rust");
+  }
+
+  // Yield every batch the decoder has completed so far
+  async function* drain(decoder: Deno.PointerObject) {
+    while (true) {
+      const batch = rustLib.symbols.next_decoder_batch(decoder);
+
+      if (batch === null) {
+        throw lastRustError("Failed to read CSV batch in Rust");
+      }
+
+      try {
+        const columnBatch = readColumnBatch(batch);
+        if (columnBatch.length === 0) {
+          return;
+        }
+
+        yield columnBatch;
+      } finally {
+        rustLib.symbols.free_column_batch(batch);
+      }
+    }
   }
 
-  // Read the result string
-  const resultView = new Deno.UnsafePointerView(resultPtr);
-  const resultCString = resultView.getCString();
+  try {
+    for await (const chunk of stream) {
+      if (rustLib.symbols.feed_csv_decoder(decoderPtr, chunk, chunk.length)) {
+        throw lastRustError(`Failed to decode ${source} in Rust`);
+      }
+
+      yield* drain(decoderPtr);
+    }
+
+    if (rustLib.symbols.finish_csv_decoder(decoderPtr)) {
+      throw lastRustError(`Failed to decode ${source} in Rust`);
+    }
+
+    yield* drain(decoderPtr);
+  } finally {
+    rustLib.symbols.free_csv_decoder(decoderPtr);
+  }
+}
 
-  // Parse JSON result
-  const records = JSON.parse(resultCString);
+/**
+ * Options for reading a Gaia `*_best_neighbour` table (see `XmatchOptions`
+ * in xmatch.rs)
+ */
+export interface RustXmatchOptions {
+  /** Also return angular distance and neighbour/mate counts */
+  withMatchQuality?: boolean;
+  /** Keep only rows matching a filter expression such as `number_of_mates = 0` */
+  filter?: string;
+}
 
-  // Free the memory allocated by Rust
-  rustLib.symbols.free_string(resultPtr);
+/**
+ * Encode crossmatch options as the JSON object expected by Rust
+ */
+function toXmatchOptionsCString(options: RustXmatchOptions): Uint8Array {
+  return toCString(JSON.stringify({
+    with_match_quality: options.withMatchQuality ?? false,
+    filter: options.filter ?? null,
+  }));
+}
+
+/**
+ * A batch of (Gaia source_id, 2MASS designation) pairs. The match quality
+ * arrays are only present if requested; null distances are NaN and null
+ * counts are -1.
+ */
+export interface RustXmatchBatch {
+  length: number;
+  sourceIds: BigUint64Array;
+  designations: string[];
+  angularDistance?: Float64Array;
+  numberOfNeighbours?: Int32Array;
+  numberOfMates?: Int32Array;
+}
+
+/**
+ * Wrap the arrays of a Rust crossmatch batch
+ */
+function readXmatchBatch(batch: Deno.PointerObject): RustXmatchBatch {
+  const symbols = getRustLib().symbols;
+  const length = Number(symbols.xmatch_batch_len(batch));
+
+  const offsets = new Int32Array(
+    borrowBuffer(symbols.xmatch_batch_designation_offsets(batch), (length + 1) * 4),
+  );
+  const bytes = new Uint8Array(
+    borrowBuffer(
+      symbols.xmatch_batch_designations(batch),
+      Number(symbols.xmatch_batch_designations_len(batch)),
+    ),
+  );
+  const designations = new Array<string>(length);
+  for (let row = 0; row < length; row++) {
+    designations[row] = decoder.decode(
+      bytes.subarray(offsets[row], offsets[row + 1]),
+    );
+  }
+
+  const angularDistance = symbols.xmatch_batch_angular_distance(batch);
+  const numberOfNeighbours = symbols.xmatch_batch_number_of_neighbours(batch);
+  const numberOfMates = symbols.xmatch_batch_number_of_mates(batch);
+
+  return {
+    length,
+    sourceIds: new BigUint64Array(
+      borrowBuffer(symbols.xmatch_batch_source_ids(batch), length * 8),
+    ),
+    designations,
+    angularDistance: angularDistance === null
+      ? undefined
+      : new Float64Array(borrowBuffer(angularDistance, length * 8)),
+    numberOfNeighbours: numberOfNeighbours === null
+      ? undefined
+      : new Int32Array(borrowBuffer(numberOfNeighbours, length * 4)),
+    numberOfMates: numberOfMates === null
+      ? undefined
+      : new Int32Array(borrowBuffer(numberOfMates, length * 4)),
+  };
+}
+
+/**
+ * Stream (Gaia source_id, 2MASS designation) pairs from a gzipped Gaia
+ * `*_best_neighbour` table using Rust, in batches of at most `chunkSize`.
+ *
+ * As with `streamGzippedCsvColumnsRust`, the typed arrays are only valid
+ * until the next batch is requested.
+ */
+export async function* streamXmatchPairsRust(
+  filePath: string,
+  options: RustXmatchOptions = {},
+  chunkSize = 100000,
+): AsyncGenerator<RustXmatchBatch> {
+  const filePathBytes = toCString(filePath);
+  const optionsJsonBytes = toXmatchOptionsCString(options);
+
+  const rustLib = getRustLib();
+
+  const reader = rustLib.symbols.open_xmatch_reader(
+    Deno.UnsafePointer.of(filePathBytes),
+    Deno.UnsafePointer.of(optionsJsonBytes),
+  );
+
+  if (reader === null) {
+    throw lastRustError("Failed to open crossmatch file in Rust");
+  }
+
+  try {
+    while (true) {
+      const batch = rustLib.symbols.read_xmatch_pairs(reader, chunkSize);
+
+      if (batch === null) {
+        throw lastRustError("Failed to read crossmatch pairs in Rust");
+      }
+
+      try {
+        const xmatchBatch = readXmatchBatch(batch);
+        if (xmatchBatch.length === 0) {
+          return;
+        }
+
+        yield xmatchBatch;
+      } finally {
+        rustLib.symbols.free_xmatch_batch(batch);
+      }
+    }
+  } finally {
+    rustLib.symbols.close_xmatch_reader(reader);
+  }
+}
+
+/**
+ * The `source_id`s of every row in `gaiadr3`, held in Rust for
+ * `ingestTmassXmatchRust`. Free with `freeGaiaSourceIdsRust`.
+ */
+export interface RustSourceIdSet {
+  pointer: Deno.PointerObject;
+  size: number;
+}
+
+/**
+ * Load the `source_id` of every row in `gaiadr3` into Rust memory, once
+ * for all the crossmatch files that will be filtered against it
+ */
+export function loadGaiaSourceIdsRust(dbPath: string): RustSourceIdSet {
+  const dbPathBytes = toCString(dbPath);
+  const rustLib = getRustLib();
+
+  const pointer = rustLib.symbols.load_gaia_source_ids(
+    Deno.UnsafePointer.of(dbPathBytes),
+  );
+
+  if (pointer === null) {
+    throw lastRustError("Failed to load Gaia source IDs in Rust");
+  }
+
+  return {
+    pointer,
+    size: Number(rustLib.symbols.source_id_set_len(pointer)),
+  };
+}
+
+/**
+ * Release a set returned by `loadGaiaSourceIdsRust`
+ */
+export function freeGaiaSourceIdsRust(ids: RustSourceIdSet): void {
+  getRustLib().symbols.free_source_id_set(ids.pointer);
+}
+
+/**
+ * Write the pairs of a gzipped Gaia `*_best_neighbour` table whose Gaia
+ * source is in `ids` straight into `tmass_xmatch`, `batchSize` pairs at a
+ * time (50,000 if 0), in one transaction
+ *
+ * If `url` is given, the same transaction marks the file completed in
+ * `file_tracking_tmass_xmatch` with its row counts, and a failure marks it
+ * failed.
+ */
+export function ingestTmassXmatchRust(
+  dbPath: string,
+  filePath: string,
+  url: string | null,
+  ids: RustSourceIdSet,
+  options: RustXmatchOptions = {},
+  batchSize = 0,
+): RustIngestCounts {
+  const dbPathBytes = toCString(dbPath);
+  const filePathBytes = toCString(filePath);
+  const urlBytes = url === null ? null : toCString(url);
+  const optionsJsonBytes = toXmatchOptionsCString(options);
+  const rows = new BigUint64Array(1);
+  const inserted = new BigUint64Array(1);
+
+  const status = getRustLib().symbols.ingest_tmass_xmatch(
+    Deno.UnsafePointer.of(dbPathBytes),
+    Deno.UnsafePointer.of(filePathBytes),
+    urlBytes && Deno.UnsafePointer.of(urlBytes),
+    ids.pointer,
+    Deno.UnsafePointer.of(optionsJsonBytes),
+    batchSize,
+    rows,
+    inserted,
+  );
+
+  if (status) {
+    throw lastRustError(`Failed to ingest ${filePath} in Rust`);
+  }
+
+  return { rows: Number(rows[0]), inserted: Number(inserted[0]) };
+}
+
+/**
+ * Which optional 2MASS PSC columns to store in `tmass` (see `TmassOptions`
+ * in tmass.rs). `j_m`, `h_m` and `k_m` are always stored.
+ */
+export interface RustTmassOptions {
+  /** Also store `j_msigcom`, `h_msigcom` and `k_msigcom` */
+  withErrors?: boolean;
+  /** Also store `ph_qual`, `rd_flg`, `bl_flg` and `cc_flg` */
+  withQualityFlags?: boolean;
+}
+
+/**
+ * The `tmass_xmatch` table as a designation index held in Rust for
+ * `ingestTmassPscRust`. Free with `freeTmassXmatchIndexRust`.
+ */
+export interface RustTmassXmatchIndex {
+  pointer: Deno.PointerObject;
+  size: number;
+}
+
+/**
+ * Load every (Gaia source_id, 2MASS designation) pair in `tmass_xmatch`
+ * into Rust memory, once for all the 2MASS files joined against it
+ */
+export function loadTmassXmatchIndexRust(dbPath: string): RustTmassXmatchIndex {
+  const dbPathBytes = toCString(dbPath);
+  const rustLib = getRustLib();
+
+  const pointer = rustLib.symbols.load_tmass_xmatch_index(
+    Deno.UnsafePointer.of(dbPathBytes),
+  );
+
+  if (pointer === null) {
+    throw lastRustError("Failed to load the 2MASS crossmatch in Rust");
+  }
+
+  return {
+    pointer,
+    size: Number(rustLib.symbols.tmass_xmatch_index_len(pointer)),
+  };
+}
+
+/**
+ * Release an index returned by `loadTmassXmatchIndexRust`
+ */
+export function freeTmassXmatchIndexRust(index: RustTmassXmatchIndex): void {
+  getRustLib().symbols.free_tmass_xmatch_index(index.pointer);
+}
+
+/**
+ * Write the photometry of every star in a gzipped 2MASS PSC file that is
+ * matched to a Gaia source in `index` straight into `tmass`, `batchSize`
+ * stars at a time (50,000 if 0), in one transaction
+ *
+ * A row is written for each Gaia source matched to a star. If `url` is
+ * given, the same transaction marks the file completed in
+ * `file_tracking_tmass` with its row counts, and a failure marks it failed.
+ */
+export function ingestTmassPscRust(
+  dbPath: string,
+  filePath: string,
+  url: string | null,
+  index: RustTmassXmatchIndex,
+  options: RustTmassOptions = {},
+  batchSize = 0,
+): RustIngestCounts {
+  const dbPathBytes = toCString(dbPath);
+  const filePathBytes = toCString(filePath);
+  const urlBytes = url === null ? null : toCString(url);
+  const optionsJsonBytes = toCString(JSON.stringify({
+    with_errors: options.withErrors ?? false,
+    with_quality_flags: options.withQualityFlags ?? false,
+  }));
+  const rows = new BigUint64Array(1);
+  const inserted = new BigUint64Array(1);
+
+  const status = getRustLib().symbols.ingest_tmass_psc(
+    Deno.UnsafePointer.of(dbPathBytes),
+    Deno.UnsafePointer.of(filePathBytes),
+    urlBytes && Deno.UnsafePointer.of(urlBytes),
+    index.pointer,
+    Deno.UnsafePointer.of(optionsJsonBytes),
+    batchSize,
+    rows,
+    inserted,
+  );
+
+  if (status) {
+    throw lastRustError(`Failed to ingest ${filePath} in Rust`);
+  }
+
+  return { rows: Number(rows[0]), inserted: Number(inserted[0]) };
+}
+
+/**
+ * The settings that determine what a database holds (see `DatasetMeta` in
+ * meta.rs)
+ */
+export interface RustDatasetMeta {
+  storedColumns: string[];
+  magnitudeLimit: number;
+  zeropoints: number[];
+  dataRelease: string;
+}
+
+/**
+ * Everything recorded in `gaiaoffline_meta`, with null for settings that
+ * are not recorded
+ */
+export interface RustDatabaseMeta {
+  /** Number of migrations applied, 0 if the database was never migrated */
+  schema_version: number;
+  /** Version of the Rust library that last recorded the settings */
+  parser_version: string | null;
+  stored_columns: string[] | null;
+  magnitude_limit: number | null;
+  zeropoints: number[] | null;
+  data_release: string | null;
+}
+
+/**
+ * Migrate a database and record the settings it is populated with in
+ * `gaiaoffline_meta`, throwing a `SettingsMismatch` error if it was
+ * populated with different ones
+ */
+export function initDatabaseMetaRust(
+  dbPath: string,
+  meta: RustDatasetMeta,
+): void {
+  const dbPathBytes = toCString(dbPath);
+  const metaJsonBytes = toCString(JSON.stringify({
+    stored_columns: meta.storedColumns,
+    magnitude_limit: meta.magnitudeLimit,
+    zeropoints: meta.zeropoints,
+    data_release: meta.dataRelease,
+  }));
+
+  const status = getRustLib().symbols.init_database_meta(
+    Deno.UnsafePointer.of(dbPathBytes),
+    Deno.UnsafePointer.of(metaJsonBytes),
+  );
+
+  if (status) {
+    throw lastRustError(`Failed to record settings for ${dbPath} in Rust`);
+  }
+}
+
+/**
+ * Read the settings recorded in `gaiaoffline_meta`
+ */
+export function readDatabaseMetaRust(dbPath: string): RustDatabaseMeta {
+  const dbPathBytes = toCString(dbPath);
+
+  const resultPtr = getRustLib().symbols.read_database_meta(
+    Deno.UnsafePointer.of(dbPathBytes),
+  );
+
+  if (resultPtr === null) {
+    throw lastRustError(`Failed to read settings for ${dbPath} in Rust`);
+  }
 
-  return records;
+  return takeJson(resultPtr);
 }
 
 /**
@@ -1,6 +1,7 @@
 import {
   GaiaDatabase,
   type GaiaRecord,
+  sameColumns,
   type TrackingProgress,
 } from "./database.ts";
 import { type CLIConfig, DEFAULT_CONFIG } from "./config.ts";
@@ -13,7 +14,9 @@ export type GaiaOptions = {
    */
   databasePath?: CLIConfig["databasePath"];
   /**
-   * The select columns to store in the database
+   * The select columns to store in the database. Must match the columns
+   * recorded when the database was populated, which are used when this is
+   * not given.
    * @default ["source_id", "ra", "dec", "parallax", "pmra", "pmdec", "radial_velocity", "phot_g_mean_flux", "phot_bp_mean_flux", "phot_rp_mean_flux", "teff_gspphot", "logg_gspphot", "mh_gspphot"]
    */
   storedColumns?: CLIConfig["storedColumns"];
@@ -77,6 +80,22 @@ export class Gaia {
     };
     this.db = new GaiaDatabase(this.options);
 
+    // Columns recorded when the database was populated
+    const recorded = this.db.getMeta()?.stored_columns;
+    if (recorded) {
+      const recordedColumns = JSON.parse(recorded) as GaiaColumn[];
+      if (!options.storedColumns) {
+        this.options.storedColumns = recordedColumns;
+      } else if (!sameColumns(options.storedColumns, recordedColumns)) {
+        this.db.close();
+        throw new Error(
+          `${this.options.databasePath} stores columns ${
+            recordedColumns.join(",")
+          } but ${options.storedColumns.join(",")} were requested`,
+        );
+      }
+    }
+
     // Check if 2MASS table exists if crossmatch is requested
     if (this.options.tmassCrossmatch && !this.db.hasTmassTable()) {
       throw new Error(
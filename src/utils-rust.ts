@@ -2,62 +2,451 @@
  * Rust-accelerated CSV processing utilities
  */
 
-import { parseGzippedCsvRust } from "./ffi/rust.ts";
+import {
+  decodeCsvStreamRust,
+  freeGaiaSourceIdsRust,
+  freeTmassXmatchIndexRust,
+  ingestGaiaCsvRust,
+  ingestTmassPscRust,
+  ingestTmassXmatchRust,
+  initDatabaseMetaRust,
+  initFileTrackingRust,
+  isColumnValid,
+  listElements,
+  loadGaiaSourceIdsRust,
+  loadTmassXmatchIndexRust,
+  parseCsvFilesRust,
+  readCsvHeaderRust,
+  RustErrorCode,
+  type RustColumn,
+  type RustColumnBatch,
+  RustParseError,
+  type RustReaderOptions,
+  type RustSourceIdSet,
+  type RustTableName,
+  type RustTmassXmatchIndex,
+  type RustTrackingTable,
+  streamGzippedCsvColumnsRust,
+  streamXmatchPairsRust,
+} from "./ffi/rust.ts";
 import type { CLIConfig } from "./config.ts";
-import type { GaiaRecord, TmassXmatchRecord } from "./database.ts";
-import { filterByMagnitude } from "./utils.ts";
+import type {
+  GaiaRecord,
+  TmassRecord,
+  TmassXmatchRecord,
+} from "./database.ts";
 
 /**
- * Stream and filter CSV from a file path using Rust parser
- * Falls back to TypeScript implementation if Rust FFI fails
+ * Stream and filter CSV from a file path or download stream using Rust
+ * parser, yielding the filtered records of at most `config.csvChunkSize`
+ * rows at a time so only one chunk is held in memory
+ *
+ * @param source - File path, or a download stream together with its URL
  */
-export async function streamAndFilterCSVRust(
+export async function* streamAndFilterCSVRust(
+  source: string | { stream: ReadableStream<Uint8Array>; url: string },
+  config: CLIConfig,
+): AsyncGenerator<GaiaRecord[]> {
+  try {
+    const options = gaiaReaderOptions(config);
+
+    // Read typed columnar chunks so no JSON crosses the FFI boundary
+    const batches = typeof source === "string"
+      ? streamGzippedCsvColumnsRust(source, options, config.csvChunkSize)
+      : decodeCsvStreamRust(
+        source.stream,
+        source.url,
+        options,
+        config.csvChunkSize,
+      );
+
+    for await (const batch of batches) {
+      yield columnBatchToRecords(batch);
+    }
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Parse and filter several downloaded files at once on the Rust thread pool
+ *
+ * Returns each file's records, or the error that file failed with, keyed by
+ * path. A failed file does not affect the others.
+ */
+export async function parseFilesRust(
+  filePaths: string[],
+  config: CLIConfig,
+): Promise<Map<string, GaiaRecord[] | Error>> {
+  const results = new Map<string, GaiaRecord[] | Error>();
+
+  try {
+    const files = parseCsvFilesRust(
+      filePaths,
+      gaiaReaderOptions(config),
+      config.rustThreads,
+    );
+
+    for await (const { filePath, batch, error } of files) {
+      results.set(
+        filePath,
+        batch ? columnBatchToRecords(batch) : wrapRustError(error),
+      );
+    }
+
+    return results;
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Add URLs to a tracking table through Rust, which owns the tables when
+ * it ingests files itself
+ */
+export function initTrackingRust(
+  table: RustTrackingTable,
+  urls: string[],
+  config: CLIConfig,
+): void {
+  try {
+    initFileTrackingRust(config.databasePath, table, urls);
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Parse and filter a downloaded file straight into the `gaiadr3` table
+ * from Rust, returning the number of rows that were new to the table
+ *
+ * The rows and the file's "completed" status in `file_tracking_gaiadr3`
+ * commit together; a file that fails is marked failed and keeps no rows.
+ */
+export function ingestGaiaFileRust(
   filePath: string,
+  url: string,
   config: CLIConfig,
-): Promise<GaiaRecord[]> {
+): number {
   try {
-    // Parse entire file with Rust
-    const allRecords = await parseGzippedCsvRust(
+    return ingestGaiaCsvRust(
+      config.databasePath,
       filePath,
-      config.storedColumns,
+      url,
+      gaiaReaderOptions(config),
       config.csvChunkSize,
-    );
+    ).inserted;
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
 
-    // Filter by magnitude (still in TypeScript for now)
-    const filteredRecords = filterByMagnitude(
-      allRecords as GaiaRecord[],
-      config.magnitudeLimit,
-      config.zeropoints[0],
-    );
+/**
+ * Parse a downloaded file of another Gaia table, such as `vari_summary`,
+ * into its own table from Rust, returning the number of rows that were new
+ * to the table
+ *
+ * The table is keyed on `source_id`, so it joins to `gaiadr3`. An empty
+ * `columns` keeps the profile's default columns. The file is tracked in
+ * the profile's tracking table as `ingestGaiaFileRust` tracks Gaia source
+ * files.
+ */
+export function ingestTableFileRust(
+  filePath: string,
+  url: string,
+  table: RustTableName,
+  columns: string[],
+  config: CLIConfig,
+): number {
+  try {
+    return ingestGaiaCsvRust(
+      config.databasePath,
+      filePath,
+      url,
+      { table, columns },
+      config.csvChunkSize,
+    ).inserted;
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Reader options for Gaia source files under the CLI configuration
+ *
+ * Faint and filtered-out rows are dropped in Rust before any output is built.
+ */
+function gaiaReaderOptions(config: CLIConfig): RustReaderOptions {
+  return {
+    columns: config.storedColumns,
+    strictColumns: true,
+    strictSchema: true,
+    magnitudeCut: {
+      limit: config.magnitudeLimit,
+      band: "G",
+      zeropoint: config.zeropoints[0],
+    },
+    filter: config.filter,
+  };
+}
 
-    return filteredRecords;
+/**
+ * Check that every column exists in the header of a downloaded file
+ *
+ * Only the header is read, so this is cheap enough to run before processing.
+ */
+export function validateColumnsRust(filePath: string, columns: string[]) {
+  try {
+    const available = new Set(readCsvHeaderRust(filePath).map((c) => c.name));
+    const missing = columns.filter((column) => !available.has(column));
+
+    if (missing.length > 0) {
+      throw new RustParseError(
+        RustErrorCode.MissingColumn,
+        `${filePath}: missing columns: ${missing.join(", ")}`,
+      );
+    }
   } catch (error) {
-    const errorMessage = error instanceof Error ? error.message : String(error);
-    throw new Error(`Rust CSV parsing failed: ${errorMessage}`);
+    throw wrapRustError(error);
   }
 }
 
 /**
  * Parse 2MASS crossmatch CSV using Rust parser
+ *
+ * @param filter - Optional filter expression to drop ambiguous matches,
+ *   e.g. `number_of_neighbours = 1 AND number_of_mates = 0`
  */
 export async function parseTmassXmatchRust(
   filePath: string,
+  filter?: string,
+  chunkSize = 100000,
 ): Promise<TmassXmatchRecord[]> {
   try {
-    const columns = ["source_id", "original_ext_source_id"];
-    const records = await parseGzippedCsvRust(
+    const records: TmassXmatchRecord[] = [];
+
+    for await (
+      const batch of streamXmatchPairsRust(filePath, { filter }, chunkSize)
+    ) {
+      for (let row = 0; row < batch.length; row++) {
+        records.push({
+          gaiadr3_source_id: batch.sourceIds[row].toString(),
+          tmass_source_id: batch.designations[row],
+        });
+      }
+    }
+
+    return records;
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Write the crossmatch pairs of a downloaded file whose Gaia source was
+ * ingested straight into `tmass_xmatch` from Rust, returning the number of
+ * new rows
+ *
+ * `ids` comes from `loadGaiaIdsRust`, loaded once for every file. The rows
+ * and the file's "completed" status commit together.
+ */
+export function ingestTmassXmatchFileRust(
+  filePath: string,
+  url: string,
+  ids: RustSourceIdSet,
+  config: CLIConfig,
+): number {
+  try {
+    return ingestTmassXmatchRust(
+      config.databasePath,
+      filePath,
+      url,
+      ids,
+      {},
+      config.csvChunkSize,
+    ).inserted;
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Load the ingested Gaia `source_id`s into Rust for crossmatch filtering;
+ * release them with `freeGaiaIdsRust`
+ */
+export function loadGaiaIdsRust(config: CLIConfig): RustSourceIdSet {
+  try {
+    return loadGaiaSourceIdsRust(config.databasePath);
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Release the set returned by `loadGaiaIdsRust`
+ */
+export function freeGaiaIdsRust(ids: RustSourceIdSet): void {
+  freeGaiaSourceIdsRust(ids);
+}
+
+/**
+ * Record the settings a Gaia population runs with in the database,
+ * refusing to append to one populated with different columns, magnitude
+ * limit, zeropoints or data release
+ */
+export function initDatabaseMetaFromConfigRust(
+  config: CLIConfig,
+  dataRelease: string,
+): void {
+  try {
+    initDatabaseMetaRust(config.databasePath, {
+      storedColumns: config.storedColumns,
+      magnitudeLimit: config.magnitudeLimit,
+      zeropoints: config.zeropoints,
+      dataRelease,
+    });
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Write the photometry of a downloaded 2MASS PSC file's crossmatched stars
+ * straight into `tmass` from Rust, returning the number of new rows
+ *
+ * `index` comes from `loadTmassIndexRust`, loaded once for every file. The
+ * rows and the file's "completed" status commit together.
+ */
+export function ingestTmassFileRust(
+  filePath: string,
+  url: string,
+  index: RustTmassXmatchIndex,
+  config: CLIConfig,
+): number {
+  try {
+    return ingestTmassPscRust(
+      config.databasePath,
       filePath,
-      columns,
-      100000,
+      url,
+      index,
+      {
+        withErrors: config.tmassErrors,
+        withQualityFlags: config.tmassQualityFlags,
+      },
+      config.csvChunkSize,
+    ).inserted;
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Load the 2MASS crossmatch into Rust for joining photometry; release it
+ * with `freeTmassIndexRust`
+ */
+export function loadTmassIndexRust(config: CLIConfig): RustTmassXmatchIndex {
+  try {
+    return loadTmassXmatchIndexRust(config.databasePath);
+  } catch (error) {
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Release the index returned by `loadTmassIndexRust`
+ */
+export function freeTmassIndexRust(index: RustTmassXmatchIndex): void {
+  freeTmassXmatchIndexRust(index);
+}
+
+/**
+ * Parse a 2MASS PSC photometry file (`psc_*.gz`) using Rust parser
+ */
+export async function parseTmassPscRust(
+  filePath: string,
+  chunkSize = 100000,
+): Promise<Omit<TmassRecord, "gaiadr3_source_id">[]> {
+  try {
+    const records: Omit<TmassRecord, "gaiadr3_source_id">[] = [];
+    const batches = streamGzippedCsvColumnsRust(
+      filePath,
+      {
+        format: "tmass_psc",
+        columns: ["designation", "j_m", "h_m", "k_m"],
+        strictColumns: true,
+      },
+      chunkSize,
     );
 
-    // Map to TmassXmatchRecord format
-    return records.map((r: any) => ({
-      gaiadr3_source_id: r.source_id,
-      tmass_source_id: r.original_ext_source_id,
-    }));
+    for await (const batch of batches) {
+      const [designation, jMag, hMag, kMag] = batch.columns;
+      if (designation.type !== "utf8") {
+        throw new Error("2MASS designation column is not utf8");
+      }
+
+      const magnitude = (column: RustColumn, row: number) =>
+        column.type === "float64" && isColumnValid(column, row)
+          ? column.values[row]
+          : null;
+
+      for (let row = 0; row < batch.length; row++) {
+        if (!isColumnValid(designation, row)) continue;
+
+        records.push({
+          tmass_source_id: designation.values[row],
+          j_m: magnitude(jMag, row),
+          h_m: magnitude(hMag, row),
+          k_m: magnitude(kMag, row),
+        });
+      }
+    }
+
+    return records;
   } catch (error) {
-    const errorMessage = error instanceof Error ? error.message : String(error);
-    throw new Error(`Rust CSV parsing failed: ${errorMessage}`);
+    throw wrapRustError(error);
+  }
+}
+
+/**
+ * Convert a columnar batch into Gaia records. Exact 64-bit integer columns
+ * are formatted as strings to match the `source_id TEXT` database column,
+ * booleans are stored as 1/0, and arrays as JSON text with NaN as null.
+ */
+export function columnBatchToRecords(batch: RustColumnBatch): GaiaRecord[] {
+  const records = new Array<GaiaRecord>(batch.length);
+
+  for (let row = 0; row < batch.length; row++) {
+    const record: Record<string, string | number | null> = {};
+
+    for (const column of batch.columns) {
+      if (!isColumnValid(column, row)) {
+        record[column.name] = null;
+      } else if (column.type === "int64") {
+        record[column.name] = column.values[row].toString();
+      } else if (column.type === "bool") {
+        record[column.name] = column.values[row] ? 1 : 0;
+      } else if (column.type === "float32[]" || column.type === "float64[]") {
+        record[column.name] = JSON.stringify(Array.from(listElements(column, row)));
+      } else {
+        record[column.name] = column.values[row];
+      }
+    }
+
+    records[row] = record as GaiaRecord;
+  }
+
+  return records;
+}
+
+/**
+ * Prefix an error with context, keeping the Rust error code if present
+ */
+function wrapRustError(error: unknown): Error {
+  const errorMessage = error instanceof Error ? error.message : String(error);
+  const message = `Rust CSV parsing failed: ${errorMessage}`;
+
+  if (error instanceof RustParseError) {
+    return new RustParseError(error.code, message);
   }
+  return new Error(message);
 }
@@ -8,6 +8,7 @@ import {
 import type { CLIConfig } from "./config.ts";
 import { Logger, LogLevel } from "./types.ts";
 import { parse as parsePSV } from "@std/csv";
+import { RustErrorCode, RustParseError } from "./ffi/rust-errors.ts";
 
 /**
  * Stream a gzipped CSV from a ReadableStream or file path
@@ -168,6 +169,29 @@ export function filterByMagnitude(
   });
 }
 
+/**
+ * Whether an error means the file stops partway through, as left by an
+ * interrupted download
+ */
+export function isTruncatedError(error: unknown): boolean {
+  return error instanceof RustParseError &&
+    error.code === RustErrorCode.Truncated;
+}
+
+/**
+ * Whether an error means the downloaded file is corrupt or incomplete and
+ * must be re-fetched
+ */
+export function isCorruptGzipError(error: unknown): boolean {
+  if (error instanceof RustParseError) {
+    return error.code === RustErrorCode.CorruptGzip ||
+      error.code === RustErrorCode.Truncated;
+  }
+
+  const errorMessage = error instanceof Error ? error.message : String(error);
+  return errorMessage.includes("corrupt gzip stream");
+}
+
 /**
  * Process a single CSV file: stream, parse in chunks, filter, and insert
  */
@@ -177,7 +201,9 @@ export async function processCSVFile(
   db: GaiaDatabase,
   config: CLIConfig,
   trackingTable: string,
-): Promise<{ success: boolean; recordCount: number; error?: string }> {
+): Promise<
+  { success: boolean; recordCount: number; error?: string; refetch?: boolean }
+> {
   try {
     if (db.isFileProcessed(trackingTable, url)) {
       console.log(`Skipping already processed file: ${url}`);
@@ -186,13 +212,20 @@ export async function processCSVFile(
 
     let totalInserted = 0;
 
-    for await (
-      const chunk of streamGzippedCSV(
+    // Dynamically import Rust FFI only when needed
+    const chunks = config.useRustParser
+      ? (await import("./ffi/rust.ts")).streamGzippedCsvRust(
         filePath,
         config.storedColumns,
         config.csvChunkSize,
-      )
-    ) {
+      ) as AsyncGenerator<GaiaRecord[]>
+      : streamGzippedCSV(
+        filePath,
+        config.storedColumns,
+        config.csvChunkSize,
+      );
+
+    for await (const chunk of chunks) {
       // Filter by magnitude
       const filteredRecords = filterByMagnitude(
         chunk,
@@ -210,8 +243,10 @@ export async function processCSVFile(
   } catch (error) {
     const errorMessage = error instanceof Error ? error.message : String(error);
 
-    // If gzip is corrupt, delete the file so it can be re-downloaded
-    if (errorMessage.includes("corrupt gzip stream")) {
+    // If gzip is corrupt or truncated, delete the file so it is downloaded
+    // again from scratch rather than resumed
+    const refetch = isCorruptGzipError(error);
+    if (refetch) {
       try {
         await Deno.remove(filePath);
       } catch {
@@ -224,7 +259,10 @@ export async function processCSVFile(
     return {
       success: false,
       recordCount: 0,
-      error: errorMessage,
+      error: isTruncatedError(error)
+        ? `Incomplete download: ${errorMessage}`
+        : errorMessage,
+      refetch,
     };
   } finally {
     try {
@@ -401,12 +439,15 @@ export async function processTmassXmatchFile(
 /**
  * Process a 2MASS photometry file
  * Extracts J, H, K magnitudes and matches with crossmatch table
+ *
+ * @param potentialRecords - Optional pre-parsed records (from FFI parsers)
  */
 export async function processTmassFile(
   filePath: string,
   url: string,
   db: GaiaDatabase,
   trackingTable: string,
+  potentialRecords?: Omit<TmassRecord, "gaiadr3_source_id">[],
 ): Promise<{ success: boolean; recordCount: number; error?: string }> {
   let file: Deno.FsFile | null = null;
 
@@ -417,61 +458,59 @@ export async function processTmassFile(
       return { success: true, recordCount: 0 };
     }
 
-    // Stream pipe-delimited 2MASS file line-by-line
-    file = await Deno.open(filePath, { read: true });
+    // If records not provided, parse with TypeScript
+    if (!potentialRecords) {
+      // Stream pipe-delimited 2MASS file line-by-line
+      file = await Deno.open(filePath, { read: true });
 
-    const csvStream = file.readable
-      .pipeThrough(
-        new DecompressionStream("gzip") as unknown as ReadableWritablePair<
-          Uint8Array,
-          Uint8Array
-        >,
-      )
-      .pipeThrough(new TextDecoderStream())
-      .pipeThrough(
-        new CsvParseStream({
-          separator: "|", // Pipe-delimited
-          skipFirstRow: false, // No header row
-        }),
-      );
+      const csvStream = file.readable
+        .pipeThrough(
+          new DecompressionStream("gzip") as unknown as ReadableWritablePair<
+            Uint8Array,
+            Uint8Array
+          >,
+        )
+        .pipeThrough(new TextDecoderStream())
+        .pipeThrough(
+          new CsvParseStream({
+            separator: "|", // Pipe-delimited
+            skipFirstRow: false, // No header row
+          }),
+        );
 
-    // Parse pipe-delimited format and collect potential records
-    // Columns we need: 5=tmass_source_id, 6=j_m, 10=h_m, 14=k_m
-    const potentialRecords: Array<{
-      tmass_source_id: string;
-      j_m: number | null;
-      h_m: number | null;
-      k_m: number | null;
-    }> = [];
-
-    for await (const cols of csvStream) {
-      // cols is an array of column values
-      const colArray = Object.values(cols);
-
-      if (colArray.length < 15) continue;
-
-      const tmassSourceId = colArray[5]?.trim();
-      if (!tmassSourceId) continue;
-
-      const jMag = colArray[6]?.trim();
-      const hMag = colArray[10]?.trim();
-      const kMag = colArray[14]?.trim();
-
-      potentialRecords.push({
-        tmass_source_id: tmassSourceId,
-        j_m: jMag && jMag !== "null" ? parseFloat(jMag) : null,
-        h_m: hMag && hMag !== "null" ? parseFloat(hMag) : null,
-        k_m: kMag && kMag !== "null" ? parseFloat(kMag) : null,
-      });
-    }
+      // Parse pipe-delimited format and collect potential records
+      // Columns we need: 5=tmass_source_id, 6=j_m, 10=h_m, 14=k_m
+      potentialRecords = [];
 
-    // File stream is done, close it
-    try {
-      file.close();
-    } catch {
-      // Already closed by stream
+      for await (const cols of csvStream) {
+        // cols is an array of column values
+        const colArray = Object.values(cols);
+
+        if (colArray.length < 15) continue;
+
+        const tmassSourceId = colArray[5]?.trim();
+        if (!tmassSourceId) continue;
+
+        const jMag = colArray[6]?.trim();
+        const hMag = colArray[10]?.trim();
+        const kMag = colArray[14]?.trim();
+
+        potentialRecords.push({
+          tmass_source_id: tmassSourceId,
+          j_m: jMag && jMag !== "null" ? parseFloat(jMag) : null,
+          h_m: hMag && hMag !== "null" ? parseFloat(hMag) : null,
+          k_m: kMag && kMag !== "null" ? parseFloat(kMag) : null,
+        });
+      }
+
+      // File stream is done, close it
+      try {
+        file.close();
+      } catch {
+        // Already closed by stream
+      }
+      file = null;
     }
-    file = null;
 
     // Match with xmatch table using batched queries
     const batchSize = 1000;